@@ -17,28 +17,28 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
+// the per-directory `mod.rs` + same-named file layout and the vim-style mode names are deliberate
+#![allow(clippy::module_inception, clippy::upper_case_acronyms)]
+
 mod render;
 mod state;
 mod util;
 
-use util::term::DoubleBuffer;
-
-use crossterm::{
-    cursor::MoveTo,
-    execute,
-    terminal::{Clear, ClearType},
+use util::{
+    backend::{Backend, CrosstermBackend},
+    term::DoubleBuffer,
 };
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut stdout = std::io::stdout();
-    let buffer = DoubleBuffer::new();
+    let mut backend = CrosstermBackend::new(std::io::stdout());
+    let (width, height) = backend.size()?;
+    let buffer = DoubleBuffer::with_size(width, height);
 
     let mut state = crate::state::state::State::new(buffer);
     state.buffer.clear();
-    state.buffer.flush(&mut stdout);
-    execute!(stdout, Clear(ClearType::All))?;
-    execute!(stdout, MoveTo(0, 0))?;
+    state.buffer.flush(&mut backend)?;
+    backend.clear()?;
     println!("thanks for using rnbook.");
-    state.event_loop().unwrap();
+    state.event_loop(&mut backend).unwrap();
     Ok(())
 }
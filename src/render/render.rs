@@ -19,46 +19,54 @@
 
 use crate::{
     state::state::State,
-    util::mode::{ModeT, OpenMode},
+    util::{backend::Backend, mode::ModeT},
 };
 
-use std::io::Write;
-
 impl State {
-    pub fn render(&mut self, stdout: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn render(&mut self, backend: &mut impl Backend) -> Result<(), Box<dyn std::error::Error>> {
         if self.buffer.too_small_flag {
             // log_message("too_small_warning!");
             self.write_too_small_warning();
             self.defaults();
-            self.buffer.flush(stdout);
+            self.buffer.flush(backend)?;
             return Ok(());
         }
-        if self.mode == ModeT::OPEN(OpenMode::READ) {
-            // TODO self.write_stuff()
+        if matches!(self.mode, ModeT::OPEN(_)) {
+            self.write_active_buffer();
             self.defaults();
-            self.buffer.flush(stdout);
+            self.buffer.flush(backend)?;
             return Ok(());
-        } else if self.mode == ModeT::OPEN(OpenMode::EDIT) {
-            // write the window and the buffer
-            // i need to put a dummy function in for the moment
         } else if self.mode == ModeT::BROWSE {
             self.write_loaded_entries();
+            self.write_preview();
             self.defaults();
-            self.buffer.flush(stdout);
+            self.buffer.flush(backend)?;
             // TODO
             return Ok(());
+        } else if self.mode == ModeT::CALENDAR {
+            self.write_calendar();
+            self.defaults();
+            self.buffer.flush(backend)?;
+            return Ok(());
         } else {
             self.defaults()
         }
 
-        self.buffer.flush(stdout);
+        self.buffer.flush(backend)?;
         Ok(())
     }
     pub fn defaults(&mut self) {
-        self.write_rectangle(0, self.buffer.width - 1, 0, self.buffer.height - 1);
+        self.write_border();
+        // the scrollbar goes over the border but under everything that pops up
+        if self.mode == ModeT::BROWSE
+            && !self.buffer.too_small_flag
+            && !self.master_index.entries.is_empty()
+        {
+            self.write_scrollbar();
+        }
 
         if !self.buffer.too_small_flag {
-            self.write_command_bar(); // the command bar will soon be slaughtered
+            self.write_status_line();
         }
         if self.command_mode && !self.buffer.too_small_flag {
             self.write_command_window();
@@ -66,6 +74,202 @@ impl State {
         if self.dbg && !self.buffer.too_small_flag {
             self.write_debug_info()
         }
-        // draws the border rectangle
+        if self.prompt.is_some() && !self.buffer.too_small_flag {
+            self.write_prompt();
+        }
+        if self.help.is_some() && !self.buffer.too_small_flag {
+            self.write_help();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::*;
+    use crate::state::state::Loaded;
+    use crate::util::{
+        backend::TestBackend,
+        config::Config,
+        mode::OpenMode,
+        term::DoubleBuffer,
+        util::{Entry, EntryMeta},
+    };
+
+    /// a state drawing on a `width` x `height` screen, holding `entries` as (label, content).
+    /// nothing is loaded from or written to disk
+    fn state(width: usize, height: usize, entries: &[(&str, &str)]) -> (State, TestBackend) {
+        let config = Config {
+            entries_path: std::env::temp_dir().join("rnbook-render-tests"),
+            ..Config::default()
+        };
+        let buffer = DoubleBuffer::with_size(width, height);
+        let mut state = State::with_config(buffer, config, Loaded::default());
+        state.dbg = false;
+        state.message = None;
+        for (i, (label, content)) in entries.iter().enumerate() {
+            let id = i.to_string();
+            state.entries_map.insert(
+                id.clone(),
+                Entry {
+                    id: id.clone(),
+                    label: label.to_string(),
+                    date: "2026/01/02".to_string(),
+                    content: content.to_string(),
+                    tags: Vec::new(),
+                    is_dirty: false,
+                },
+            );
+            state.master_index.entries.push(EntryMeta {
+                id: id.clone(),
+                label: label.to_string(),
+                date: "2026/01/02".to_string(),
+                file: format!("entry_{}.json", id),
+                tags: Vec::new(),
+                pinned: false,
+            });
+        }
+        state.idx_active = !entries.is_empty();
+        state.no_entry_flag = entries.is_empty();
+        state.n_fits = state.screen().body.height as u32;
+        (state, TestBackend::new(width, height))
+    }
+
+    fn keys(state: &mut State, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            state.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+    }
+
+    /// draws a frame and returns the screen, row by row. the backend is kept from frame to
+    /// frame since only what changed is sent to it
+    fn snapshot(state: &mut State, backend: &mut TestBackend) -> Vec<String> {
+        state.render(backend).unwrap();
+        backend.rows()
+    }
+
+    const ENTRIES: &[(&str, &str)] = &[
+        ("groceries", "- eggs\n- [x] milk"),
+        ("notes", "# Notes\n\nsome *words* and [[groceries]]"),
+    ];
+
+    #[test]
+    fn browse() {
+        let (mut state, mut backend) = state(60, 8, ENTRIES);
+        assert_eq!(
+            snapshot(&mut state, &mut backend),
+            [
+                "┌──────────────────────────────────────────────────────────┐",
+                "│ BROWSE  rnbook-render-tests                          1/2 │",
+                "│ groceries                                     │2026/01/02│",
+                "│ notes                                         │2026/01/02│",
+                "│                                                          │",
+                "│                                                          │",
+                "│                                                          │",
+                "└───────────────────────────────────────────────── 1 of 2 ─┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn read() {
+        let (mut state, mut backend) = state(60, 8, ENTRIES);
+        snapshot(&mut state, &mut backend);
+        keys(&mut state, "j\n");
+        assert_eq!(
+            snapshot(&mut state, &mut backend),
+            [
+                "┌─ notes ─ 2026/01/02 ─────────────────────────────────────┐",
+                "│ READ  rnbook-render-tests  notes             ln 1, col 1 │",
+                "│ Notes                                                    │",
+                "│                                                          │",
+                "│ some words and [[groceries]]                             │",
+                "│                                                          │",
+                "│                                                          │",
+                "└──────────────────────────────────────────────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn edit() {
+        let (mut state, mut backend) = state(60, 8, ENTRIES);
+        keys(&mut state, "j\ni");
+        assert_eq!(state.mode, ModeT::OPEN(OpenMode::EDIT));
+        assert_eq!(
+            snapshot(&mut state, &mut backend),
+            [
+                "┌─ notes ─ 2026/01/02 ─────────────────────────────────────┐",
+                "│ EDIT  rnbook-render-tests  notes             ln 1, col 1 │",
+                "│ # Notes                                                  │",
+                "│                                                          │",
+                "│ some *words* and [[groceries]]                           │",
+                "│                                                          │",
+                "│                                                          │",
+                "└──────────────────────────────────────────────────────────┘",
+            ]
+        );
+        let cursor = backend.cell(2, 2);
+        assert_eq!(
+            (cursor.fg, cursor.bg),
+            (state.theme.cursor.fg, state.theme.cursor.bg)
+        );
+    }
+
+    #[test]
+    fn command_window() {
+        let (mut state, mut backend) = state(60, 8, ENTRIES);
+        keys(&mut state, ":new");
+        assert_eq!(
+            snapshot(&mut state, &mut backend),
+            [
+                "┌──────────────────────────────────────────────────────────┐",
+                "│ BROWSE  rnbook-render-tests                          1/2 │",
+                "│ groceries    ┌────────────────────────────┐   │2026/01/02│",
+                "│ notes        │new                         │   │2026/01/02│",
+                "│              └────────────────────────────┘              │",
+                "│                                                          │",
+                "│                                                          │",
+                "└───────────────────────────────────────────────── 1 of 2 ─┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn too_small() {
+        let (mut state, mut backend) = state(40, 6, ENTRIES);
+        assert_eq!(
+            snapshot(&mut state, &mut backend),
+            [
+                "┌──────────────────────────────────────┐",
+                "│                                      │",
+                "│      > 60x4 TERM SIZE REQUIRED       │",
+                "│                                      │",
+                "│                                      │",
+                "└──────────────────────────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn no_entries() {
+        let (mut state, mut backend) = state(60, 6, &[]);
+        assert_eq!(
+            snapshot(&mut state, &mut backend),
+            [
+                "┌──────────────────────────────────────────────────────────┐",
+                "│ BROWSE  rnbook-render-tests                              │",
+                "│                                                          │",
+                "│             < not an entry to be found :) >              │",
+                "│                                                          │",
+                "└──────────────────────────────────────────────────────────┘",
+            ]
+        );
     }
 }
@@ -0,0 +1,195 @@
+/*
+ * src/state/calendar/calendar.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::{
+    state::state::State,
+    util::{constant::DATE_FORMAT, mode::ModeT, template::Template},
+};
+
+/// everything the calendar screen needs to remember between frames
+#[derive(Debug, Clone)]
+pub struct Calendar {
+    pub selected: NaiveDate, // the highlighted day, the shown month is always the one it's in
+    pub day_idx: usize,      // selected entry in the list of the selected day's entries
+    pub day_focus: bool,     // true while the arrow keys move through that list instead of the grid
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Self {
+            selected: Local::now().date_naive(),
+            day_idx: 0,
+            day_focus: false,
+        }
+    }
+}
+
+impl Calendar {
+    /// the first day of the selected month
+    pub fn month_start(&self) -> NaiveDate {
+        self.selected.with_day(1).unwrap()
+    }
+
+    /// number of days in the selected month
+    pub fn days_in_month(&self) -> u32 {
+        let start = self.month_start();
+        let next = start + Months::new(1);
+        (next - start).num_days() as u32
+    }
+
+    fn shift_days(&mut self, days: i64) {
+        let shifted = if days < 0 {
+            self.selected
+                .checked_sub_days(Days::new(days.unsigned_abs()))
+        } else {
+            self.selected.checked_add_days(Days::new(days as u64))
+        };
+        if let Some(date) = shifted {
+            self.selected = date;
+        }
+    }
+
+    fn shift_months(&mut self, months: i32) {
+        let shifted = if months < 0 {
+            self.selected
+                .checked_sub_months(Months::new(months.unsigned_abs()))
+        } else {
+            self.selected.checked_add_months(Months::new(months as u32))
+        };
+        if let Some(date) = shifted {
+            self.selected = date;
+        }
+    }
+}
+
+/// parses the `date` field of an entry, which add_entry always writes with DATE_FORMAT
+pub fn parse_entry_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), DATE_FORMAT).ok()
+}
+
+impl State {
+    /// switch to the calendar, starting on the selected entry's day if there is one
+    pub fn open_calendar(&mut self) {
+        self.calendar.selected = self
+            .master_index
+            .entries
+            .get(self.idx)
+            .and_then(|meta| parse_entry_date(&meta.date))
+            .unwrap_or_else(|| Local::now().date_naive());
+        self.calendar.day_idx = 0;
+        self.calendar.day_focus = false;
+        self.last_mode = self.mode.clone();
+        self.mode = ModeT::CALENDAR;
+    }
+
+    /// positions in the master index of every entry written on `date`
+    pub fn entries_on(&self, date: NaiveDate) -> Vec<usize> {
+        self.master_index
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, meta)| parse_entry_date(&meta.date) == Some(date))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// open today's entry, creating it first if nothing has been written today. a new entry is
+    /// filled in from the configured daily template, if there is one
+    pub fn open_today(&mut self) -> std::io::Result<()> {
+        let today = Local::now().date_naive();
+        let id = match self.entries_on(today).first() {
+            Some(&pos) => self.master_index.entries[pos].id.clone(),
+            None => {
+                let date = today.format(DATE_FORMAT).to_string();
+                match self.config.daily_template.clone() {
+                    Some(name) => {
+                        let template = Template::load(&self.config.templates_path()?, &name)?;
+                        let label = if template.label.is_some() {
+                            template.render_label(None)
+                        } else {
+                            date
+                        };
+                        self.create_entry(&label, template.render_body(), template.tags.clone());
+                    }
+                    None => self.add_entry(&date),
+                }
+                self.master_index.entries.last().unwrap().id.clone()
+            }
+        };
+        self.open_entry(&id);
+        Ok(())
+    }
+
+    /// handles keys while the calendar is up. returns true if the key was used
+    pub fn handle_calendar_key(&mut self, key_event: KeyEvent) -> bool {
+        let on_day = self.entries_on(self.calendar.selected);
+
+        if self.calendar.day_focus {
+            match key_event.code {
+                KeyCode::Down => {
+                    self.calendar.day_idx = (self.calendar.day_idx + 1) % on_day.len().max(1)
+                }
+                KeyCode::Up => {
+                    self.calendar.day_idx = self
+                        .calendar
+                        .day_idx
+                        .checked_sub(1)
+                        .unwrap_or(on_day.len().saturating_sub(1))
+                }
+                KeyCode::Enter => {
+                    if let Some(&pos) = on_day.get(self.calendar.day_idx) {
+                        let id = self.master_index.entries[pos].id.clone();
+                        self.open_entry(&id);
+                    }
+                }
+                KeyCode::Esc => self.calendar.day_focus = false,
+                _ => return false,
+            }
+            return true;
+        }
+
+        match key_event.code {
+            KeyCode::Left => self.calendar.shift_days(-1),
+            KeyCode::Right => self.calendar.shift_days(1),
+            KeyCode::Up => self.calendar.shift_days(-7),
+            KeyCode::Down => self.calendar.shift_days(7),
+            KeyCode::PageUp => self.calendar.shift_months(-1),
+            KeyCode::PageDown => self.calendar.shift_months(1),
+            KeyCode::Char('t') => self.calendar.selected = Local::now().date_naive(),
+            KeyCode::Enter => match on_day.len() {
+                0 => {}
+                1 => {
+                    let id = self.master_index.entries[on_day[0]].id.clone();
+                    self.open_entry(&id);
+                }
+                _ => {
+                    self.calendar.day_idx = 0;
+                    self.calendar.day_focus = true;
+                }
+            },
+            KeyCode::Esc => self.mode = ModeT::BROWSE,
+            _ => return false,
+        }
+        self.calendar.day_idx = 0;
+        true
+    }
+}
@@ -18,19 +18,19 @@
  */
 
 use crate::{
-    state::{self, state::State},
+    state,
     util::{
-        config::{self},
-        util::{log_message, Entry, EntryMeta, MasterIndex},
+        config::MASTER_INDEX_FILE,
+        constant::DATE_FORMAT,
+        hooks::HookEvent,
+        mode::ModeT,
+        template::Template,
+        util::{log_message, write_atomic, Entry, EntryMeta, MasterIndex},
     },
 };
 
 use serde_json;
-use std::{
-    fs::{self, File},
-    path::Path,
-};
-use uuid::Uuid;
+use std::{fs, path::Path};
 
 impl state::state::State {
     /// Load the master index from disk (or create an empty one if not present)
@@ -40,6 +40,9 @@ impl state::state::State {
             let data = fs::read_to_string(&master_path)?;
             self.master_index =
                 serde_json::from_str(&data).unwrap_or_else(|_| MasterIndex::default());
+            // a hand-edited index may have pins scattered around, the sort is stable so the
+            // user's ordering survives within each group
+            self.master_index.entries.sort_by_key(|meta| !meta.pinned);
         } else {
             self.master_index = MasterIndex::default();
         }
@@ -49,9 +52,10 @@ impl state::state::State {
     /// Save the master index to disk.
     pub fn save_master_index(&self) -> std::io::Result<()> {
         let master_path = self.config.master_index_path();
-        let file = fs::File::create(&master_path)?;
-        serde_json::to_writer_pretty(file, &self.master_index)?;
-        Ok(())
+        write_atomic(
+            &master_path,
+            &serde_json::to_vec_pretty(&self.master_index)?,
+        )
     }
 
     /// Load all entries from disk into the in-memory mapping.
@@ -88,7 +92,7 @@ impl state::state::State {
         let mut buffer = Vec::new();
         for meta in &self.master_index.entries {
             if let Some(entry) = self.entries_map.get(&meta.id) {
-                buffer.push(entry.stringify(self.buffer.width));
+                buffer.push(entry.stringify(self.buffer.width, meta.pinned));
             } else {
                 buffer.push(String::from("<missing entry>"));
             }
@@ -98,47 +102,414 @@ impl state::state::State {
 
     /// add new entry
     pub fn add_entry(&mut self, label: &str) {
+        self.create_entry(label, String::new(), Vec::new());
+    }
+
+    /// add new entry with some starting content and tags, e.g. from a template
+    pub fn create_entry(&mut self, label: &str, content: String, tags: Vec<String>) {
         use uuid::Uuid;
+        self.stash_open_edits();
         let new_id = Uuid::new_v4().to_string();
-        let current_date = chrono::Local::now().format("%Y/%m/%d").to_string();
+        let current_date = chrono::Local::now().format(DATE_FORMAT).to_string();
         let new_entry = Entry {
             id: new_id.clone(),
             label: label.to_string(),
             date: current_date.clone(),
-            content: String::new(),
+            content,
+            tags: tags.clone(),
             is_dirty: true,
         };
         // generate a filename for the new entry
         let file = format!("entry_{}.json", Uuid::new_v4().simple());
+        let meta_id = new_id.clone();
         let meta = EntryMeta {
             id: new_id,
             label: label.to_string(),
             date: current_date,
             file,
+            tags,
+            pinned: false,
         };
         self.current_entry = Some(new_entry.clone());
         self.current_entry_meta = Some(meta.clone());
         self.buffer_editable = true;
         self.entries_map.insert(new_entry.id.clone(), new_entry);
         self.master_index.entries.push(meta);
+        self.idx_active = true;
+        self.no_entry_flag = false;
         self.save_current_entry().unwrap();
+        self.fire_hook(HookEvent::Create, &meta_id);
     }
 
-    /// save the currently edited entry to disk
+    /// add new entry filled in from the template called `name`. returns the new entry's id
+    pub fn add_entry_from_template(
+        &mut self,
+        name: &str,
+        label: Option<&str>,
+    ) -> std::io::Result<String> {
+        let template = Template::load(&self.config.templates_path()?, name)?;
+        self.create_entry(
+            &template.render_label(label),
+            template.render_body(),
+            template.tags.clone(),
+        );
+        Ok(self.master_index.entries.last().unwrap().id.clone())
+    }
+
+    /// save the currently edited entry to disk, and bring the loaded entry and its metadata in
+    /// the master index up to date with it
     pub fn save_current_entry(&mut self) -> std::io::Result<()> {
-        if let (Some(current), Some(meta)) = (&mut self.current_entry, &mut self.current_entry_meta)
-        {
-            let file_path = self.config.entries_path.join(&meta.file);
-            let file = fs::File::create(&file_path)?;
-            serde_json::to_writer_pretty(file, current)?;
+        if let Some(current) = &mut self.current_entry {
             current.is_dirty = false;
-            meta.label = current.label.clone();
-            meta.date = current.date.clone();
+            self.entries_map.insert(current.id.clone(), current.clone());
+            if let Some(meta) = self
+                .master_index
+                .entries
+                .iter_mut()
+                .find(|meta| meta.id == current.id)
+            {
+                meta.label = current.label.clone();
+                meta.date = current.date.clone();
+                meta.tags = current.tags.clone();
+                self.current_entry_meta = Some(meta.clone());
+            }
+            let id = current.id.clone();
+            self.write_entry(&id)?;
             self.save_master_index()?;
         }
         Ok(())
     }
 
+    /// write a loaded entry to its file
+    pub fn write_entry(&self, entry_id: &str) -> std::io::Result<()> {
+        let meta = self
+            .master_index
+            .entries
+            .iter()
+            .find(|meta| meta.id == entry_id);
+        if let (Some(meta), Some(entry)) = (meta, self.entries_map.get(entry_id)) {
+            let file_path = self.config.entries_path.join(&meta.file);
+            write_atomic(&file_path, &serde_json::to_vec_pretty(entry)?)?;
+        }
+        Ok(())
+    }
+
+    /// change an entry's label, on disk and in memory. if either the entry or the index can't be
+    /// written the old label is put back, so the two never disagree
+    pub fn rename_entry(&mut self, entry_id: &str, new_label: &str) -> std::io::Result<()> {
+        let Some(old_label) = self
+            .master_index
+            .entries
+            .iter()
+            .find(|meta| meta.id == entry_id)
+            .map(|meta| meta.label.clone())
+        else {
+            return Ok(());
+        };
+        self.set_label(entry_id, new_label);
+        let written = self
+            .write_entry(entry_id)
+            .and_then(|_| self.save_master_index());
+        match written {
+            Ok(()) => self.fire_hook(HookEvent::Rename, entry_id),
+            Err(_) => {
+                self.set_label(entry_id, &old_label);
+                let _ = self.write_entry(entry_id);
+            }
+        }
+        written
+    }
+
+    /// the in-memory half of a rename
+    fn set_label(&mut self, entry_id: &str, label: &str) {
+        if let Some(entry) = self.entries_map.get_mut(entry_id) {
+            entry.label = label.to_string();
+        }
+        if let Some(meta) = self
+            .master_index
+            .entries
+            .iter_mut()
+            .find(|meta| meta.id == entry_id)
+        {
+            meta.label = label.to_string();
+        }
+        if let Some(current) = &mut self.current_entry {
+            if current.id == entry_id {
+                current.label = label.to_string();
+            }
+        }
+        if let Some(meta) = &mut self.current_entry_meta {
+            if meta.id == entry_id {
+                meta.label = label.to_string();
+            }
+        }
+    }
+
+    /// copy an entry under a new id and file, right below the original. returns the copy's id
+    pub fn duplicate_entry(&mut self, entry_id: &str) -> std::io::Result<String> {
+        use uuid::Uuid;
+        self.stash_open_edits();
+        let pos = self.entry_position(entry_id)?;
+        let Some(original) = self.entries_map.get(entry_id).cloned() else {
+            return Err(not_found(entry_id));
+        };
+        let new_id = Uuid::new_v4().to_string();
+        let label = format!("{} (copy)", original.label);
+        let copy = Entry {
+            id: new_id.clone(),
+            label: label.clone(),
+            is_dirty: false,
+            ..original
+        };
+        let meta = EntryMeta {
+            id: new_id.clone(),
+            label,
+            file: format!("entry_{}.json", Uuid::new_v4().simple()),
+            pinned: false,
+            ..self.master_index.entries[pos].clone()
+        };
+        // a copy of a pinned entry goes below the pins
+        let at = if self.master_index.entries[pos].pinned {
+            self.pinned_count()
+        } else {
+            pos + 1
+        };
+        self.entries_map.insert(new_id.clone(), copy);
+        self.master_index.entries.insert(at, meta);
+        self.write_entry(&new_id)?;
+        self.save_master_index()?;
+        self.fire_hook(HookEvent::Create, &new_id);
+        Ok(new_id)
+    }
+
+    /// combine entries into one, oldest first, in place of the first of them in the list. the
+    /// originals go to the trash. returns the merged entry's id
+    pub fn merge_entries(&mut self, entry_ids: &[String]) -> std::io::Result<String> {
+        use uuid::Uuid;
+        self.stash_open_edits();
+        let mut parts: Vec<(usize, Entry)> = Vec::new();
+        for id in entry_ids {
+            let pos = self.entry_position(id)?;
+            let entry = self
+                .entries_map
+                .get(id)
+                .cloned()
+                .ok_or_else(|| not_found(id))?;
+            if !parts.iter().any(|(_, part)| part.id == entry.id) {
+                parts.push((pos, entry));
+            }
+        }
+        let Some(at) = parts.iter().map(|(pos, _)| *pos).min() else {
+            return Err(not_found(""));
+        };
+        // dates are zero padded, so they sort as text. the sort is stable, which keeps list
+        // order for entries from the same day
+        parts.sort_by(|(_, a), (_, b)| a.date.cmp(&b.date));
+        let first = parts[0].1.clone();
+
+        let content = parts
+            .iter()
+            .map(|(_, part)| format!("{} ({})\n{}", part.label, part.date, part.content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let mut tags: Vec<String> = Vec::new();
+        for tag in parts.iter().flat_map(|(_, part)| part.tags.iter()) {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        let new_id = Uuid::new_v4().to_string();
+        let merged = Entry {
+            id: new_id.clone(),
+            content,
+            tags: tags.clone(),
+            is_dirty: false,
+            ..first.clone()
+        };
+        let meta = EntryMeta {
+            id: new_id.clone(),
+            label: first.label,
+            date: first.date,
+            file: format!("entry_{}.json", Uuid::new_v4().simple()),
+            tags,
+            pinned: self.master_index.entries[at].pinned,
+        };
+        self.entries_map.insert(new_id.clone(), merged);
+        self.master_index.entries.insert(at, meta);
+        self.write_entry(&new_id)?;
+        for (_, part) in &parts {
+            self.trash_entry(&part.id)?;
+        }
+        self.save_master_index()?;
+        self.fire_hook(HookEvent::Create, &new_id);
+        if let Some(pos) = self
+            .master_index
+            .entries
+            .iter()
+            .position(|meta| meta.id == new_id)
+        {
+            self.idx = pos;
+        }
+        Ok(new_id)
+    }
+
+    /// add and remove tags on entries
+    pub fn tag_entries(
+        &mut self,
+        entry_ids: &[String],
+        add: &[String],
+        remove: &[String],
+    ) -> std::io::Result<()> {
+        let retag = |tags: &mut Vec<String>| {
+            tags.retain(|tag| !remove.contains(tag));
+            for tag in add {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        };
+        for id in entry_ids {
+            if let Some(entry) = self.entries_map.get_mut(id) {
+                retag(&mut entry.tags);
+            }
+            if let Some(meta) = self
+                .master_index
+                .entries
+                .iter_mut()
+                .find(|meta| &meta.id == id)
+            {
+                retag(&mut meta.tags);
+            }
+            if let Some(current) = self.current_entry.as_mut().filter(|entry| &entry.id == id) {
+                retag(&mut current.tags);
+            }
+            self.write_entry(id)?;
+        }
+        self.save_master_index()
+    }
+
+    /// write entries into `dir` as markdown files named after their labels. returns how many
+    /// files were written
+    pub fn export_entries(&self, entry_ids: &[String], dir: &Path) -> std::io::Result<usize> {
+        fs::create_dir_all(dir)?;
+        let mut written = 0;
+        for id in entry_ids {
+            let Some(entry) = self.entries_map.get(id) else {
+                continue;
+            };
+            let stem: String = entry
+                .label
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || " -_".contains(c) {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let stem = match stem.trim() {
+                "" => id.chars().take(8).collect(),
+                stem => stem.to_string(),
+            };
+            let mut path = dir.join(format!("{}.md", stem));
+            let mut n = 2;
+            while path.exists() {
+                path = dir.join(format!("{}-{}.md", stem, n));
+                n += 1;
+            }
+            let mut text = format!("# {}\n\n{}\n", entry.label, entry.date);
+            if !entry.tags.is_empty() {
+                text.push_str(&format!("tags: {}\n", entry.tags.join(", ")));
+            }
+            text.push_str(&format!("\n{}\n", entry.content));
+            fs::write(&path, text)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// move entries into the notebook whose entries directory is `dir`, files and all
+    pub fn move_entries(&mut self, entry_ids: &[String], dir: &Path) -> std::io::Result<()> {
+        let same = match (dir.canonicalize(), self.config.entries_path.canonicalize()) {
+            (Ok(dir), Ok(entries_path)) => dir == entries_path,
+            _ => dir == self.config.entries_path,
+        };
+        if same {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "entries are already in that notebook",
+            ));
+        }
+        self.stash_open_edits();
+        fs::create_dir_all(dir)?;
+        let index_path = dir.join(MASTER_INDEX_FILE);
+        let mut index: MasterIndex = match fs::read_to_string(&index_path) {
+            Ok(data) => serde_json::from_str(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MasterIndex::default(),
+            Err(e) => return Err(e),
+        };
+        // the files go first, then both indexes are written once. if moving a file fails the
+        // ones already moved are still recorded in both before giving up
+        let mut moved = Vec::new();
+        let mut result = Ok(());
+        for id in entry_ids {
+            let meta = self.master_index.entries[self.entry_position(id)?].clone();
+            let from = self.config.entries_path.join(&meta.file);
+            let to = dir.join(&meta.file);
+            if from.exists() && fs::rename(&from, &to).is_err() {
+                // a different file system, copy it over instead
+                if let Err(e) = fs::copy(&from, &to).and_then(|_| fs::remove_file(&from)) {
+                    result = Err(e);
+                    break;
+                }
+            }
+            moved.push(meta);
+        }
+        if moved.is_empty() {
+            return result;
+        }
+        index.entries.extend(moved.iter().map(|meta| EntryMeta {
+            pinned: false,
+            ..meta.clone()
+        }));
+        write_atomic(&index_path, &serde_json::to_vec_pretty(&index)?)?;
+        for meta in &moved {
+            self.forget_entry(self.entry_position(&meta.id)?);
+        }
+        self.save_master_index()?;
+        result
+    }
+
+    /// where an entry is in the master index
+    fn entry_position(&self, entry_id: &str) -> std::io::Result<usize> {
+        self.master_index
+            .entries
+            .iter()
+            .position(|meta| meta.id == entry_id)
+            .ok_or_else(|| not_found(entry_id))
+    }
+
+    /// move an entry's file into the trash directory and forget about it
+    pub fn trash_entry(&mut self, entry_id: &str) -> std::io::Result<()> {
+        let pos = self.entry_position(entry_id)?;
+        let hook = self.hook_payload(HookEvent::Delete, entry_id);
+        let trash_path = self.config.trash_path();
+        fs::create_dir_all(&trash_path)?;
+        let file = &self.master_index.entries[pos].file;
+        let file_path = self.config.entries_path.join(file);
+        if file_path.exists() {
+            fs::rename(&file_path, trash_path.join(file))?;
+        }
+        self.forget_entry(pos);
+        self.save_master_index()?;
+        if let Some(payload) = hook {
+            self.queue_hook(HookEvent::Delete, payload);
+        }
+        Ok(())
+    }
+
     /// delete an entry immediately from disk and memory
     /// `entry_id` is the identifier of the entry to delete
     pub fn delete_entry(&mut self, entry_id: &str) -> std::io::Result<()> {
@@ -148,35 +519,98 @@ impl state::state::State {
             .iter()
             .position(|e| e.id == entry_id)
         {
+            let hook = self.hook_payload(HookEvent::Delete, entry_id);
             let meta = &self.master_index.entries[pos];
             let file_path = self.config.entries_path.join(&meta.file);
             if file_path.exists() {
                 fs::remove_file(&file_path)?;
             }
-            self.master_index.entries.remove(pos);
+            self.forget_entry(pos);
             self.save_master_index()?;
-            self.entries_map.remove(entry_id);
-            if let Some(current) = &self.current_entry {
-                if current.id == entry_id {
-                    self.current_entry = None;
-                    self.current_entry_meta = None;
-                }
+            if let Some(payload) = hook {
+                self.queue_hook(HookEvent::Delete, payload);
             }
         }
         Ok(())
     }
 
-    /// write all entries (as given in the master index) to disk
-    /// useful for bulk :w command or on exit
-    pub fn write_loaded_entries_to_disk(&self) -> std::io::Result<()> {
-        for meta in &self.master_index.entries {
-            if let Some(entry) = self.entries_map.get(&meta.id) {
-                let file_path = self.config.entries_path.join(&meta.file);
-                let file = fs::File::create(&file_path)?;
-                serde_json::to_writer_pretty(file, entry)?;
+    /// drop the entry at `pos` in the master index from memory, closing it if it's open. the
+    /// index on disk is left for the caller to save
+    fn forget_entry(&mut self, pos: usize) {
+        let meta = self.master_index.entries.remove(pos);
+        self.entries_map.remove(&meta.id);
+        self.marked.remove(&meta.id);
+        self.mark_anchor = None;
+        if let Some(current) = &self.current_entry {
+            if current.id == meta.id {
+                self.current_entry = None;
+                self.current_entry_meta = None;
+                if matches!(self.mode, ModeT::OPEN(_)) {
+                    self.editor.dirty = false;
+                    self.close_entry();
+                }
             }
         }
-        self.save_master_index()?;
-        Ok(())
+        let len = self.master_index.entries.len();
+        self.idx_active = len > 0;
+        if self.idx >= len {
+            self.idx = len.saturating_sub(1);
+        }
     }
+
+    /// number of pinned entries, which always make up the head of the master index
+    pub fn pinned_count(&self) -> usize {
+        self.master_index
+            .entries
+            .iter()
+            .take_while(|meta| meta.pinned)
+            .count()
+    }
+
+    /// swap the selected entry with the one above it. entries never cross the pinned boundary
+    pub fn move_selected_entry_up(&mut self) -> std::io::Result<()> {
+        if !self.idx_active || self.idx == 0 || self.idx >= self.master_index.entries.len() {
+            return Ok(());
+        }
+        if self.idx == self.pinned_count() {
+            return Ok(()); // first unpinned entry, can't jump above the pins
+        }
+        self.master_index.entries.swap(self.idx, self.idx - 1);
+        self.idx -= 1;
+        self.save_master_index()
+    }
+
+    /// swap the selected entry with the one below it. entries never cross the pinned boundary
+    pub fn move_selected_entry_down(&mut self) -> std::io::Result<()> {
+        if !self.idx_active || self.idx + 1 >= self.master_index.entries.len() {
+            return Ok(());
+        }
+        if self.idx + 1 == self.pinned_count() {
+            return Ok(()); // last pinned entry, can't sink below the pins
+        }
+        self.master_index.entries.swap(self.idx, self.idx + 1);
+        self.idx += 1;
+        self.save_master_index()
+    }
+
+    /// pin or unpin the selected entry. a newly pinned entry goes to the bottom of the pinned
+    /// group and an unpinned one to the top of the rest, so it moves as little as possible
+    pub fn toggle_pin_selected(&mut self) -> std::io::Result<()> {
+        if !self.idx_active || self.idx >= self.master_index.entries.len() {
+            return Ok(());
+        }
+        let mut meta = self.master_index.entries.remove(self.idx);
+        meta.pinned = !meta.pinned;
+        let new_idx = self.pinned_count();
+        self.master_index.entries.insert(new_idx, meta);
+        self.idx = new_idx;
+        self.save_master_index()
+    }
+}
+
+fn not_found(entry_id: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("no entry with id {}", entry_id),
+    )
 }
@@ -0,0 +1,382 @@
+/*
+ * src/state/editor/editor.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::util::{text::char_width, util::wrap_offsets};
+
+/// the text of the open entry plus a cursor. READ mode moves the cursor around, EDIT mode also
+/// changes the text. `row` is a line index and `col` a char index into that line
+#[derive(Debug, Clone, Default)]
+pub struct Editor {
+    pub lines: Vec<String>,
+    pub row: usize,
+    pub col: usize,
+    pub scroll: usize, // first display row (after wrapping) drawn on screen
+    pub dirty: bool,   // true if the text changed since it was loaded or saved
+    pub anchor: Option<(usize, usize)>, // other end of the selection, the cursor being one end
+}
+
+/// one wrapped piece of a line as it is drawn: chars `start..end` of `lines[row]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayRow {
+    pub row: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Editor {
+    pub fn new(text: &str) -> Self {
+        Self {
+            lines: text.split('\n').map(String::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.is_empty())
+    }
+
+    pub fn line_len(&self, row: usize) -> usize {
+        self.lines.get(row).map_or(0, |line| line.chars().count())
+    }
+
+    /// byte offset of char `col` in line `row`
+    pub fn byte_index(&self, row: usize, col: usize) -> usize {
+        let line = &self.lines[row];
+        line.char_indices().nth(col).map_or(line.len(), |(i, _)| i)
+    }
+
+    /// every line wrapped to `width`, in drawing order
+    pub fn display_rows(&self, width: usize) -> Vec<DisplayRow> {
+        let mut rows = Vec::new();
+        for (row, line) in self.lines.iter().enumerate() {
+            for (start, end) in wrap_offsets(line, width) {
+                rows.push(DisplayRow { row, start, end });
+            }
+        }
+        rows
+    }
+
+    /// (display row, x) of the cursor once the text is wrapped to `width`, x in cells
+    pub fn cursor_position(&self, width: usize) -> (usize, usize) {
+        let rows = self.display_rows(width);
+        let mut found = (0, 0);
+        for (i, display_row) in rows.iter().enumerate() {
+            if display_row.row == self.row && display_row.start <= self.col {
+                let x = self.lines[self.row]
+                    .chars()
+                    .take(self.col)
+                    .skip(display_row.start)
+                    .map(char_width)
+                    .sum();
+                found = (i, x);
+            }
+        }
+        found
+    }
+
+    /// scroll just enough to keep the cursor within `height` rows
+    pub fn follow_cursor(&mut self, width: usize, height: usize) {
+        let (cursor_row, _) = self.cursor_position(width);
+        self.scroll_to(cursor_row, height);
+    }
+
+    /// scroll just enough to keep display row `row` within `height` rows
+    pub fn scroll_to(&mut self, row: usize, height: usize) {
+        let height = height.max(1);
+        if row < self.scroll {
+            self.scroll = row;
+        } else if row >= self.scroll + height {
+            self.scroll = row + 1 - height;
+        }
+    }
+
+    /// byte offset of the cursor in `text()`
+    pub fn offset(&self) -> usize {
+        let before: usize = self.lines[..self.row]
+            .iter()
+            .map(|line| line.len() + 1)
+            .sum();
+        before + self.byte_index(self.row, self.col)
+    }
+
+    pub fn move_left(&mut self) {
+        self.anchor = None;
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        self.anchor = None;
+        if self.col < self.line_len(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn move_up(&mut self, n: usize) {
+        self.anchor = None;
+        self.row = self.row.saturating_sub(n);
+        self.col = self.col.min(self.line_len(self.row));
+    }
+
+    pub fn move_down(&mut self, n: usize) {
+        self.anchor = None;
+        self.row = (self.row + n).min(self.lines.len().saturating_sub(1));
+        self.col = self.col.min(self.line_len(self.row));
+    }
+
+    pub fn move_line_start(&mut self) {
+        self.anchor = None;
+        self.col = 0;
+    }
+
+    pub fn move_line_end(&mut self) {
+        self.anchor = None;
+        self.col = self.line_len(self.row);
+    }
+
+    pub fn move_top(&mut self) {
+        self.anchor = None;
+        self.row = 0;
+        self.col = 0;
+    }
+
+    pub fn move_bottom(&mut self) {
+        self.anchor = None;
+        self.row = self.lines.len().saturating_sub(1);
+        self.col = 0;
+    }
+
+    /// put the cursor on byte `offset` of `text()`, rounded down to a char
+    pub fn set_offset(&mut self, offset: usize) {
+        let mut start = 0;
+        for (row, line) in self.lines.iter().enumerate() {
+            if offset <= start + line.len() || row + 1 == self.lines.len() {
+                let mut i = offset.saturating_sub(start).min(line.len());
+                while !line.is_char_boundary(i) {
+                    i -= 1;
+                }
+                self.row = row;
+                self.col = line[..i].chars().count();
+                return;
+            }
+            start += line.len() + 1;
+        }
+    }
+
+    /// the selected (row, col) range in text order, if anything is selected
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.anchor?;
+        let cursor = (self.row, self.col);
+        match anchor.cmp(&cursor) {
+            std::cmp::Ordering::Less => Some((anchor, cursor)),
+            std::cmp::Ordering::Greater => Some((cursor, anchor)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    /// the selection as byte offsets into `text()`
+    pub fn selected_offsets(&self) -> Option<std::ops::Range<usize>> {
+        let ((r0, c0), (r1, c1)) = self.selection()?;
+        let offset = |row: usize, col: usize| -> usize {
+            let before: usize = self.lines[..row].iter().map(|line| line.len() + 1).sum();
+            before + self.byte_index(row, col)
+        };
+        Some(offset(r0, c0)..offset(r1, c1))
+    }
+
+    /// the selected chars of line `row`, one past its end if the line break is selected too
+    pub fn selected_cols(&self, row: usize) -> Option<std::ops::Range<usize>> {
+        let ((r0, c0), (r1, c1)) = self.selection()?;
+        if row < r0 || row > r1 {
+            return None;
+        }
+        let start = if row == r0 { c0 } else { 0 };
+        let end = if row == r1 {
+            c1
+        } else {
+            self.line_len(row) + 1
+        };
+        Some(start..end)
+    }
+
+    /// remove the selected text, leaving the cursor where it started. false if nothing was selected
+    pub fn delete_selection(&mut self) -> bool {
+        let Some(((r0, c0), (r1, c1))) = self.selection() else {
+            self.anchor = None;
+            return false;
+        };
+        self.anchor = None;
+        let end = self.byte_index(r1, c1);
+        let rest = self.lines[r1][end..].to_string();
+        let start = self.byte_index(r0, c0);
+        self.lines[r0].truncate(start);
+        self.lines[r0].push_str(&rest);
+        self.lines.drain(r0 + 1..=r1);
+        self.row = r0;
+        self.col = c0;
+        self.dirty = true;
+        true
+    }
+
+    /// put the cursor on a position, clamped to the text
+    pub fn set_cursor(&mut self, row: usize, col: usize) {
+        self.row = row.min(self.lines.len().saturating_sub(1));
+        self.col = col.min(self.line_len(self.row));
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.delete_selection();
+        let i = self.byte_index(self.row, self.col);
+        self.lines[self.row].insert(i, c);
+        self.col += 1;
+        self.dirty = true;
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        for c in s.chars() {
+            if c == '\n' {
+                self.insert_newline();
+            } else {
+                self.insert_char(c);
+            }
+        }
+    }
+
+    pub fn insert_newline(&mut self) {
+        self.delete_selection();
+        let i = self.byte_index(self.row, self.col);
+        let rest = self.lines[self.row].split_off(i);
+        self.lines.insert(self.row + 1, rest);
+        self.row += 1;
+        self.col = 0;
+        self.dirty = true;
+    }
+
+    /// delete the char before the cursor, joining lines at the start of one
+    pub fn backspace(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        if self.col > 0 {
+            self.col -= 1;
+            let i = self.byte_index(self.row, self.col);
+            self.lines[self.row].remove(i);
+            self.dirty = true;
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+            self.lines[self.row].push_str(&line);
+            self.dirty = true;
+        }
+    }
+
+    /// delete the char under the cursor, joining lines at the end of one
+    pub fn delete(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        if self.col < self.line_len(self.row) {
+            let i = self.byte_index(self.row, self.col);
+            self.lines[self.row].remove(i);
+            self.dirty = true;
+        } else if self.row + 1 < self.lines.len() {
+            let line = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&line);
+            self.dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits() {
+        let mut editor = Editor::new("ab\ncd");
+        editor.move_right();
+        editor.insert_char('x');
+        assert_eq!(editor.text(), "axb\ncd");
+        editor.insert_newline();
+        assert_eq!(
+            (editor.text().as_str(), editor.row, editor.col),
+            ("ax\nb\ncd", 1, 0)
+        );
+
+        // at the start of a line backspace joins it to the one above, at the end delete
+        // joins the one below
+        editor.backspace();
+        assert_eq!(
+            (editor.text().as_str(), editor.row, editor.col),
+            ("axb\ncd", 0, 2)
+        );
+        editor.move_line_end();
+        editor.delete();
+        assert_eq!(editor.text(), "axbcd");
+        assert!(editor.dirty);
+
+        // nothing before the start or after the end
+        editor.move_top();
+        editor.backspace();
+        editor.move_bottom();
+        editor.move_line_end();
+        editor.delete();
+        assert_eq!(editor.text(), "axbcd");
+    }
+
+    #[test]
+    fn moves() {
+        let mut editor = Editor::new("long line\nab\n");
+        editor.move_line_end();
+        editor.move_down(1);
+        assert_eq!((editor.row, editor.col), (1, 2));
+        editor.move_down(5);
+        assert_eq!((editor.row, editor.col), (2, 0));
+        editor.move_left();
+        assert_eq!((editor.row, editor.col), (1, 2));
+        editor.move_right();
+        assert_eq!((editor.row, editor.col), (2, 0));
+    }
+
+    #[test]
+    fn positions() {
+        let mut editor = Editor::new("hello world\n日本");
+        editor.set_cursor(0, 8);
+        assert_eq!(editor.cursor_position(8), (1, 2));
+        editor.set_cursor(1, 1);
+        assert_eq!(editor.cursor_position(8), (2, 2));
+        assert_eq!(editor.offset(), "hello world\n日".len());
+
+        editor.scroll = 0;
+        editor.follow_cursor(8, 2);
+        assert_eq!(editor.scroll, 1);
+    }
+}
@@ -0,0 +1,288 @@
+/*
+ * src/state/event/action.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// turning key presses into actions through the keymap, and what each action does in each mode
+use crossterm::event::KeyEvent;
+
+use crate::{
+    state::state::State,
+    util::{
+        command::EntryId,
+        keymap::{Action, KeyChord, KeyMode, Lookup},
+        mode::{ModeT, OpenMode},
+        prompt::{Prompt, PromptAction},
+    },
+};
+
+impl State {
+    /// the keymap mode for where we are, ignoring the command bar
+    pub fn key_mode(&self) -> KeyMode {
+        match self.mode {
+            ModeT::OPEN(OpenMode::READ) => KeyMode::Read,
+            ModeT::OPEN(OpenMode::EDIT) => KeyMode::Edit,
+            _ => KeyMode::Browse,
+        }
+    }
+
+    /// add a key press to the pending sequence and run whatever it completes. a sequence that
+    /// leads nowhere is taken apart: its first key runs on its own and the rest are tried again.
+    /// returns true if it's time to quit
+    pub fn feed_key(&mut self, mode: KeyMode, key_event: KeyEvent) -> bool {
+        self.pending_keys.push(KeyChord::from_event(&key_event));
+        while !self.pending_keys.is_empty() {
+            match self.keymap.lookup(mode, &self.pending_keys) {
+                Lookup::Action(action) => {
+                    self.last_sequence_len = self.pending_keys.len();
+                    self.pending_keys.clear();
+                    return self.run_action(mode, action);
+                }
+                Lookup::Prefix => return false,
+                Lookup::None => {
+                    let first = self.pending_keys.remove(0);
+                    match self.keymap.lookup(mode, &[first]) {
+                        Lookup::Action(action) => {
+                            self.last_sequence_len = 1;
+                            if self.run_action(mode, action) {
+                                return true;
+                            }
+                        }
+                        _ => self.unbound_key(mode, first),
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// a key nothing is bound to. in the editor and the command bar chars are typed in
+    fn unbound_key(&mut self, mode: KeyMode, key: KeyChord) {
+        let Some(c) = key.typed_char() else {
+            return;
+        };
+        match mode {
+            KeyMode::Edit => self.editor.insert_char(c),
+            KeyMode::Command => {
+                self.command_error = None;
+                self.completion = None;
+                self.command_bar.push_char(c);
+            }
+            _ => {}
+        }
+    }
+
+    /// open the command bar with `text` already typed in
+    pub fn open_command_bar(&mut self, text: &str) {
+        self.command_bar.swap();
+        if !text.is_empty() {
+            self.command_bar.set(text);
+        }
+        self.command_mode = true;
+    }
+
+    /// close the command bar, keeping what was typed for next time
+    pub fn close_command_bar(&mut self) {
+        self.command_bar.swap();
+        self.command_mode = false;
+        self.command_error = None;
+        self.completion = None;
+        self.history.pos = None;
+    }
+
+    /// run an action bound in `mode`. returns true if it's time to quit
+    pub fn run_action(&mut self, mode: KeyMode, action: Action) -> bool {
+        match action {
+            Action::Quit => return true,
+            Action::RecordMacro if self.recording.is_some() => {
+                self.stop_recording();
+                return false;
+            }
+            Action::RecordMacro | Action::PlayMacro => {
+                self.awaiting_register = Some(action);
+                return false;
+            }
+            _ => {}
+        }
+        match mode {
+            KeyMode::Browse => return self.run_browse_action(action),
+            KeyMode::Read => self.run_read_action(action),
+            KeyMode::Edit => self.run_edit_action(action),
+            KeyMode::Command => self.run_command_action(action),
+        }
+        false
+    }
+
+    fn run_browse_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::Down => self.select_next(),
+            Action::Up => self.select_prev(),
+            Action::PageDown => self.select_page_down(),
+            Action::PageUp => self.select_page_up(),
+            Action::First => self.select_first(),
+            Action::Last => self.select_last(),
+            Action::Command => self.open_command_bar(""),
+            Action::Filter => self.open_command_bar("filter "),
+            Action::Open => self.open_selected_entry(),
+            // marks and the filter are cleared before Esc means quit
+            Action::Close if !self.clear_marks() && self.filter.take().is_none() => return true,
+            Action::MoveUp | Action::MoveDown | Action::TogglePin => {
+                let result = match action {
+                    Action::MoveUp => self.move_selected_entry_up(),
+                    Action::MoveDown => self.move_selected_entry_down(),
+                    _ => self.toggle_pin_selected(),
+                };
+                if let Err(e) = result {
+                    self.error(&format!("failed to reorder entries: {}", e));
+                }
+            }
+            Action::Calendar => self.open_calendar(),
+            Action::TogglePreview => self.preview_open = !self.preview_open,
+            Action::WidenList => self.set_split_ratio(self.split_ratio + 5),
+            Action::NarrowList => self.set_split_ratio(self.split_ratio.saturating_sub(5)),
+            Action::ToggleMark => self.toggle_mark(),
+            Action::MarkRange => self.mark_range(),
+            Action::MarkMatches => {
+                let n = self.mark_filter_matches();
+                self.info(&format!("marked {} entries", n));
+            }
+            Action::NextMatch => self.select_filter_match(true),
+            Action::PrevMatch => self.select_filter_match(false),
+            Action::Delete => self.ask_delete(),
+            _ => {}
+        }
+        false
+    }
+
+    /// ask before deleting the marked entries, or the selected one
+    fn ask_delete(&mut self) {
+        match self.target_ids(None) {
+            Ok(ids) => self.confirm_delete(ids),
+            Err(e) => self.warn(&e),
+        }
+    }
+
+    /// ask before deleting the entries `ids`, naming the entry when there's only one so a
+    /// reference that matched the wrong one can still be turned down
+    pub fn confirm_delete(&mut self, ids: Vec<EntryId>) {
+        let question = match ids.as_slice() {
+            [id] => {
+                let label = self
+                    .master_index
+                    .entries
+                    .iter()
+                    .find(|meta| &meta.id == id)
+                    .map_or(String::new(), |meta| meta.label.clone());
+                format!("delete '{}'?", label)
+            }
+            ids => format!("delete {} entries?", ids.len()),
+        };
+        self.prompt = Some(Prompt::new(&question, PromptAction::DeleteEntries(ids)));
+    }
+
+    fn save_from_key(&mut self) {
+        match self.save_open_entry() {
+            Ok(()) => self.info("saved"),
+            Err(e) => self.error(&format!("failed to save entry: {}", e)),
+        }
+    }
+
+    fn run_read_action(&mut self, action: Action) {
+        let page = self.n_fits.max(1) as usize;
+        match action {
+            Action::Down => self.editor.move_down(1),
+            Action::Up => self.editor.move_up(1),
+            Action::Left => self.editor.move_left(),
+            Action::Right => self.editor.move_right(),
+            Action::PageDown => self.editor.move_down(page),
+            Action::PageUp => self.editor.move_up(page),
+            Action::First => self.editor.move_top(),
+            Action::Last => self.editor.move_bottom(),
+            Action::LineStart => self.editor.move_line_start(),
+            Action::LineEnd => self.editor.move_line_end(),
+            Action::Command => self.open_command_bar(""),
+            Action::Close => self.close_entry(),
+            Action::Save => self.save_from_key(),
+            Action::Edit => self.mode = ModeT::OPEN(OpenMode::EDIT),
+            Action::Backlinks => {
+                self.backlinks_open = true;
+                self.backlinks_idx = 0;
+            }
+            Action::FollowLink => self.follow_link_under_cursor(),
+            Action::GoBack => self.go_back(),
+            _ => {}
+        }
+    }
+
+    fn run_edit_action(&mut self, action: Action) {
+        let page = self.n_fits.max(1) as usize;
+        match action {
+            Action::Down => self.editor.move_down(1),
+            Action::Up => self.editor.move_up(1),
+            Action::Left => self.editor.move_left(),
+            Action::Right => self.editor.move_right(),
+            Action::PageDown => self.editor.move_down(page),
+            Action::PageUp => self.editor.move_up(page),
+            Action::LineStart => self.editor.move_line_start(),
+            Action::LineEnd => self.editor.move_line_end(),
+            Action::Newline => self.editor.insert_newline(),
+            Action::Indent => self.editor.insert_str("    "),
+            Action::Backspace => self.editor.backspace(),
+            Action::DeleteChar => self.editor.delete(),
+            Action::Save => self.save_from_key(),
+            Action::Read => self.mode = ModeT::OPEN(OpenMode::READ),
+            _ => {}
+        }
+    }
+
+    fn run_command_action(&mut self, action: Action) {
+        match action {
+            Action::Submit => return self.submit_command(),
+            Action::Close => return self.close_command_bar(),
+            Action::Complete => {
+                self.command_error = None;
+                return self.complete_command();
+            }
+            Action::HistorySearch => {
+                self.history_search = Some(Default::default());
+                return;
+            }
+            Action::Left => self.command_bar.move_left(),
+            Action::Right => self.command_bar.move_right(),
+            Action::LineStart => self.command_bar.move_start(),
+            Action::LineEnd => self.command_bar.move_end(),
+            Action::Backspace => self.command_bar.pop_char(),
+            Action::DeleteChar => self.command_bar.delete_char(),
+            Action::DeleteWord => self.command_bar.delete_word(),
+            Action::DeleteToStart => self.command_bar.delete_to_start(),
+            Action::HistoryPrev => {
+                let current = self.command_bar.get_buffer_contents();
+                if let Some(older) = self.history.older(&current) {
+                    self.command_bar.set(older);
+                }
+            }
+            Action::HistoryNext => {
+                if let Some(newer) = self.history.newer() {
+                    self.command_bar.set(newer);
+                }
+            }
+            _ => return,
+        }
+        self.command_error = None;
+        self.completion = None;
+    }
+}
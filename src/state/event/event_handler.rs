@@ -21,150 +21,241 @@ use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 
 use crate::{
     state::state::State,
-    util::mode::{ModeT, OpenMode},
+    util::{
+        backend::Backend,
+        keymap::{Action, KeyChord, KeyMode, Lookup},
+        mode::{ModeT, OpenMode},
+    },
 };
 
-use std::{
-    io::stdout,
-    time::{Duration, Instant},
-};
+use std::time::{Duration, Instant};
+
+/// how often to look for finished hooks while some are running
+const HOOK_POLL: Duration = Duration::from_millis(100);
 
 impl State {
-    /// main event loop function!
-    pub fn event_loop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.init()?;
-        let mut stdout = stdout();
-        let mut last_tick = Instant::now();
-        let tick_rate = Duration::from_millis(33); // ~30 FPS
+    /// main event loop function! a frame is only drawn when something asked for one, in between
+    /// the loop sleeps until there's input or a timer is due
+    pub fn event_loop(
+        &mut self,
+        backend: &mut impl Backend,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.init(backend)?;
+        self.request_redraw();
 
         loop {
-            if self.handle_event() {
-                break;
+            if self.needs_redraw {
+                self.needs_redraw = false;
+                self.render(backend)?;
             }
-
-            if last_tick.elapsed() >= tick_rate {
-                last_tick = Instant::now();
-                self.render(&mut stdout)?;
+            if self.handle_event() || self.quitting {
+                break;
             }
+            self.run_timers();
         }
-        self.deconstruct();
+        self.deconstruct(backend);
         Ok(())
     }
 
-    /// polls, checks for an event of an sort. returns true if yes
+    /// waits for an event of any sort, or until the next timer is due. returns true to quit
     pub fn handle_event(&mut self) -> bool {
-        if event::poll(Duration::from_millis(10)).unwrap() {
-            match event::read().unwrap() {
-                Event::Key(key_event) => {
-                    if key_event.kind != crossterm::event::KeyEventKind::Press {
-                        // stupid windows bug
-                        return false;
-                    }
-                    return self.handle_key_event(key_event);
+        if let Some(timeout) = self.next_timer() {
+            if !event::poll(timeout).unwrap() {
+                return false;
+            }
+        }
+        match event::read().unwrap() {
+            Event::Key(key_event) => {
+                if key_event.kind != crossterm::event::KeyEventKind::Press {
+                    // stupid windows bug
+                    return false;
                 }
-                Event::Resize(_, _) => self.handle_resize_event(),
-                _ => {} // ignore any other events
+                self.request_redraw();
+                return self.handle_key_event(key_event);
+            }
+            Event::Resize(width, height) => {
+                self.handle_resize_event(width as usize, height as usize);
+                self.request_redraw();
             }
+            Event::Mouse(mouse_event) if self.handle_mouse_event(mouse_event) => {
+                self.request_redraw();
+            }
+            _ => {} // ignore any other events
         }
         false
     }
+
+    /// how long the loop may wait for input before something else needs doing. `None` means
+    /// nothing is pending and it can wait for as long as it takes
+    fn next_timer(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let mut next = self
+            .message
+            .as_ref()
+            .map(|message| message.expires_at().saturating_duration_since(now));
+        if self.hooks_running > 0 {
+            // finished hooks can't wake the loop, so look for them now and then
+            next = Some(next.map_or(HOOK_POLL, |next| next.min(HOOK_POLL)));
+        }
+        next
+    }
+
+    /// everything that happens without input: messages timing out and hooks finishing
+    fn run_timers(&mut self) {
+        if self
+            .message
+            .as_ref()
+            .is_some_and(|message| message.expired())
+        {
+            self.message = None;
+            self.request_redraw();
+        }
+        self.collect_hook_results();
+    }
 }
 
 impl State {
-    /// handles **keyboard input**
+    /// handles **keyboard input**. popups and the screens with keys of their own get the
+    /// first look, everything else goes through the keymap
     pub fn handle_key_event(&mut self, key_event: KeyEvent) -> bool {
-        match key_event.code {
-            KeyCode::Down => {
-                if self.idx_active {
-                    self.idx += 1;
-                    if self.idx >= self.entries_map.len() {
-                        self.idx = 0
-                    } /* else {
-                      }; */
-                }
+        self.record_key(&key_event);
+        if let Some(action) = self.awaiting_register.take() {
+            return self.use_register(action, key_event);
+        }
+        if self.prompt.is_some() {
+            match key_event.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => self.answer_prompt(true),
+                KeyCode::Char('n') | KeyCode::Char('N') => self.answer_prompt(false),
+                KeyCode::Esc => self.prompt = None,
+                _ => {}
             }
-            KeyCode::Up => {
-                if self.idx_active {
-                    if self.idx == 0 {
-                        self.idx = (self.entries_map.len())
-                            .checked_sub(1)
-                            .unwrap_or(self.entries_map.len());
-                    } else {
-                        self.idx -= 1;
+            return false;
+        }
+        if self.help.is_some() {
+            self.handle_help_key(key_event);
+            return false;
+        }
+        if self.command_mode {
+            if self.history_search.is_some() && self.handle_history_search_key(key_event) {
+                return false;
+            }
+            return self.feed_key(KeyMode::Command, key_event);
+        }
+        match self.mode {
+            ModeT::CALENDAR => {
+                if self.handle_calendar_key(key_event) {
+                    return false;
+                }
+                // only the command bar and quitting are shared with browsing
+                let key = KeyChord::from_event(&key_event);
+                match self.keymap.lookup(KeyMode::Browse, &[key]) {
+                    Lookup::Action(action @ (Action::Command | Action::Quit)) => {
+                        self.run_action(KeyMode::Browse, action)
                     }
+                    _ => false,
                 }
             }
-            KeyCode::Esc => {
-                if self.command_mode {
-                    self.command_bar.swap();
-                    self.command_mode = false;
-                } else {
-                    match self.mode {
-                        ModeT::BROWSE => {
-                            return true;
-                        }
-                        ModeT::OPEN(OpenMode::READ) => {
-                            // read
-                        }
-                        _ => {}
-                    }
+            ModeT::OPEN(OpenMode::READ) if self.backlinks_open => {
+                if self.handle_backlinks_key(key_event) {
+                    return false;
                 }
+                self.feed_key(KeyMode::Read, key_event)
             }
-            KeyCode::Char(c) => {
-                if key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                    if c == 'c' {
-                        return true; // exit on CTRL+C
-                    }
-                } else if c == ':' && self.mode != ModeT::OPEN(OpenMode::EDIT) && !self.command_mode
-                {
-                    self.command_bar.swap();
-                    self.command_mode = true;
-                } else {
-                    self.handle_char(c);
+            _ => self.feed_key(self.key_mode(), key_event),
+        }
+    }
+
+    /// keys during a Ctrl+R search. typing narrows the search, Ctrl+R again finds an older
+    /// match, Enter runs the match and Esc gives up on it
+    fn handle_history_search_key(&mut self, key_event: KeyEvent) -> bool {
+        let Some(mut search) = self.history_search.take() else {
+            return false;
+        };
+        let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        let len = self.history.entries.len();
+        match key_event.code {
+            KeyCode::Char('r') if ctrl => {
+                let before = search.found.unwrap_or(len);
+                if let Some(found) = self.history.search(&search.query, before) {
+                    search.found = Some(found);
                 }
             }
+            KeyCode::Char('g') if ctrl => return true,
+            KeyCode::Char(c) if !ctrl => {
+                search.query.push(c);
+                search.found = self.history.search(&search.query, len);
+            }
             KeyCode::Backspace => {
-                if self.command_mode {
-                    self.command_bar.pop_char();
-                }
+                search.query.pop();
+                search.found = self.history.search(&search.query, len);
             }
+            KeyCode::Esc => return true,
             KeyCode::Enter => {
-                if self.command_mode {
-                    self.submit_command();
+                if let Some(found) = search.found {
+                    self.command_bar.set(&self.history.entries[found].clone());
                 }
-                if self.mode == ModeT::BROWSE {
-                    // find selected entry, create buffer for edits made and change state to display dialogue
+                return false; // falls through to the usual Enter, which runs it
+            }
+            _ => {
+                // anything else keeps the match in the command bar to be edited
+                if let Some(found) = search.found {
+                    self.command_bar.set(&self.history.entries[found].clone());
                 }
+                return true;
             }
+        }
+        self.history_search = Some(search);
+        true
+    }
+
+    /// handles keys while the help popup is open, it swallows everything
+    fn handle_help_key(&mut self, key_event: KeyEvent) {
+        let page = self.n_fits.max(1) as usize;
+        match key_event.code {
+            KeyCode::Down | KeyCode::Char('j') => self.help_scroll += 1,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.help_scroll = self.help_scroll.saturating_sub(1)
+            }
+            KeyCode::PageDown => self.help_scroll += page,
+            KeyCode::PageUp => self.help_scroll = self.help_scroll.saturating_sub(page),
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.help = None,
             _ => {}
         }
-        false
+    }
+
+    /// handles keys while the backlinks pane has focus
+    fn handle_backlinks_key(&mut self, key_event: KeyEvent) -> bool {
+        let Some(current_id) = self.current_entry.as_ref().map(|entry| entry.id.clone()) else {
+            return false;
+        };
+        let backlinks = self.backlinks.clone();
+        match key_event.code {
+            KeyCode::Down => self.backlinks_idx = (self.backlinks_idx + 1) % backlinks.len().max(1),
+            KeyCode::Up => {
+                self.backlinks_idx = self
+                    .backlinks_idx
+                    .checked_sub(1)
+                    .unwrap_or(backlinks.len().saturating_sub(1))
+            }
+            KeyCode::Enter => {
+                if let Some(id) = backlinks.get(self.backlinks_idx) {
+                    self.link_history.push(current_id);
+                    self.backlinks_open = false;
+                    self.open_entry(id);
+                }
+            }
+            KeyCode::Char('b') | KeyCode::Esc => self.backlinks_open = false,
+            _ => return false,
+        }
+        true
     }
 
     /// handles **resize events**
-    pub fn handle_resize_event(&mut self) {
-        self.buffer.resize();
-        self.n_fits = (self.buffer.height - 4) as u32;
+    pub fn handle_resize_event(&mut self, width: usize, height: usize) {
+        self.buffer.resize(width, height);
+        self.n_fits = self.screen().body.height as u32;
         self.string_buffer.clear();
         self.populate_string_buffer();
         // crate::util::log_message("resize event, resize() called");
     }
-
-    /// is passed any raw character presses
-    pub fn handle_char(&mut self, c: char) {
-        if self.command_mode {
-            self.command_bar.push_char(c);
-        }
-        match &self.mode {
-            ModeT::BROWSE => {}
-            ModeT::OPEN(open_mode) => match open_mode {
-                OpenMode::EDIT => {
-                    // find the buffer (should be somewhere in state) and push this char to it
-                }
-                OpenMode::READ => {
-                    // we should only really be worried about commands
-                } //_ => {}
-            },
-        }
-    }
 }
@@ -0,0 +1,110 @@
+/*
+ * src/state/event/macros.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// recording key presses into registers and replaying them, like vim's q and @
+use crossterm::event::KeyEvent;
+
+use crate::{
+    state::state::State,
+    util::keymap::{Action, KeyChord},
+};
+
+/// how many macros may replay each other before we assume one replays itself forever
+const MAX_MACRO_DEPTH: usize = 16;
+
+impl State {
+    /// the key after q or @ names the register. returns true if it's time to quit
+    pub fn use_register(&mut self, action: Action, key_event: KeyEvent) -> bool {
+        let chord = KeyChord::from_event(&key_event);
+        let register = match chord.typed_char() {
+            Some('@') if action == Action::PlayMacro => self.last_played,
+            Some(c) if c.is_ascii_alphanumeric() => Some(c),
+            _ => None,
+        };
+        let Some(register) = register else {
+            return false;
+        };
+        if action == Action::RecordMacro {
+            self.recording = Some((register, Vec::new()));
+            return false;
+        }
+        self.play_register(register)
+    }
+
+    /// remember a key press if a macro is being recorded
+    pub fn record_key(&mut self, key_event: &KeyEvent) {
+        if self.macro_depth > 0 {
+            return; // keys coming from a macro are already in the one being replayed
+        }
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(KeyChord::new(key_event.code, key_event.modifiers));
+        }
+    }
+
+    /// finish recording, leaving out the keys that stopped it
+    pub fn stop_recording(&mut self) {
+        let Some((register, mut keys)) = self.recording.take() else {
+            return;
+        };
+        keys.truncate(keys.len().saturating_sub(self.last_sequence_len));
+        self.registers.set(register, &keys);
+        match self.registers.save() {
+            Ok(()) => self.info(&format!("recorded {} keys into @{}", keys.len(), register)),
+            Err(e) => self.error(&format!("failed to save registers: {}", e)),
+        }
+    }
+
+    /// press the keys in a register again. returns true if they quit
+    pub fn play_register(&mut self, register: char) -> bool {
+        let Some(keys) = self.registers.get(register) else {
+            self.warn(&format!("register @{} is empty", register));
+            return false;
+        };
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            self.error(&format!("@{} replays itself, stopped", register));
+            return false;
+        }
+        self.last_played = Some(register);
+        self.macro_depth += 1;
+        let mut quit = false;
+        for key in keys {
+            if self.handle_key_event(KeyEvent::new(key.code, key.modifiers)) || self.quitting {
+                quit = true;
+                break;
+            }
+        }
+        self.macro_depth -= 1;
+        quit
+    }
+
+    /// the registers as lines for the help popup
+    pub fn register_lines(&self) -> Vec<String> {
+        let mut lines = vec![String::from("registers"), String::new()];
+        if self.registers.macros.is_empty() {
+            lines.push(String::from("nothing recorded yet"));
+        }
+        lines.extend(
+            self.registers
+                .macros
+                .iter()
+                .map(|(register, keys)| format!("@{}  {}", register, keys)),
+        );
+        lines
+    }
+}
@@ -17,4 +17,7 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod action;
 pub mod event_handler;
+pub mod macros;
+pub mod mouse;
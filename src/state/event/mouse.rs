@@ -0,0 +1,205 @@
+/*
+ * src/state/event/mouse.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// **mouse input**: clicking and double-clicking entries, the wheel, placing the cursor and
+/// dragging out a selection in the open entry, and dragging the divider next to the preview
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use std::time::{Duration, Instant};
+
+use crate::{
+    state::state::State,
+    util::{
+        markdown,
+        mode::{ModeT, OpenMode},
+        text::char_width,
+    },
+};
+
+/// two clicks on the same cell closer together than this make a double-click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// rows moved by one notch of the wheel
+const SCROLL_LINES: isize = 3;
+
+impl State {
+    /// handles a mouse event, positions being the (column, row) of the cell under the pointer.
+    /// popups only take the wheel and the command bar nothing at all. returns false if the
+    /// event changed nothing, so plain movement doesn't cost a frame
+    pub fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> bool {
+        let kind = mouse_event.kind;
+        let (x, y) = (mouse_event.column as usize, mouse_event.row as usize);
+        if kind == MouseEventKind::Moved || self.buffer.too_small_flag {
+            return false;
+        }
+        if self.help.is_some() {
+            match kind {
+                MouseEventKind::ScrollDown => self.help_scroll += SCROLL_LINES as usize,
+                MouseEventKind::ScrollUp => {
+                    self.help_scroll = self.help_scroll.saturating_sub(SCROLL_LINES as usize)
+                }
+                _ => return false,
+            }
+            return true;
+        }
+        if self.prompt.is_some() || self.command_mode {
+            return false;
+        }
+        match self.mode {
+            ModeT::BROWSE => self.handle_browse_mouse(kind, x, y),
+            ModeT::OPEN(_) => self.handle_open_mouse(kind, x, y),
+            ModeT::CALENDAR => return false,
+        }
+        true
+    }
+
+    /// a click selects an entry and a double-click opens it. the wheel scrolls the list, and
+    /// the divider between list and preview can be dragged
+    fn handle_browse_mouse(&mut self, kind: MouseEventKind, x: usize, y: usize) {
+        let (list, preview) = self.browse_panes();
+        match kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if preview.is_some() && x == list.right() && list.contains(list.x, y) {
+                    self.dragging_split = true;
+                } else if list.contains(x, y) {
+                    let i = self.scroll + y - list.y;
+                    let double = self.double_click(x, y) && i == self.idx;
+                    if i < self.master_index.entries.len() {
+                        self.idx = i;
+                        if double {
+                            self.open_selected_entry();
+                        }
+                    }
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging_split => {
+                let body = self.screen().body;
+                let ratio = (x.saturating_sub(body.x) * 100).div_ceil(body.width.max(1));
+                self.set_split_ratio(ratio);
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.dragging_split = false,
+            MouseEventKind::ScrollDown => self.scroll_list(SCROLL_LINES),
+            MouseEventKind::ScrollUp => self.scroll_list(-SCROLL_LINES),
+            _ => {}
+        }
+    }
+
+    /// a click puts the cursor where it was and dragging from there selects up to the
+    /// pointer. the wheel scrolls the text
+    fn handle_open_mouse(&mut self, kind: MouseEventKind, x: usize, y: usize) {
+        let (text_area, _) = self.open_panes();
+        match kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if !text_area.contains(x, y) {
+                    self.editor.anchor = None;
+                    return;
+                }
+                self.place_cursor(self.editor.scroll + y - text_area.y, x - text_area.x);
+                self.editor.anchor = Some((self.editor.row, self.editor.col));
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.editor.anchor.is_some() => {
+                // dragging past the top or bottom takes the text along
+                if y < text_area.y {
+                    self.scroll_text(-1);
+                } else if y >= text_area.bottom() {
+                    self.scroll_text(1);
+                }
+                let row = y.clamp(text_area.y, text_area.bottom().saturating_sub(1)) - text_area.y;
+                let x = x.clamp(text_area.x, text_area.right()) - text_area.x;
+                let anchor = self.editor.anchor;
+                self.place_cursor(self.editor.scroll + row, x);
+                self.editor.anchor = anchor;
+            }
+            MouseEventKind::ScrollDown => self.scroll_text(SCROLL_LINES),
+            MouseEventKind::ScrollUp => self.scroll_text(-SCROLL_LINES),
+            _ => {}
+        }
+    }
+
+    /// remembers a click on (x, y) and says whether it made a double-click
+    fn double_click(&mut self, x: usize, y: usize) -> bool {
+        let now = Instant::now();
+        let double = self.last_click.is_some_and(|(at, last_x, last_y)| {
+            (last_x, last_y) == (x, y) && now.duration_since(at) < DOUBLE_CLICK
+        });
+        // a third click starts over instead of making another double-click
+        self.last_click = if double { None } else { Some((now, x, y)) };
+        double
+    }
+
+    /// put the cursor on whatever is drawn at cell `x` of display row `row`, counting rows from
+    /// the top of the text rather than the screen
+    fn place_cursor(&mut self, row: usize, x: usize) {
+        let width = self.open_panes().0.width;
+        if self.mode == ModeT::OPEN(OpenMode::READ) {
+            let rows = markdown::render(&self.editor.text(), width, &self.theme);
+            if let Some(offset) = markdown::source_at(&rows, row, x) {
+                self.editor.set_offset(offset);
+            }
+            return;
+        }
+
+        let rows = self.editor.display_rows(width);
+        let Some(&display_row) = rows.get(row).or(rows.last()) else {
+            return;
+        };
+        let line = &self.editor.lines[display_row.row];
+        let mut col = display_row.start;
+        let mut used = 0;
+        for c in line.chars().take(display_row.end).skip(display_row.start) {
+            used += char_width(c);
+            if used > x {
+                break;
+            }
+            col += 1;
+        }
+        // the end of a wrapped row is where the next one starts, so stop just short of it
+        if display_row.end < self.editor.line_len(display_row.row) {
+            col = col.min(display_row.end.saturating_sub(1));
+        }
+        self.editor.set_cursor(display_row.row, col);
+    }
+
+    /// scroll the open entry by `delta` rows, taking the cursor along if it would go off screen
+    fn scroll_text(&mut self, delta: isize) {
+        let text_area = self.open_panes().0;
+        let (width, height) = (text_area.width, text_area.height.max(1));
+        let (total, (cursor_row, cursor_x)) = match self.mode {
+            ModeT::OPEN(OpenMode::READ) => {
+                let rows = markdown::render(&self.editor.text(), width, &self.theme);
+                (rows.len(), markdown::locate(&rows, self.editor.offset()))
+            }
+            _ => (
+                self.editor.display_rows(width).len(),
+                self.editor.cursor_position(width),
+            ),
+        };
+        let max_scroll = total.saturating_sub(height);
+        self.editor.scroll = self
+            .editor
+            .scroll
+            .saturating_add_signed(delta)
+            .min(max_scroll);
+
+        let scroll = self.editor.scroll;
+        if cursor_row < scroll {
+            self.place_cursor(scroll, cursor_x);
+        } else if cursor_row >= scroll + height {
+            self.place_cursor(scroll + height - 1, cursor_x);
+        }
+    }
+}
@@ -17,37 +17,369 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::state::state::State;
-use crate::util::command::{Command, Commander};
+use crate::state::{editor::editor::Editor, state::State};
+use crate::util::command::Commander;
+use crate::util::constant::{MAX_SPLIT, MIN_SPLIT};
+use crate::util::hooks::{HookEvent, HookPayload};
+use crate::util::message::{Level, Message};
+use crate::util::mode::{ModeT, OpenMode};
+use crate::util::prompt::{Prompt, PromptAction};
 use crate::util::util::log_message;
-use std::str::FromStr;
 
 impl State {
+    /// run what's in the command bar. if it doesn't parse, the command window stays up with the
+    /// error pointing at the problem so it can be fixed
     pub fn submit_command(&mut self) {
         let input = self.command_bar.get_buffer_contents();
-        self.command_bar.clear();
-        self.command_bar.swap();
-        match input.parse::<Command>() {
-            Ok(cmd) => {
-                Commander::dispatch(cmd, self);
+        self.completion = None;
+        self.history.push(&input);
+        if let Err(e) = self.history.save() {
+            self.warn(&format!("failed to save command history: {}", e));
+        }
+        match Commander::parse(&input) {
+            Ok((spec, parsed)) => {
+                self.command_error = None;
+                self.command_bar.clear();
+                self.command_bar.swap();
+                self.command_mode = false;
+                Commander::dispatch(spec, &parsed, self);
             }
-            Err(_) => {
-                eprintln!("Failed to parse command: {}", input);
+            Err(e) => {
+                self.command_error = Some(e);
             }
         }
-        self.command_mode = false;
     }
-    /*pub fn load_selected_entry_for_edit(&mut self) {
-        if self.idx_active && self.entries_map.len() > 0 && !self.idx > self.entries_map.len() {
-            let selected_entry_meta = self.master_index.entries.get(self.idx).unwrap_or(
-                {
-                    log_message("failed to load slected entry!");
-                    &EntryMeta {
-                id: String::from("");
-                label: String::from("meta label")
 
+    /// show `text` in the status line. errors are also written to the log
+    pub fn notify(&mut self, level: Level, text: &str) {
+        if level == Level::Error {
+            log_message(text);
+        }
+        self.message = Some(Message::new(level, text));
+        self.request_redraw();
+    }
+
+    /// have the next turn of the event loop draw a frame
+    pub fn request_redraw(&mut self) {
+        self.needs_redraw = true;
+    }
+
+    pub fn info(&mut self, text: &str) {
+        self.notify(Level::Info, text);
+    }
+
+    pub fn warn(&mut self, text: &str) {
+        self.notify(Level::Warn, text);
+    }
+
+    pub fn error(&mut self, text: &str) {
+        self.notify(Level::Error, text);
+    }
+
+    /// run the hook for `event` on an entry, if one is configured
+    pub fn fire_hook(&mut self, event: HookEvent, entry_id: &str) {
+        if let Some(payload) = self.hook_payload(event, entry_id) {
+            self.queue_hook(event, payload);
+        }
+    }
+
+    /// what the hook for `event` will be told about an entry, or None if there's no hook for
+    /// it. a delete takes this before the entry is gone from the index and queues it after
+    pub fn hook_payload(&self, event: HookEvent, entry_id: &str) -> Option<HookPayload> {
+        self.config.hooks.command(event)?;
+        let meta = self
+            .master_index
+            .entries
+            .iter()
+            .find(|meta| meta.id == entry_id)?;
+        Some(HookPayload {
+            event: event.to_string(),
+            id: meta.id.clone(),
+            label: meta.label.clone(),
+            path: self.config.entries_path.join(&meta.file),
+            entries_path: self.config.entries_path.clone(),
+        })
+    }
+
+    pub fn queue_hook(&mut self, event: HookEvent, payload: HookPayload) {
+        let Some(command) = self.config.hooks.command(event) else {
+            return;
+        };
+        self.hook_queue.push(event, command, payload);
+        self.hooks_running += 1;
+    }
+
+    /// show failures of hooks that finished since the last look
+    pub fn collect_hook_results(&mut self) {
+        let results: Vec<_> = match self.hook_queue.results.lock() {
+            Ok(mut results) => results.drain(..).collect(),
+            Err(_) => return,
+        };
+        if results.is_empty() {
+            return;
+        }
+        self.hooks_running = self.hooks_running.saturating_sub(results.len());
+        self.request_redraw();
+        for result in results {
+            if let Err(e) = result.outcome {
+                self.error(&format!("{} hook failed: {}", result.event, e));
             }
-            });
         }
-    }*/
+    }
+
+    /// open the help popup with `lines` in it
+    pub fn show_help(&mut self, lines: Vec<String>) {
+        self.help = Some(lines);
+        self.help_scroll = 0;
+    }
+
+    /// move the selection down one entry, wrapping around to the top
+    pub fn select_next(&mut self) {
+        let len = self.master_index.entries.len();
+        if self.idx_active && len > 0 {
+            self.idx = (self.idx + 1) % len;
+        }
+    }
+
+    /// move the selection up one entry, wrapping around to the bottom
+    pub fn select_prev(&mut self) {
+        let len = self.master_index.entries.len();
+        if self.idx_active && len > 0 {
+            self.idx = self.idx.checked_sub(1).unwrap_or(len - 1);
+        }
+    }
+
+    /// move the selection a whole screen of entries down, stopping at the last one
+    pub fn select_page_down(&mut self) {
+        let len = self.master_index.entries.len();
+        if self.idx_active && len > 0 {
+            self.idx = std::cmp::min(self.idx + self.n_fits.max(1) as usize, len - 1);
+        }
+    }
+
+    /// move the selection a whole screen of entries up, stopping at the first one
+    pub fn select_page_up(&mut self) {
+        if self.idx_active {
+            self.idx = self.idx.saturating_sub(self.n_fits.max(1) as usize);
+        }
+    }
+
+    pub fn select_first(&mut self) {
+        if self.idx_active {
+            self.idx = 0;
+        }
+    }
+
+    pub fn select_last(&mut self) {
+        if self.idx_active {
+            self.idx = self.master_index.entries.len().saturating_sub(1);
+        }
+    }
+
+    /// keep `scroll` such that the selected entry is on screen. called before the list is drawn,
+    /// so anything that moves `idx` (reordering, pinning, deleting) gets followed for free
+    pub fn follow_selection(&mut self) {
+        let n_fits = self.n_fits.max(1) as usize;
+        if self.idx < self.scroll {
+            self.scroll = self.idx;
+        } else if self.idx >= self.scroll + n_fits {
+            self.scroll = self.idx + 1 - n_fits;
+        }
+        let max_scroll = self.master_index.entries.len().saturating_sub(n_fits);
+        if self.scroll > max_scroll {
+            self.scroll = max_scroll;
+        }
+    }
+
+    /// move the list `delta` rows without the selection, which only moves as far as it has to
+    /// to stay on screen
+    pub fn scroll_list(&mut self, delta: isize) {
+        let len = self.master_index.entries.len();
+        if len == 0 {
+            return;
+        }
+        let n_fits = self.n_fits.max(1) as usize;
+        let max_scroll = len.saturating_sub(n_fits);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max_scroll);
+        self.idx = self.idx.clamp(self.scroll, self.scroll + n_fits - 1);
+    }
+
+    /// give the list `ratio` percent of the width next to the preview, within bounds
+    pub fn set_split_ratio(&mut self, ratio: usize) {
+        self.split_ratio = ratio.clamp(MIN_SPLIT, MAX_SPLIT);
+    }
+
+    /// open the selected entry for reading
+    pub fn open_selected_entry(&mut self) {
+        if let Some(meta) = self.master_index.entries.get(self.idx) {
+            let id = meta.id.clone();
+            self.open_entry(&id);
+        }
+    }
+
+    /// load an entry into the editor and switch to READ mode. the mode we came from is kept
+    /// in `last_mode` so closing the entry goes back there (the list or the calendar). unsaved
+    /// edits to the open entry are asked about first, and the entry is opened after the answer
+    pub fn open_entry(&mut self, entry_id: &str) -> bool {
+        if self.editor.dirty && self.current_entry.is_some() {
+            self.ask_to_save(PromptAction::SaveAndOpen(entry_id.to_string()));
+            return false;
+        }
+        let Some(pos) = self
+            .master_index
+            .entries
+            .iter()
+            .position(|meta| meta.id == entry_id)
+        else {
+            return false;
+        };
+        let Some(entry) = self.entries_map.get(entry_id) else {
+            return false;
+        };
+
+        self.idx = pos;
+        self.editor = Editor::new(&entry.content);
+        self.current_entry = Some(entry.clone());
+        self.current_entry_meta = Some(self.master_index.entries[pos].clone());
+        self.backlinks = self.find_backlinks(entry_id);
+        self.backlinks_idx = 0;
+        if !matches!(self.mode, ModeT::OPEN(_)) {
+            self.last_mode = self.mode.clone();
+        }
+        self.mode = ModeT::OPEN(OpenMode::READ);
+        true
+    }
+
+    /// leave the open entry and go back to wherever it was opened from. unsaved edits are
+    /// asked about first
+    pub fn close_entry(&mut self) {
+        if self.editor.dirty {
+            self.ask_to_save(PromptAction::SaveAndClose);
+            return;
+        }
+        self.editor = Editor::default();
+        self.link_history.clear();
+        self.backlinks_open = false;
+        self.mode = self.last_mode.clone();
+        if matches!(self.mode, ModeT::OPEN(_)) {
+            self.mode = ModeT::BROWSE;
+        }
+    }
+
+    /// ask whether to save the open entry before `action` leaves it
+    fn ask_to_save(&mut self, action: PromptAction) {
+        let label = self
+            .current_entry
+            .as_ref()
+            .map_or(String::new(), |entry| entry.label.clone());
+        self.prompt = Some(Prompt::new(
+            &format!("save changes to '{}'?", label),
+            action,
+        ));
+    }
+
+    /// copy the editor's text into the open entry and save it
+    pub fn save_open_entry(&mut self) -> std::io::Result<()> {
+        if matches!(self.mode, ModeT::OPEN(_)) {
+            if let Some(current) = &mut self.current_entry {
+                current.content = self.editor.text();
+            }
+        }
+        self.save_current_entry()?;
+        self.editor.dirty = false;
+        if let Some(id) = self.current_entry.as_ref().map(|entry| entry.id.clone()) {
+            self.fire_hook(HookEvent::Save, &id);
+        }
+        Ok(())
+    }
+
+    /// save edits to the open entry before something else takes its place
+    pub fn stash_open_edits(&mut self) {
+        if self.editor.dirty && self.current_entry.is_some() {
+            if let Err(e) = self.save_open_entry() {
+                self.error(&format!("failed to save entry: {}", e));
+            }
+        }
+    }
+
+    /// rename an entry. if other entries link to the old label the user is offered to point
+    /// them at the new one
+    pub fn rename_with_relink(&mut self, id: &str, new_label: &str) -> std::io::Result<()> {
+        let old_label = self
+            .master_index
+            .entries
+            .iter()
+            .find(|meta| meta.id == id)
+            .map_or(String::new(), |meta| meta.label.clone());
+
+        self.rename_entry(id, new_label)?;
+
+        let n_links = self.count_links_to_label(&old_label);
+        if n_links > 0 && !old_label.eq_ignore_ascii_case(new_label) {
+            self.prompt = Some(Prompt::new(
+                &format!("update {} entries linking to '{}'?", n_links, old_label),
+                PromptAction::RelinkRenamed {
+                    old_label,
+                    new_label: new_label.to_string(),
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    /// act on the user's answer to the prompt that is up
+    pub fn answer_prompt(&mut self, yes: bool) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        match prompt.action {
+            PromptAction::SaveAndClose => {
+                if yes {
+                    if let Err(e) = self.save_open_entry() {
+                        self.error(&format!("failed to save entry: {}", e));
+                        return;
+                    }
+                    self.info("saved");
+                }
+                self.editor.dirty = false;
+                self.close_entry();
+            }
+            PromptAction::SaveAndOpen(id) => {
+                if yes {
+                    if let Err(e) = self.save_open_entry() {
+                        self.error(&format!("failed to save entry: {}", e));
+                        return;
+                    }
+                    self.info("saved");
+                }
+                self.editor.dirty = false;
+                self.open_entry(&id);
+            }
+            PromptAction::DeleteEntries(ids) => {
+                if yes {
+                    for id in &ids {
+                        if let Err(e) = self.delete_entry(id) {
+                            self.error(&format!("failed to delete entry: {}", e));
+                            return;
+                        }
+                    }
+                    match ids.len() {
+                        1 => self.info("entry deleted"),
+                        n => self.info(&format!("{} entries deleted", n)),
+                    }
+                }
+            }
+            PromptAction::RelinkRenamed {
+                old_label,
+                new_label,
+            } => {
+                if yes {
+                    match self.rewrite_links(&old_label, &new_label) {
+                        Ok(n) => self.info(&format!("updated links in {} entries", n)),
+                        Err(e) => self.error(&format!("failed to update links: {}", e)),
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,307 @@
+/*
+ * src/state/link/link.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// wiki-style links between entries: `[[label]]` or `[[id]]` anywhere in an entry's content.
+/// labels are matched case-insensitively, ids may be shortened to a unique prefix
+use crate::state::{editor::editor::Editor, state::State};
+use crate::util::util::EntryMeta;
+
+/// the shortest id prefix that is accepted as a link target
+pub const MIN_ID_PREFIX: usize = 4;
+
+/// a link found in one line of text. `start..end` are char offsets covering the brackets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkSpan {
+    pub start: usize,
+    pub end: usize,
+    pub target: String,
+}
+
+/// every `[[...]]` in `line`, in order. empty or unclosed links are ignored
+pub fn find_links(line: &str) -> Vec<LinkSpan> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut links = Vec::new();
+    let mut i = 0;
+    while i + 1 < chars.len() {
+        if chars[i] == '[' && chars[i + 1] == '[' {
+            let open = i;
+            let mut j = i + 2;
+            while j + 1 < chars.len() && !(chars[j] == ']' && chars[j + 1] == ']') {
+                j += 1;
+            }
+            if j + 1 >= chars.len() {
+                break; // unclosed
+            }
+            let target: String = chars[open + 2..j].iter().collect();
+            if !target.trim().is_empty() {
+                links.push(LinkSpan {
+                    start: open,
+                    end: j + 2,
+                    target: target.trim().to_string(),
+                });
+            }
+            i = j + 2;
+        } else {
+            i += 1;
+        }
+    }
+    links
+}
+
+/// the id of the entry among `entries` a link target points at: an exact id, then a label,
+/// then an id prefix
+pub fn resolve(entries: &[EntryMeta], target: &str) -> Option<String> {
+    if let Some(meta) = entries.iter().find(|meta| meta.id == target) {
+        return Some(meta.id.clone());
+    }
+    if let Some(meta) = entries
+        .iter()
+        .find(|meta| meta.label.eq_ignore_ascii_case(target))
+    {
+        return Some(meta.id.clone());
+    }
+    if target.len() >= MIN_ID_PREFIX {
+        let mut matches = entries.iter().filter(|meta| meta.id.starts_with(target));
+        if let (Some(meta), None) = (matches.next(), matches.next()) {
+            return Some(meta.id.clone());
+        }
+    }
+    None
+}
+
+impl State {
+    /// the id of the entry a link target points at, see `resolve`
+    pub fn resolve_link(&self, target: &str) -> Option<String> {
+        resolve(&self.master_index.entries, target)
+    }
+
+    /// ids of every entry with a link to `entry_id`, in master index order
+    pub fn find_backlinks(&self, entry_id: &str) -> Vec<String> {
+        self.master_index
+            .entries
+            .iter()
+            .filter(|meta| meta.id != entry_id)
+            .filter(|meta| {
+                self.entries_map.get(&meta.id).is_some_and(|entry| {
+                    entry.content.lines().any(|line| {
+                        find_links(line).iter().any(|link| {
+                            self.resolve_link(&link.target).as_deref() == Some(entry_id)
+                        })
+                    })
+                })
+            })
+            .map(|meta| meta.id.clone())
+            .collect()
+    }
+
+    /// the target of the link the cursor is on in the open entry, if any
+    pub fn link_under_cursor(&self) -> Option<String> {
+        let line = self.editor.lines.get(self.editor.row)?;
+        find_links(line)
+            .into_iter()
+            .find(|link| link.start <= self.editor.col && self.editor.col < link.end)
+            .map(|link| link.target)
+    }
+
+    /// open the entry the link under the cursor points at. the entry we leave is remembered so
+    /// `go_back` can return to it
+    pub fn follow_link_under_cursor(&mut self) {
+        let Some(target) = self.link_under_cursor() else {
+            return;
+        };
+        let Some(id) = self.resolve_link(&target) else {
+            return;
+        };
+        if let Some(current) = &self.current_entry {
+            self.link_history.push(current.id.clone());
+        }
+        self.open_entry(&id);
+    }
+
+    /// return to the entry we followed the last link from
+    pub fn go_back(&mut self) {
+        while let Some(id) = self.link_history.pop() {
+            // asking about unsaved edits first still counts as going back
+            if self.open_entry(&id) || self.prompt.is_some() {
+                return;
+            }
+        }
+    }
+
+    /// how many entries have a link written with `label` as the target
+    pub fn count_links_to_label(&self, label: &str) -> usize {
+        self.entries_map
+            .values()
+            .filter(|entry| {
+                entry.content.lines().any(|line| {
+                    find_links(line)
+                        .iter()
+                        .any(|link| link.target.eq_ignore_ascii_case(label))
+                })
+            })
+            .count()
+    }
+
+    /// point every `[[old_label]]` at `new_label` instead, writing each changed entry to disk.
+    /// returns the number of entries that changed
+    pub fn rewrite_links(&mut self, old_label: &str, new_label: &str) -> std::io::Result<usize> {
+        let ids: Vec<String> = self
+            .master_index
+            .entries
+            .iter()
+            .map(|meta| meta.id.clone())
+            .collect();
+        let mut changed = 0;
+
+        for id in ids {
+            let Some(entry) = self.entries_map.get_mut(&id) else {
+                continue;
+            };
+            let rewritten = rewrite_text_links(&entry.content, old_label, new_label);
+            if rewritten == entry.content {
+                continue;
+            }
+            entry.content = rewritten;
+            self.write_entry(&id)?;
+            changed += 1;
+
+            // keep the open entry in step, unless it has edits of its own we'd throw away
+            if let Some(current) = &mut self.current_entry {
+                if current.id == id {
+                    current.content = self.entries_map[&id].content.clone();
+                    if !self.editor.dirty {
+                        let (row, col) = (self.editor.row, self.editor.col);
+                        self.editor = Editor::new(&current.content);
+                        self.editor.set_cursor(row, col);
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// `text` with the target of every link to `old_label` replaced by `new_label`
+fn rewrite_text_links(text: &str, old_label: &str, new_label: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            let mut out = String::new();
+            let mut last = 0;
+            for link in find_links(line) {
+                if link.target.eq_ignore_ascii_case(old_label) {
+                    out.extend(&chars[last..link.start]);
+                    out.push_str(&format!("[[{}]]", new_label));
+                    last = link.end;
+                }
+            }
+            out.extend(&chars[last..]);
+            out
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_links() {
+        let targets = |line: &str| -> Vec<(usize, usize, String)> {
+            find_links(line)
+                .into_iter()
+                .map(|link| (link.start, link.end, link.target))
+                .collect()
+        };
+        type Case<'a> = (&'a str, &'a [(usize, usize, &'a str)]);
+        let cases: [Case; 7] = [
+            ("no links", &[]),
+            ("see [[a]]", &[(4, 9, "a")]),
+            (
+                "[[ spaced out ]] and [[b]]",
+                &[(0, 16, "spaced out"), (21, 26, "b")],
+            ),
+            ("日本 [[語]]", &[(3, 8, "語")]),
+            ("empty [[ ]] and [[]]", &[]),
+            ("unclosed [[a] and [[b", &[]),
+            ("[[[a]]]", &[(0, 6, "[a")]),
+        ];
+        for (line, expected) in cases {
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|&(start, end, target)| (start, end, target.to_string()))
+                .collect();
+            assert_eq!(targets(line), expected, "links in {:?}", line);
+        }
+    }
+
+    #[test]
+    fn resolves() {
+        let meta = |id: &str, label: &str| EntryMeta {
+            id: id.to_string(),
+            label: label.to_string(),
+            date: String::new(),
+            file: String::new(),
+            tags: Vec::new(),
+            pinned: false,
+        };
+        let entries = [
+            meta("abcd1234", "Groceries"),
+            meta("abce5678", "abcd1234"),
+            meta("ffff0000", "notes"),
+        ];
+        let cases = [
+            ("abcd1234", Some("abcd1234")), // an id beats a label
+            ("groceries", Some("abcd1234")),
+            ("NOTES", Some("ffff0000")),
+            ("ffff", Some("ffff0000")),
+            ("abc", None), // too short a prefix
+            ("abcd", Some("abcd1234")),
+            ("abce", Some("abce5678")),
+            ("ab", None),
+            ("nothing", None),
+        ];
+        for (target, id) in cases {
+            assert_eq!(
+                resolve(&entries, target).as_deref(),
+                id,
+                "resolving {:?}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn rewrites_links() {
+        let cases = [
+            ("see [[Old]] and [[old]]", "see [[New one]] and [[New one]]"),
+            ("[[ old ]] stays linked", "[[New one]] stays linked"),
+            ("[[older]] and [[other]]", "[[older]] and [[other]]"),
+            (
+                "日本 [[old]]\n[[old]] 語",
+                "日本 [[New one]]\n[[New one]] 語",
+            ),
+            ("no links, old", "no links, old"),
+        ];
+        for (text, rewritten) in cases {
+            assert_eq!(rewrite_text_links(text, "old", "New one"), rewritten);
+        }
+    }
+}
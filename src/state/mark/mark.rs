@@ -0,0 +1,150 @@
+/*
+ * src/state/mark/mark.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// marking entries in BROWSE so that commands can work on several of them at once, and the
+/// list filter that `*` marks the matches of. marks are kept by id so they survive reordering
+use crate::{
+    state::{reference::reference::match_rank, state::State},
+    util::{command::EntryId, parser::Range, util::EntryMeta},
+};
+
+/// an entry matches the filter if its label contains it or one of its tags starts with it
+pub fn matches_filter(meta: &EntryMeta, filter: &str) -> bool {
+    let lower = filter.to_lowercase();
+    match_rank(&meta.label, filter).is_some_and(|rank| rank <= 2)
+        || meta
+            .tags
+            .iter()
+            .any(|tag| tag.to_lowercase().starts_with(&lower))
+}
+
+impl State {
+    /// mark the selected entry, or unmark it, then move on to the next one
+    pub fn toggle_mark(&mut self) {
+        let Some(meta) = self.master_index.entries.get(self.idx) else {
+            return;
+        };
+        if !self.marked.remove(&meta.id) {
+            self.marked.insert(meta.id.clone());
+        }
+        if self.idx + 1 < self.master_index.entries.len() {
+            self.idx += 1;
+        }
+    }
+
+    /// the first press drops an anchor at the selected entry, the second marks everything
+    /// between the anchor and the selection
+    pub fn mark_range(&mut self) {
+        let Some(anchor) = self.mark_anchor.take() else {
+            if self.idx_active {
+                self.mark_anchor = Some(self.idx);
+            }
+            return;
+        };
+        let (start, end) = (anchor.min(self.idx), anchor.max(self.idx));
+        for meta in self.master_index.entries.iter().take(end + 1).skip(start) {
+            self.marked.insert(meta.id.clone());
+        }
+    }
+
+    /// true if the entry at `i` is between the anchor of an unfinished range and the selection
+    pub fn in_pending_range(&self, i: usize) -> bool {
+        self.mark_anchor
+            .is_some_and(|anchor| (anchor.min(self.idx)..=anchor.max(self.idx)).contains(&i))
+    }
+
+    /// mark every entry matching the filter. returns how many match
+    pub fn mark_filter_matches(&mut self) -> usize {
+        let Some(filter) = &self.filter else {
+            return 0;
+        };
+        let ids: Vec<EntryId> = self
+            .master_index
+            .entries
+            .iter()
+            .filter(|meta| matches_filter(meta, filter))
+            .map(|meta| meta.id.clone())
+            .collect();
+        let n = ids.len();
+        self.marked.extend(ids);
+        n
+    }
+
+    /// drop the marks and any half-made range. returns false if there was nothing to clear
+    pub fn clear_marks(&mut self) -> bool {
+        let had_any = !self.marked.is_empty() || self.mark_anchor.is_some();
+        self.marked.clear();
+        self.mark_anchor = None;
+        had_any
+    }
+
+    /// the marked entries in list order
+    pub fn marked_ids(&self) -> Vec<EntryId> {
+        self.master_index
+            .entries
+            .iter()
+            .filter(|meta| self.marked.contains(&meta.id))
+            .map(|meta| meta.id.clone())
+            .collect()
+    }
+
+    /// true if the entry at `i` matches the filter, if there is one
+    pub fn is_filter_match(&self, i: usize) -> bool {
+        match (&self.filter, self.master_index.entries.get(i)) {
+            (Some(filter), Some(meta)) => matches_filter(meta, filter),
+            _ => false,
+        }
+    }
+
+    /// select the next entry matching the filter, or the previous one, wrapping around
+    pub fn select_filter_match(&mut self, forward: bool) {
+        let len = self.master_index.entries.len();
+        if self.filter.is_none() || len == 0 {
+            return;
+        }
+        for step in 1..=len {
+            let i = if forward {
+                (self.idx + step) % len
+            } else {
+                (self.idx + len - step) % len
+            };
+            if self.is_filter_match(i) {
+                self.idx = i;
+                return;
+            }
+        }
+    }
+
+    /// the entries a bulk command works on: the range if it got one, otherwise the marked
+    /// entries, otherwise the selected one
+    pub fn target_ids(&self, range: Option<Range>) -> Result<Vec<EntryId>, String> {
+        if let Some(range) = range {
+            let (start, end) = range.resolve(self.idx, self.master_index.entries.len())?;
+            return Ok(self.master_index.entries[start..=end]
+                .iter()
+                .map(|meta| meta.id.clone())
+                .collect());
+        }
+        let marked = self.marked_ids();
+        if !marked.is_empty() {
+            return Ok(marked);
+        }
+        self.resolve_entry_ref("").map(|id| vec![id])
+    }
+}
@@ -17,9 +17,13 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod calendar;
 pub mod db;
 pub mod editor;
 pub mod event;
 pub mod helper;
+pub mod link;
+pub mod mark;
+pub mod reference;
 pub mod state;
 pub mod terminal;
@@ -0,0 +1,148 @@
+/*
+ * src/state/reference/reference.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// entry references: how commands name the entry they work on. a reference is the selected
+/// entry (`.` or nothing at all), a list position (`3`), a unique id prefix (`3f2a`) or a label,
+/// which may be abbreviated as long as only one entry matches it
+use crate::{
+    state::{link::link::MIN_ID_PREFIX, state::State},
+    util::{command::EntryId, mode::ModeT},
+};
+
+/// how many of the entries matching an ambiguous reference are listed in the error
+const AMBIGUOUS_LISTED: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryRef {
+    Selected,
+    Position(usize), // 1-based, as shown in the list
+    Text(String),    // an id, id prefix or label
+}
+
+impl EntryRef {
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        if text.is_empty() || text == "." {
+            EntryRef::Selected
+        } else if let Ok(n) = text.parse::<usize>() {
+            EntryRef::Position(n)
+        } else {
+            EntryRef::Text(text.to_string())
+        }
+    }
+}
+
+/// how well `label` matches `query`, lower is better: exact, prefix, substring, and finally the
+/// query's chars appearing in order. None if it doesn't match at all
+pub fn match_rank(label: &str, query: &str) -> Option<u8> {
+    let label = label.to_lowercase();
+    let query = query.to_lowercase();
+    if label == query {
+        return Some(0);
+    }
+    if label.starts_with(&query) {
+        return Some(1);
+    }
+    if label.contains(&query) {
+        return Some(2);
+    }
+    let mut chars = label.chars();
+    query.chars().all(|q| chars.any(|c| c == q)).then_some(3)
+}
+
+impl State {
+    /// the entry a command without an entry reference works on: the open one, or the selected
+    /// one when browsing
+    pub fn selected_entry_id(&self) -> Option<EntryId> {
+        match (&self.mode, &self.current_entry) {
+            (ModeT::OPEN(_), Some(entry)) => Some(entry.id.clone()),
+            _ if self.idx_active => self
+                .master_index
+                .entries
+                .get(self.idx)
+                .map(|meta| meta.id.clone()),
+            _ => None,
+        }
+    }
+
+    /// find the entry `text` refers to. see the top of this file for what a reference can be
+    pub fn resolve_entry_ref(&self, text: &str) -> Result<EntryId, String> {
+        let entries = &self.master_index.entries;
+        match EntryRef::parse(text) {
+            EntryRef::Selected => self
+                .selected_entry_id()
+                .ok_or_else(|| String::from("no entry is selected")),
+            EntryRef::Position(n) => n
+                .checked_sub(1)
+                .and_then(|i| entries.get(i))
+                .map(|meta| meta.id.clone())
+                .ok_or_else(|| format!("no entry at position {}", n)),
+            EntryRef::Text(text) => {
+                if let Some(meta) = entries.iter().find(|meta| meta.id == text) {
+                    return Ok(meta.id.clone());
+                }
+                if text.len() >= MIN_ID_PREFIX {
+                    let by_id: Vec<usize> = (0..entries.len())
+                        .filter(|&i| entries[i].id.starts_with(&text))
+                        .collect();
+                    if !by_id.is_empty() {
+                        return self.pick_one(&text, &by_id);
+                    }
+                }
+                let ranks: Vec<(usize, u8)> = entries
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, meta)| match_rank(&meta.label, &text).map(|rank| (i, rank)))
+                    .collect();
+                let Some(best) = ranks.iter().map(|(_, rank)| *rank).min() else {
+                    return Err(format!("no entry matches '{}'", text));
+                };
+                let matches: Vec<usize> = ranks
+                    .into_iter()
+                    .filter(|(_, rank)| *rank == best)
+                    .map(|(i, _)| i)
+                    .collect();
+                self.pick_one(&text, &matches)
+            }
+        }
+    }
+
+    /// the one entry out of `matches` (indices into the master index), or an error listing
+    /// them by position so the user can pick one
+    fn pick_one(&self, text: &str, matches: &[usize]) -> Result<EntryId, String> {
+        let entries = &self.master_index.entries;
+        if let [i] = matches {
+            return Ok(entries[*i].id.clone());
+        }
+        let mut listed: Vec<String> = matches
+            .iter()
+            .take(AMBIGUOUS_LISTED)
+            .map(|&i| format!("{} '{}'", i + 1, entries[i].label))
+            .collect();
+        if matches.len() > AMBIGUOUS_LISTED {
+            listed.push(format!("{} more", matches.len() - AMBIGUOUS_LISTED));
+        }
+        Err(format!(
+            "'{}' matches {} entries: {}. use a position or more of the label",
+            text,
+            matches.len(),
+            listed.join(", ")
+        ))
+    }
+}
@@ -19,26 +19,33 @@
 
 /// this file includes a lot of methods on state, though methods on state are littered kind of all over this codebase
 // src/state/state.rs
-use crossterm::{
-    execute,
-    terminal::{self, Clear, ClearType},
-};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
-    io::{stdout, Write},
-    path::Path,
+    time::Instant,
 };
 
+use crate::state::{calendar::calendar::Calendar, editor::editor::Editor};
 use crate::util::{
+    backend::Backend,
     command_bar::CommandBar,
+    completion::Completion,
     config::Config,
+    constant::{MAX_SPLIT, MIN_SPLIT},
+    history::{History, HistorySearch},
+    hooks::{HookQueue, HOOK_WAIT},
+    keymap::{Action, KeyChord, Keymap},
+    message::{Level, Message},
     mode::ModeT,
+    parser::ParseError,
+    prompt::Prompt,
+    registers::Registers,
     term::DoubleBuffer,
-    util::{Entry, EntryMeta, MasterIndex},
+    theme::{ColorDepth, Theme},
+    util::{log_message, Entry, EntryMeta, MasterIndex},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct State {
     pub buffer: DoubleBuffer,
 
@@ -56,26 +63,126 @@ pub struct State {
 
     pub command_bar: CommandBar,
     pub command_mode: bool,
+    pub command_error: Option<ParseError>, // why the last command didn't parse, shown under it
+    pub completion: Option<Completion>,    // tab completion candidates for the command bar
+    pub history: History,                  // commands submitted, this session and before
+    pub history_search: Option<HistorySearch>, // a Ctrl+R search in progress
 
     pub idx: usize,       // selected index (based on master_index.entries order)
     pub idx_active: bool, // true if there are entries
+    pub scroll: usize,    // index of the first entry drawn in the list, follows idx
+
+    pub marked: HashSet<String>, // ids of the entries marked for a bulk command
+    pub mark_anchor: Option<usize>, // where a range mark started, until it's finished
+    pub filter: Option<String>,  // matching entries are highlighted and can be marked with *
+    pub preview_open: bool,      // the selected entry is shown next to the list when there's room
+    pub split_ratio: usize,      // the list's share of the width next to the preview, in percent
+
+    pub mouse: bool, // the mouse is captured, set from the config
+    pub last_click: Option<(Instant, usize, usize)>, // when and where, to spot a double-click
+    pub dragging_split: bool, // the divider between list and preview is being dragged
+
+    pub calendar: Calendar,
 
-    pub active_buffer: String, // theoretically this is the String buffer to be written to the current_entry on :w
-    // pub active_buffer_lines: Vec<String>,
+    pub editor: Editor, // the open entry's text, written to the current_entry on :w
     pub buffer_editable: bool,
 
+    pub link_history: Vec<String>, // ids of the entries we followed links from, most recent last
+    pub backlinks_open: bool,      // true while the backlinks pane is shown in READ mode
+    pub backlinks: Vec<String>,    // ids of the entries linking to the open one, found on open
+    pub backlinks_idx: usize,      // selected entry in the backlinks pane
+
+    pub prompt: Option<Prompt>, // a yes/no question waiting for an answer
+
+    pub help: Option<Vec<String>>, // lines of the help popup while it's open
+    pub help_scroll: usize,
+
+    pub message: Option<Message>, // feedback shown in the status line until it times out
+    pub hook_queue: HookQueue,    // hooks waiting to run, and finished ones for the event loop
+    pub hooks_running: usize,     // hooks started and not yet collected
+    pub quitting: bool,           // set by :quit, the event loop stops after this key
+    pub needs_redraw: bool,       // something changed since the last frame
+
+    pub keymap: Keymap,
+    pub theme: Theme,
+    pub pending_keys: Vec<KeyChord>, // the start of a key sequence, waiting for the rest
+    pub last_sequence_len: usize,    // how many keys made up the last action run
+
+    pub registers: Registers,
+    pub recording: Option<(char, Vec<KeyChord>)>, // the register being recorded and its keys
+    pub awaiting_register: Option<Action>,        // a macro action waiting for its register key
+    pub last_played: Option<char>,
+    pub macro_depth: usize, // how deep in replaying macros that replay macros we are
+
     pub dbg: bool,
 
     pub current_entry: Option<Entry>, // entry being edited (if any)
     pub current_entry_meta: Option<EntryMeta>, // corresponding metadata for the entry in edit mode
 }
 
+/// what a state reads from the config directory besides config.json
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub theme: Theme,
+    pub theme_problems: Vec<String>,
+    pub history: History,
+    pub registers: Registers,
+}
+
+impl Loaded {
+    pub fn load(config: &Config) -> Self {
+        let (theme, theme_problems) = Theme::load(&config.theme, ColorDepth::detect());
+        Self {
+            theme,
+            theme_problems,
+            history: History::load(),
+            registers: Registers::load(),
+        }
+    }
+}
+
 impl State {
     pub fn new(buffer: DoubleBuffer) -> Self {
         let config = Config::load().unwrap_or_default();
-        let n_fits: u32 = (buffer.height - 4) as u32;
         fs::create_dir_all(&config.entries_path)
             .expect("failed to create entries directory specified in config");
+        let loaded = Loaded::load(&config);
+        Self::with_config(buffer, config, loaded)
+    }
+
+    /// a state using `config` and `loaded` instead of reading them from the config directory
+    pub fn with_config(mut buffer: DoubleBuffer, config: Config, loaded: Loaded) -> Self {
+        let n_fits: u32 = buffer.height.saturating_sub(4) as u32;
+        let preview_open = config.preview;
+        let mouse = config.mouse;
+        let split_ratio = config.split_ratio.clamp(MIN_SPLIT, MAX_SPLIT);
+        let (keymap, keymap_problems) = Keymap::new(&config.keymap);
+        let Loaded {
+            theme,
+            theme_problems,
+            history,
+            registers,
+        } = loaded;
+        buffer.set_base(theme.text);
+        let problems: Vec<String> = keymap_problems
+            .iter()
+            .map(|problem| format!("keymap: {}", problem))
+            .chain(
+                theme_problems
+                    .iter()
+                    .map(|problem| format!("theme: {}", problem)),
+            )
+            .collect();
+        for problem in &problems {
+            log_message(problem);
+        }
+        let message = problems.first().map(|problem| {
+            let more = match problems.len() {
+                1 => String::new(),
+                n => format!(" (and {} more)", n - 1),
+            };
+            Message::new(Level::Warn, &format!("{}{}", problem, more))
+        });
         Self {
             buffer,
             mode: ModeT::BROWSE,
@@ -86,15 +193,47 @@ impl State {
             no_entry_flag: true,
             entries_map: HashMap::new(),
             master_index: MasterIndex::default(),
-            command_bar: CommandBar {
-                buffer: String::from("test buffer on state initialization"),
-                user_buffer: String::new(),
-            },
+            command_bar: CommandBar::default(),
             command_mode: false,
+            command_error: None,
+            completion: None,
+            history,
+            history_search: None,
             idx: 0,
             idx_active: false,
-            active_buffer: String::new(),
+            scroll: 0,
+            marked: HashSet::new(),
+            mark_anchor: None,
+            filter: None,
+            preview_open,
+            split_ratio,
+            mouse,
+            last_click: None,
+            dragging_split: false,
+            calendar: Calendar::default(),
+            editor: Editor::default(),
             buffer_editable: false,
+            link_history: Vec::new(),
+            backlinks_open: false,
+            backlinks: Vec::new(),
+            backlinks_idx: 0,
+            prompt: None,
+            help: None,
+            help_scroll: 0,
+            message,
+            hook_queue: HookQueue::default(),
+            hooks_running: 0,
+            quitting: false,
+            needs_redraw: true,
+            keymap,
+            theme,
+            pending_keys: Vec::new(),
+            last_sequence_len: 0,
+            registers,
+            recording: None,
+            awaiting_register: None,
+            last_played: None,
+            macro_depth: 0,
             dbg: true,
             current_entry: None,
             current_entry_meta: None,
@@ -103,13 +242,10 @@ impl State {
 
     /// initialize things in memory and on screen when State is instantiated. this includes
     /// setting some flags and initializing buffers and loading entries and other such bullshit.
-    pub fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut stdout = std::io::stdout();
-        let _ = execute!(stdout, terminal::EnterAlternateScreen);
-        let _ = execute!(stdout, crossterm::cursor::Hide);
+    pub fn init(&mut self, backend: &mut impl Backend) -> Result<(), Box<dyn std::error::Error>> {
+        backend.enter(self.mouse)?;
         self.buffer.clear();
-        self.buffer.flush(&mut stdout);
-        let _ = terminal::enable_raw_mode();
+        self.buffer.flush(backend)?;
 
         self.mode = ModeT::BROWSE;
         self.load_all_entries()?;
@@ -121,22 +257,43 @@ impl State {
 
         self.populate_string_buffer();
 
-        self.buffer.clear();
-        self.buffer.resize();
+        let (width, height) = backend.size()?;
+        self.buffer.resize(width, height);
         Ok(())
     }
 
-    /// this function is a sorry excuse for an attempt at cleaning up instead of just std::process::exit(0)
-    pub fn deconstruct(&mut self) {
-        // TODO do something about this
-        let mut stdout: std::io::Stdout = std::io::stdout();
-        let _ = execute!(stdout, terminal::LeaveAlternateScreen);
-        let _ = terminal::disable_raw_mode();
-        let _ = execute!(stdout, Clear(ClearType::All));
+    /// give the terminal back and let the hooks still queued finish
+    pub fn deconstruct(&mut self, backend: &mut impl Backend) {
+        if let Err(e) = backend.leave(self.mouse) {
+            log_message(&format!("failed to restore the terminal: {}", e));
+        }
+        self.finish_hooks();
+    }
+
+    /// give hooks that haven't run yet, like the on_save of a `:w` right before `:q`, the time
+    /// to finish. the terminal is back to normal by now, so what happens goes to stderr
+    fn finish_hooks(&mut self) {
+        if self.hooks_running > 0 {
+            eprintln!("waiting for hooks to finish");
+        }
+        if !self.hook_queue.finish(HOOK_WAIT) {
+            let text = format!("gave up on hooks still running after {:?}", HOOK_WAIT);
+            log_message(&text);
+            eprintln!("{}", text);
+        }
+        let results = match self.hook_queue.results.lock() {
+            Ok(mut results) => std::mem::take(&mut *results),
+            Err(_) => return,
+        };
+        for result in results {
+            if let Err(e) = result.outcome {
+                eprintln!("{} hook failed: {}", result.event, e);
+            }
+        }
     }
 
+    /// leave once the key that asked for it is handled
     pub fn quit(&mut self) {
-        self.deconstruct();
-        std::process::exit(0);
+        self.quitting = true;
     }
 }
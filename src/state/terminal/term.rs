@@ -17,321 +17,716 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crossterm::style::Color;
+use chrono::Datelike;
+use crossterm::style::Attribute;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::state::link::link::find_links;
 use crate::util::{
     constant::{
-        HORIZONTAL_LINE, LEFT_LOWER_SHOULDER, LEFT_UPPER_SHOULDER, NO_ENTRIES_WARNING,
-        RIGHT_LOWER_SHOULDER, RIGHT_UPPER_SHOULDER, TOO_SMALL_WARNING, VERTICAL_LINE,
+        DATE_FORMAT, EMPTY_ENTRY_WARNING, HORIZONTAL_LINE, MARK_MARKER, NO_ENTRIES_WARNING,
+        PENDING_MARK_MARKER, TOO_SMALL_WARNING,
     },
-    util::log_message,
+    layout::{Constraint, Direction, Padding, Rect},
+    markdown,
+    markup::{fenced, highlight, Markup},
+    message::Level,
+    mode::{ModeT, OpenMode},
+    text,
+    util::wrap_lines,
+    widget::{write_line, Input, Line, List, Popup, Scrollbar, Span, StatusBar, TextView, Widget},
 };
 
+/// the parts of the screen every mode shares
+#[derive(Debug, Clone, Copy)]
+pub struct Screen {
+    /// the whole terminal, the border goes around its edge
+    pub area: Rect,
+    /// the status line, just under the top border
+    pub status: Rect,
+    /// everything between the status line and the bottom border
+    pub body: Rect,
+}
+
 impl crate::state::state::State {
-    /// this function needs to be flushed.
-    pub fn write_line_horizontal(&mut self, x_0: usize, x: usize, y: usize) {
-        for i in x_0..=x {
-            self.buffer.write(i, y, HORIZONTAL_LINE)
-        }
+    pub fn screen(&self) -> Screen {
+        let area = Rect::new(0, 0, self.buffer.width, self.buffer.height);
+        let [status, body] = area.inner().split(
+            Direction::Vertical,
+            [Constraint::Length(1), Constraint::Fill],
+        );
+        Screen { area, status, body }
     }
 
-    pub fn write_colored_line_horizontal(
-        &mut self,
-        x_0: usize,
-        x: usize,
-        y: usize,
-        fg: Color,
-        bg: Color,
-    ) {
-        for i in x_0..=x {
-            self.buffer.write_colored(i, y, HORIZONTAL_LINE, fg, bg);
-        }
+    /// one line of text in the default style
+    fn plain(&self, text: impl Into<String>) -> Line {
+        vec![Span::new(text, self.theme.text)]
     }
 
-    pub fn write_line_vertical(&mut self, x: usize, y_0: usize, y: usize) {
-        for i in y_0..=y {
-            self.buffer.write(x, i, VERTICAL_LINE)
-        }
+    pub fn write_too_small_warning(&mut self) {
+        //        self.buffer.clear(); we do not need to do this
+        let area = self.screen().area.inner();
+        TextView::new(vec![self.plain(TOO_SMALL_WARNING)])
+            .centered()
+            .draw(&mut self.buffer, area);
     }
 
-    pub fn write_colored_line_vertical(
-        &mut self,
-        x: usize,
-        y_0: usize,
-        y: usize,
-        fg: Color,
-        bg: Color,
-    ) {
-        for i in y_0..=y {
-            self.buffer.write_colored(x, i, VERTICAL_LINE, fg, bg)
-        }
+    pub fn write_no_entries_warning(&mut self) {
+        let body = self.screen().body;
+        TextView::new(vec![self.plain(NO_ENTRIES_WARNING)])
+            .centered()
+            .draw(&mut self.buffer, body);
     }
 
-    pub fn write_rectangle(&mut self, x_0: usize, x: usize, y_0: usize, y: usize) {
-        self.buffer.write(x_0, y_0, LEFT_UPPER_SHOULDER);
-        self.buffer.write(x, y_0, RIGHT_UPPER_SHOULDER);
-        self.buffer.write(x_0, y, LEFT_LOWER_SHOULDER);
-        self.buffer.write(x, y, RIGHT_LOWER_SHOULDER);
+    /// draws the border around the screen, with the open entry's title in the top edge and
+    /// "N of M" for the selected entry in the bottom one
+    pub fn write_border(&mut self) {
+        let mut border = Popup::new(self.theme.border);
+        if !self.buffer.too_small_flag {
+            match self.mode {
+                ModeT::OPEN(_) => border = border.title(self.entry_title()),
+                ModeT::BROWSE if !self.master_index.entries.is_empty() => {
+                    let position =
+                        format!(" {} of {} ", self.idx + 1, self.master_index.entries.len());
+                    border = border.footer(self.plain(position));
+                }
+                _ => {}
+            }
+        }
+        let area = self.screen().area;
+        border.draw(&mut self.buffer, area);
+    }
 
-        self.write_line_horizontal(x_0 + 1, x - 1, y_0);
-        self.write_line_horizontal(x_0 + 1, x - 1, y);
-        self.write_line_vertical(x_0, y_0 + 1, y - 1);
-        self.write_line_vertical(x, y_0 + 1, y - 1);
+    /// the open entry's label and date, then its tags
+    fn entry_title(&self) -> Line {
+        let Some(entry) = &self.current_entry else {
+            return Vec::new();
+        };
+        let title = format!(" {} {} {} ", entry.label, HORIZONTAL_LINE, entry.date);
+        let tags: String = entry.tags.iter().map(|tag| format!("#{} ", tag)).collect();
+        vec![
+            Span::new(title, self.theme.text),
+            Span::new(tags, self.theme.tag),
+        ]
     }
 
-    pub fn write_colored_rectangle(
-        &mut self,
-        x_0: usize,
-        x: usize,
-        y_0: usize,
-        y: usize,
-        fg: Color,
-        bg: Color,
-    ) {
-        self.buffer
-            .write_colored(x_0, y_0, LEFT_UPPER_SHOULDER, fg, bg);
-        self.buffer
-            .write_colored(x, y_0, RIGHT_UPPER_SHOULDER, fg, bg);
-        self.buffer
-            .write_colored(x_0, y, LEFT_LOWER_SHOULDER, fg, bg);
-        self.buffer
-            .write_colored(x, y, RIGHT_LOWER_SHOULDER, fg, bg);
-
-        self.write_colored_line_horizontal(x_0 + 1, x - 1, y_0, fg, bg);
-        self.write_colored_line_horizontal(x_0 + 1, x - 1, y, fg, bg);
-        self.write_colored_line_vertical(x_0, y_0 + 1, y - 1, fg, bg);
-        self.write_colored_line_vertical(x, y_0 + 1, y - 1, fg, bg);
+    /// draws the status line: mode, notebook, open entry and where the cursor is, with the
+    /// latest message in between
+    pub fn write_status_line(&mut self) {
+        let label = match (&self.current_entry, &self.mode) {
+            (Some(entry), ModeT::OPEN(_)) => {
+                let dirty = if self.editor.dirty { " [+]" } else { "" };
+                format!(" {}{} ", entry.label, dirty)
+            }
+            _ => String::new(),
+        };
+        let position = match self.mode {
+            ModeT::OPEN(_) => format!(" ln {}, col {} ", self.editor.row + 1, self.editor.col + 1),
+            ModeT::BROWSE if self.idx_active => {
+                format!(" {}/{} ", self.idx + 1, self.master_index.entries.len())
+            }
+            ModeT::CALENDAR => format!(" {} ", self.calendar.selected.format(DATE_FORMAT)),
+            _ => String::new(),
+        };
+        let mode = match &self.mode {
+            ModeT::OPEN(open_mode) => open_mode.to_string(),
+            mode => mode.to_string(),
+        };
+        let recording = match &self.recording {
+            Some((register, _)) => format!(" recording @{} ", register),
+            None => String::new(),
+        };
+        let segments = vec![
+            Span::new(format!(" {} ", mode), self.theme.status_mode),
+            Span::new(
+                format!(" {} ", self.config.notebook_name()),
+                self.theme.status_notebook,
+            ),
+            Span::new(label, self.theme.status),
+            Span::new(recording, self.theme.error.with(Attribute::Reverse)),
+        ];
+        let message = self.message.as_ref().map(|message| {
+            let style = match message.level {
+                Level::Info => self.theme.status,
+                Level::Warn => self.theme.warning.with(Attribute::Reverse),
+                Level::Error => self.theme.error.with(Attribute::Reverse),
+            };
+            Span::new(format!(" {} ", message.text), style)
+        });
+
+        let area = self.screen().status;
+        StatusBar::new(self.theme.status, segments)
+            .message(message)
+            .right(Span::new(position, self.theme.status_mode))
+            .draw(&mut self.buffer, area);
     }
-    pub fn write_char_horizontal(&mut self, x_0: usize, x: usize, y: usize, ch: char) {
-        for i in x_0..=x {
-            self.buffer.write(i, y, ch)
+
+    /// draws the command bar in a box in the middle of the screen, with a parse error or the
+    /// tab completions underneath
+    pub fn write_command_window(&mut self) {
+        let Screen { area, body, .. } = self.screen();
+        let window_width = area.width as u32 / 2;
+        let mut str = self.command_bar.stringify(window_width);
+        let mut cursor = self
+            .command_bar
+            .column(self.command_bar.cursor, window_width);
+        let n = text::width(&str);
+        // during a Ctrl+R search the window shows the search instead of the input
+        if let Some(search) = &self.history_search {
+            let found = search
+                .found
+                .map_or("", |found| self.history.entries[found].as_str());
+            str = format!("(reverse-i-search)`{}': {}", search.query, found);
+            cursor = None;
         }
-    }
 
-    // i dont think either of the above or below methods r ever getting used ever.
+        let window = area.centered(n + 2, 3);
+        let input_area = window.inner();
+        Popup::new(self.theme.popup).draw(&mut self.buffer, window);
+        let mut input = Input::new(&str, self.theme.command);
+        if let Some(cursor) = cursor {
+            let ch = self
+                .command_bar
+                .buffer
+                .chars()
+                .nth(self.command_bar.cursor)
+                .unwrap_or(' ');
+            input = input.cursor(cursor, ch, self.theme.command.with(Attribute::Reverse));
+        }
+        input.draw(&mut self.buffer, input_area);
 
-    pub fn write_char_vertical(&mut self, x: usize, y_0: usize, y: usize, ch: char) {
-        for i in y_0..=y {
-            self.buffer.write(x, i, ch)
+        let below = Rect::new(input_area.x, window.bottom(), body.width, 1).intersection(body);
+        // a parse error points at the offending column from the bottom edge, then explains below
+        if let Some(error) = &self.command_error {
+            if let Some(column) = self.command_bar.column(error.pos, window_width) {
+                let edge = window.row(2);
+                let caret = Rect::new(input_area.x + column, edge.y, 1, edge.height);
+                write_line(
+                    &mut self.buffer,
+                    caret.intersection(window),
+                    &[Span::new("^", self.theme.error)],
+                );
+            }
+            let message = vec![Span::new(error.to_string(), self.theme.error)];
+            write_line(&mut self.buffer, below, &message);
+        } else {
+            let row = Rect {
+                y: below.y,
+                height: below.height,
+                ..body.pad(Padding::horizontal(1))
+            };
+            self.write_completions(row);
         }
     }
-    pub fn write_str_at(&mut self, x: usize, y: usize, str: &str) {
-        for (i, ch) in str.char_indices() {
-            self.buffer.write(x + i, y, ch);
+
+    /// lists the tab completion candidates in `area`, the current one highlighted
+    pub fn write_completions(&mut self, area: Rect) {
+        let Some(completion) = &self.completion else {
+            return;
+        };
+        if completion.candidates.len() < 2 {
+            return;
         }
-    }
-    pub fn write_colored_str_at(&mut self, x: usize, y: usize, str: &str, fg: Color, bg: Color) {
-        for (i, ch) in str.char_indices() {
-            self.buffer.write_colored(x + i, y, ch, fg, bg)
+        let mut line = Vec::new();
+        let mut x = 0;
+        for (i, candidate) in completion.candidates.iter().enumerate() {
+            let n = text::width(candidate);
+            if x + n + 1 > area.width {
+                line.push(Span::new("..", self.theme.muted));
+                break;
+            }
+            let style = if i == completion.idx {
+                self.theme.selection
+            } else {
+                self.theme.command
+            };
+            line.push(Span::new(candidate.as_str(), style));
+            line.push(Span::new(" ", self.theme.command));
+            x += n + 1;
         }
+        write_line(&mut self.buffer, area, &line);
     }
-    pub fn write_too_small_warning(&mut self) {
-        //        self.buffer.clear(); we do not need to do this
-        self.write_rectangle(0, self.buffer.width - 1, 0, self.buffer.height - 1);
-        self.write_str_at(
-            (self.buffer.width / 2) - (TOO_SMALL_WARNING.len() / 2),
-            self.buffer.height / 2,
-            TOO_SMALL_WARNING,
-        );
-    }
-    pub fn write_no_entries_warning(&mut self) {
-        self.write_rectangle(0, self.buffer.width - 1, 0, self.buffer.height - 1);
-        self.write_str_at(
-            (self.buffer.width / 2) - (NO_ENTRIES_WARNING.len() / 2),
-            self.buffer.height / 2,
-            NO_ENTRIES_WARNING,
-        );
-    }
 
-    pub fn write_command_bar(&mut self) {
-        let str = self.command_bar.stringify(self.buffer.width as u32);
-        self.write_colored_str_at(1, 1, &str, Color::Black, Color::White)
+    /// draws the help popup in the middle of the screen
+    pub fn write_help(&mut self) {
+        let Some(help) = &self.help else {
+            return;
+        };
+        let area = self.screen().area;
+        let longest = help.iter().map(|line| text::width(line)).max().unwrap_or(0);
+        let width = (longest + 4).min(area.width.saturating_sub(4));
+        let lines: Vec<Line> = help
+            .iter()
+            .flat_map(
+                |line| match wrap_lines(line, width.saturating_sub(4).max(1)) {
+                    wrapped if wrapped.is_empty() => vec![String::new()],
+                    wrapped => wrapped,
+                },
+            )
+            .map(|line| self.plain(line))
+            .collect();
+        let height = (lines.len() + 2).min(area.height.saturating_sub(4));
+        if width < 8 || height < 3 {
+            return;
+        }
+        let n_visible = height - 2;
+        let total = lines.len();
+        self.help_scroll = self.help_scroll.min(total.saturating_sub(n_visible));
+
+        let window = area.centered(width, height);
+        let mut popup = Popup::new(self.theme.popup)
+            .title(vec![Span::new(" help ", self.theme.popup)])
+            .fill(self.theme.text);
+        if total > n_visible {
+            let position = format!(" {}/{} ", self.help_scroll + n_visible, total);
+            popup = popup.footer(vec![Span::new(position, self.theme.popup)]);
+        }
+        popup.draw(&mut self.buffer, window);
+        TextView::new(lines)
+            .scroll(self.help_scroll)
+            .draw(&mut self.buffer, window.inner().pad(Padding::horizontal(1)));
     }
 
-    pub fn write_command_window(&mut self) {
-        let str = self.command_bar.stringify(self.buffer.width as u32 / 2);
-        // this should be buffered
-        let n = str.len();
-        let effective_width = n + 2; // one on each side
-        self.write_colored_rectangle(
-            (self.buffer.width / 2) - (effective_width / 2),
-            (self.buffer.width / 2) + (effective_width / 2),
-            (self.buffer.height / 2) - 1,
-            (self.buffer.height / 2) + 1,
-            Color::Cyan,
-            Color::Black,
-        );
-        self.write_colored_str_at(
-            (self.buffer.width / 2) - (effective_width / 2) + 1,
-            self.buffer.height / 2,
-            &str,
-            Color::Green,
-            Color::Black,
+    /// where the entry list and the preview of the selected entry go in BROWSE. the preview
+    /// only gets room when it's open and the terminal is wide enough to split. the column right
+    /// of the list is for the scrollbar, on the border or between the two
+    pub fn browse_panes(&self) -> (Rect, Option<Rect>) {
+        let body = self.screen().body;
+        if !self.preview_open || self.buffer.narrow_flag || self.master_index.entries.is_empty() {
+            return (body, None);
+        }
+        let [list, _, preview] = body.split(
+            Direction::Horizontal,
+            [
+                Constraint::Percent(self.split_ratio),
+                Constraint::Length(1),
+                Constraint::Fill,
+            ],
         );
+        (list, Some(preview))
     }
 
     pub fn write_loaded_entries(&mut self) {
-        let num_entries = self.entries_map.len();
-        let mut idx: u32 = 0;
-        let max_idx: u32 = self.buffer.height as u32 - 4;
-
-        // log_message(&format!("self.master_index: {:?\n}", self.master_index));
-
-        if num_entries > 0 {
-            for i in 0..=std::cmp::min(self.n_fits, num_entries as u32) - 1 {
-                if i < max_idx && !i > self.master_index.entries.len() as u32 {
-                    let default_entry = crate::util::util::Entry {
-                        label: String::from("IT DIDNT WORK!"),
-                        date: String::from("Da/te/Da-Ho:ur:Ho"),
-                        content: String::from("this is some content"),
-                        id: String::from("this is an ID"),
-                        is_dirty: false,
-                    }; // TODO do something about this abomination
-
-                    let entry_meta = self.master_index.entries.get(i as usize).unwrap();
-                    let entry = self
-                        .entries_map
-                        .get(&entry_meta.id)
-                        .unwrap_or(&default_entry);
-
-                    let entry_string = if idx == self.idx as u32 {
-                        format!("> {}", entry.selected_stringify(self.buffer.width))
-                    } else {
-                        entry.stringify(self.buffer.width)
-                    };
-
-                    self.write_str_at(2, idx as usize + 2, &entry_string);
-                    self.buffer.write_colored(
-                        self.buffer.width - 1,
-                        idx as usize + 2,
-                        VERTICAL_LINE,
-                        Color::White,
-                        Color::Black,
-                    ); // dirty fucking hackc but should work
-                       // lol it worked
-                    idx += 1;
-                }
-            }
-        } else {
+        let (body, _) = self.browse_panes();
+        let num_entries = self.master_index.entries.len();
+        if num_entries == 0 {
             self.no_entry_flag = true;
             self.write_no_entries_warning();
+            return;
+        }
+
+        self.follow_selection();
+        let mut items = Vec::new();
+        for i in (self.scroll..num_entries).take(body.height) {
+            let entry_meta = &self.master_index.entries[i];
+            let Some(entry) = self.entries_map.get(&entry_meta.id) else {
+                items.push(Vec::new());
+                continue;
+            };
+            // the closing line of a row falls on the right border, so it gets cut off
+            let entry_string = entry.stringify(body.width + 2, entry_meta.pinned);
+
+            // marked entries get a marker in the gutter, filter matches are highlighted
+            let (marker, style) = if self.marked.contains(&entry_meta.id) {
+                (MARK_MARKER, self.theme.mark)
+            } else if self.in_pending_range(i) {
+                (PENDING_MARK_MARKER, self.theme.mark)
+            } else if self.is_filter_match(i) {
+                (' ', self.theme.matched)
+            } else {
+                (' ', self.theme.text)
+            };
+            // the selected entry is drawn in reverse video, keeping its mark or match color
+            let row_style = if i != self.idx {
+                style
+            } else if style == self.theme.text {
+                self.theme.selection
+            } else {
+                style.with(Attribute::Reverse)
+            };
+            items.push(vec![
+                Span::new(marker.to_string(), style),
+                Span::new(entry_string, row_style),
+            ]);
+        }
+        List::new(items).draw(&mut self.buffer, body);
+    }
+
+    /// draws a scrollbar over the right border alongside the entry list, so it has to go after
+    /// the border itself. the thumb is only shown when the list doesn't fit on screen
+    pub fn write_scrollbar(&mut self) {
+        let (body, _) = self.browse_panes();
+        let column = Rect::new(body.right(), body.y, 1, body.height);
+        Scrollbar::new(
+            self.master_index.entries.len(),
+            body.height,
+            self.scroll,
+            self.theme.border,
+        )
+        .draw(&mut self.buffer, column);
+    }
+
+    /// chars `start..end` of a line of source being edited, highlighted as markdown. `in_code`
+    /// is for lines of a fenced code block, which are left as they are, and the `selected`
+    /// chars are shown as such over everything else
+    fn highlighted_row(
+        &self,
+        line: &str,
+        start: usize,
+        end: usize,
+        in_code: bool,
+        selected: Option<Range<usize>>,
+    ) -> Line {
+        let links = if in_code {
+            Vec::new()
+        } else {
+            find_links(line)
+        };
+        let highlights = highlight(line, in_code);
+        let byte = |col: usize| line.char_indices().nth(col).map_or(line.len(), |(i, _)| i);
+        let mut col = start;
+        let mut spans: Line = Vec::new();
+        for grapheme in line[byte(start)..byte(end)].graphemes(true) {
+            let mut style = self.theme.text;
+            for highlight in highlights.iter().filter(|h| h.start <= col && col < h.end) {
+                style = match highlight.markup {
+                    Markup::Heading(attrs) => self.theme.heading.with_all(attrs),
+                    Markup::Emphasis(attrs) => style.with_all(attrs),
+                    Markup::Code => self.theme.code,
+                    Markup::Bullet => self.theme.bullet,
+                    Markup::Quote => self.theme.quote,
+                    Markup::Rule => self.theme.muted,
+                };
+            }
+            if links.iter().any(|l| l.start <= col && col < l.end) {
+                style = self.theme.link.with_all(style.attrs);
+            }
+            if selected
+                .as_ref()
+                .is_some_and(|selected| selected.contains(&col))
+            {
+                style = self.theme.selection;
+            }
+            match spans.last_mut() {
+                Some(last) if last.style == style => last.text.push_str(grapheme),
+                _ => spans.push(Span::new(grapheme, style)),
+            }
+            col += grapheme.chars().count();
+        }
+        spans
+    }
+
+    /// draws the selected entry's text into the pane next to the list, read only and from the
+    /// top, styled as it would be in READ
+    pub fn write_preview(&mut self) {
+        let (_, Some(area)) = self.browse_panes() else {
+            return;
+        };
+        let area = area.pad(Padding::horizontal(1));
+        let Some(entry) = self
+            .master_index
+            .entries
+            .get(self.idx)
+            .and_then(|meta| self.entries_map.get(&meta.id))
+        else {
+            return;
+        };
+        if entry.content.is_empty() {
+            TextView::new(vec![self.plain(EMPTY_ENTRY_WARNING)])
+                .centered()
+                .draw(&mut self.buffer, area);
+            return;
         }
+
+        let lines = markdown::render(&entry.content, area.width, &self.theme)
+            .into_iter()
+            .take(area.height)
+            .map(|row| row.line)
+            .collect();
+        TextView::new(lines).draw(&mut self.buffer, area);
+    }
+
+    /// where the open entry's text goes and where the backlinks pane goes, which is empty
+    /// while it's closed
+    pub fn open_panes(&self) -> (Rect, Rect) {
+        let body = self.screen().body;
+        let [text_area, pane] = if self.backlinks_open {
+            body.split(
+                Direction::Horizontal,
+                [
+                    Constraint::Fill,
+                    Constraint::Length(self.backlinks_pane_width() + 1),
+                ],
+            )
+        } else {
+            [body, Rect::default()]
+        };
+        (text_area.pad(Padding::horizontal(1)), pane)
     }
 
+    /// draws the open entry's text, wrapped to the screen, with the cursor and any links
+    /// highlighted. leaves room on the right for the backlinks pane when it is open
     pub fn write_active_buffer(&mut self) {
-        let effective_width = self.buffer.width - 2;
-        let x_0 = 2;
-        let x = self.buffer.width - 2;
-        let y_0 = 2;
-        let y = self.buffer.height - 2;
-
-        let s = String::new();
-
-        if !self.active_buffer.is_empty() {
-            let str: &str = "todo: write_active_buffer()";
-
-            self.write_colored_str_at(
-                (self.buffer.width / 2) - str.len() / 2,
-                self.buffer.height / 2,
-                str,
-                Color::Red,
-                Color::Black,
-            );
-            //for ()
-            /*
-            let mut lines: Vec<&str> = self.active_buffer.lines().collect();
-            for (i, line) in lines.iter_mut().enumerate() {
-                if line.len() >= effective_width {
-                    let split: (&str, &str) = line.split_at(effective_width - 1);
-                    let editable_line = lines.get_mut(i + 1).unwrap_or({
-                        &mut s.as_str()
+        let (text_area, pane) = self.open_panes();
+        if self.editor.is_empty() && self.mode == ModeT::OPEN(OpenMode::READ) {
+            TextView::new(vec![self.plain(EMPTY_ENTRY_WARNING)])
+                .centered()
+                .draw(&mut self.buffer, text_area);
+        }
+
+        let (width, height) = (text_area.width, text_area.height);
+        // READ shows the entry rendered as markdown and EDIT the source with some highlighting.
+        // the cursor is in the source either way, READ shows it where its text ended up
+        let (lines, cursor_row, cursor_x, grapheme): (Vec<Line>, _, _, _) =
+            if self.mode == ModeT::OPEN(OpenMode::READ) {
+                let rows = markdown::render(&self.editor.text(), width, &self.theme);
+                let (cursor_row, cursor_x) = markdown::locate(&rows, self.editor.offset());
+                let grapheme = rows
+                    .get(cursor_row)
+                    .map_or(" ", |row| row.grapheme_at(cursor_x))
+                    .to_string();
+                self.editor.scroll_to(cursor_row, height);
+                let selected = self.editor.selected_offsets();
+                let lines = rows
+                    .into_iter()
+                    .skip(self.editor.scroll)
+                    .take(height)
+                    .map(|mut row| {
+                        if let Some(range) = &selected {
+                            row.select(range, self.theme.selection);
+                        }
+                        row.line
                     })
-                }
+                    .collect();
+                (lines, cursor_row, cursor_x, grapheme)
+            } else {
+                self.editor.follow_cursor(width, height);
+                let (cursor_row, cursor_x) = self.editor.cursor_position(width);
+                let fenced = fenced(&self.editor.lines);
+                let lines = self
+                    .editor
+                    .display_rows(width)
+                    .iter()
+                    .skip(self.editor.scroll)
+                    .take(height)
+                    .map(|row| {
+                        let line = &self.editor.lines[row.row];
+                        let selected = self.editor.selected_cols(row.row);
+                        self.highlighted_row(line, row.start, row.end, fenced[row.row], selected)
+                    })
+                    .collect();
+                let line = &self.editor.lines[self.editor.row];
+                let i = self.editor.byte_index(self.editor.row, self.editor.col);
+                let grapheme = line[i..].graphemes(true).next().unwrap_or(" ");
+                (lines, cursor_row, cursor_x, grapheme.to_string())
+            };
+        TextView::new(lines).draw(&mut self.buffer, text_area);
+
+        if cursor_row >= self.editor.scroll && cursor_row < self.editor.scroll + height {
+            let x = std::cmp::min(cursor_x, width.saturating_sub(1));
+            let row = text_area.row(cursor_row - self.editor.scroll);
+            let cell = Rect::new(row.x + x, row.y, width - x, row.height);
+            write_line(
+                &mut self.buffer,
+                cell,
+                &[Span::new(grapheme, self.theme.cursor)],
+            );
+        }
 
-            } */
+        if self.backlinks_open {
+            self.write_backlinks_pane(pane);
         }
     }
 
-    pub fn write_debug_info(&mut self) {
-        let str_start_x = self.buffer.width - 20;
-        let str_start_y = self.buffer.height - 12;
-
-        self.write_colored_rectangle(
-            self.buffer.width - 21,
-            self.buffer.width - 2,
-            self.buffer.height - 13,
-            self.buffer.height - 2,
-            Color::Yellow,
-            Color::Black,
+    pub fn backlinks_pane_width(&self) -> usize {
+        std::cmp::min(32, self.buffer.width / 3)
+    }
+
+    /// draws the list of entries linking to the open one into `area`, down the right side of
+    /// the screen
+    pub fn write_backlinks_pane(&mut self, area: Rect) {
+        Popup::new(self.theme.popup)
+            .title(vec![Span::new(" backlinks ", self.theme.popup)])
+            .fill(self.theme.text)
+            .draw(&mut self.buffer, area);
+        let inner = area.inner();
+
+        if self.backlinks.is_empty() {
+            TextView::new(vec![self.plain("nothing links here")])
+                .draw(&mut self.buffer, inner.pad(Padding::horizontal(1)));
+        }
+        let items = self
+            .backlinks
+            .iter()
+            .map(|id| {
+                let label = self
+                    .entries_map
+                    .get(id)
+                    .map_or(String::new(), |entry| entry.label.clone());
+                self.plain(label)
+            })
+            .collect();
+        List::new(items)
+            .select(Some(self.backlinks_idx))
+            .marker("> ")
+            .draw(&mut self.buffer, inner);
+    }
+
+    /// draws the prompt waiting for an answer in a box in the middle of the screen
+    pub fn write_prompt(&mut self) {
+        let Some(prompt) = &self.prompt else {
+            return;
+        };
+        let question = vec![Span::new(prompt.question.as_str(), self.theme.warning)];
+        let window = self
+            .screen()
+            .area
+            .centered(text::width(&prompt.question) + 4, 3);
+        Popup::new(self.theme.warning)
+            .fill(self.theme.warning)
+            .draw(&mut self.buffer, window);
+        TextView::new(vec![question])
+            .draw(&mut self.buffer, window.inner().pad(Padding::horizontal(1)));
+    }
+
+    /// draws the month grid of the selected day, plus the entries written on that day
+    pub fn write_calendar(&mut self) {
+        const CELL_WIDTH: usize = 4;
+        let grid_width = CELL_WIDTH * 7;
+        let body = self.screen().body.pad(Padding::horizontal(1));
+        let [grid, _, list] = body.split(
+            Direction::Horizontal,
+            [
+                Constraint::Length(grid_width + 2),
+                Constraint::Length(2),
+                Constraint::Fill,
+            ],
         );
-        self.write_colored_str_at(str_start_x, str_start_y, "DBG!", Color::Red, Color::Black);
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 1,
-            &format!("(w, h): ({}, {})", self.buffer.width, self.buffer.height),
-            Color::Green,
-            Color::Black,
+        let [grid, _] = grid.split(
+            Direction::Vertical,
+            [Constraint::Length(11), Constraint::Fill],
         );
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 2,
-            &format!("mode: {}", self.mode),
-            Color::Green,
-            Color::Black,
+
+        let calendar = self.calendar.clone();
+        let month_start = calendar.month_start();
+        let today = chrono::Local::now().date_naive();
+
+        Popup::new(self.theme.popup).draw(&mut self.buffer, grid);
+        let inner = grid.inner();
+        let title = month_start.format("%B %Y").to_string();
+        TextView::new(vec![self.plain(title)])
+            .centered()
+            .draw(&mut self.buffer, inner.row(0));
+        let weekdays: Line = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]
+            .iter()
+            .flat_map(|day| {
+                [
+                    Span::new(" ", self.theme.text),
+                    Span::new(*day, self.theme.heading),
+                    Span::new(" ", self.theme.text),
+                ]
+            })
+            .collect();
+        write_line(&mut self.buffer, inner.row(1), &weekdays);
+        let rule = HORIZONTAL_LINE.to_string().repeat(inner.width);
+        write_line(
+            &mut self.buffer,
+            inner.row(2),
+            &[Span::new(rule, self.theme.popup)],
         );
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 3,
-            &format!("cmd: {}", self.command_mode),
-            Color::Green,
-            Color::Black,
+
+        let offset = month_start.weekday().num_days_from_monday() as usize;
+        for day in 1..=calendar.days_in_month() {
+            let date = month_start.with_day(day).unwrap();
+            let cell = offset + day as usize - 1;
+            let row = inner.row(3 + cell / 7);
+            let area = Rect::new(
+                row.x + (cell % 7) * CELL_WIDTH,
+                row.y,
+                CELL_WIDTH,
+                row.height,
+            );
+
+            let has_entries = !self.entries_on(date).is_empty();
+            let style = if date == calendar.selected {
+                self.theme.selection
+            } else if has_entries {
+                self.theme.tag.with(Attribute::Reverse)
+            } else if date == today {
+                self.theme.warning
+            } else {
+                self.theme.text
+            };
+            let label = vec![Span::new(format!(" {:>2} ", day), style)];
+            write_line(&mut self.buffer, area.intersection(inner), &label);
+        }
+
+        // the selected day's entries, to the right of the grid
+        let list = list.pad(Padding {
+            top: 1,
+            ..Padding::default()
+        });
+        let heading = format!("{}:", calendar.selected.format(DATE_FORMAT));
+        write_line(
+            &mut self.buffer,
+            list.row(0),
+            &[Span::new(heading, self.theme.heading)],
         );
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 4,
-            &format!("nld: {}", self.entries_map.len()),
-            Color::Green,
-            Color::Black,
+        let [_, entries] = list.split(
+            Direction::Vertical,
+            [Constraint::Length(2), Constraint::Fill],
         );
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 5,
-            &format!("idx: {}", self.idx),
-            Color::Green,
-            Color::Black,
+
+        let on_day = self.entries_on(calendar.selected);
+        if on_day.is_empty() {
+            TextView::new(vec![self.plain("no entries")]).draw(&mut self.buffer, entries);
+        }
+        let items = on_day
+            .iter()
+            .map(|&pos| self.plain(self.master_index.entries[pos].label.as_str()))
+            .collect();
+        List::new(items)
+            .select(calendar.day_focus.then_some(calendar.day_idx))
+            .marker("> ")
+            .draw(&mut self.buffer, entries);
+    }
+
+    /// draws what's going on inside into the bottom right corner
+    pub fn write_debug_info(&mut self) {
+        let body = self.screen().body;
+        let [_, rows] = body.split(
+            Direction::Vertical,
+            [Constraint::Fill, Constraint::Length(12)],
         );
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 6,
-            &format!("dxa: {}", self.idx_active),
-            Color::Green,
-            Color::Black,
+        let [_, area] = rows.split(
+            Direction::Horizontal,
+            [Constraint::Fill, Constraint::Length(20)],
         );
 
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 7,
-            &format!(
+        let info = [
+            format!("(w, h): ({}, {})", self.buffer.width, self.buffer.height),
+            format!("mode: {}", self.mode),
+            format!("cmd: {}", self.command_mode),
+            format!("nld: {}", self.entries_map.len()),
+            format!("idx: {}", self.idx),
+            format!("dxa: {}", self.idx_active),
+            format!(
                 "bn6: {}",
-                self.active_buffer.chars().take(6).collect::<String>()
+                self.editor.text().chars().take(6).collect::<String>()
             ),
-            Color::Green,
-            Color::Black,
+            format!("bed: {}", self.buffer_editable),
+            format!("mindex: {}", self.master_index.entries.len()),
+        ];
+        let mut lines = vec![vec![Span::new("DBG!", self.theme.error)]];
+        lines.extend(
+            info.into_iter()
+                .map(|line| vec![Span::new(line, self.theme.command)]),
         );
 
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 8,
-            &format!("bed: {}", self.buffer_editable),
-            Color::Green,
-            Color::Black,
-        );
-        self.write_colored_str_at(
-            str_start_x,
-            str_start_y + 9,
-            &format!("mindex: {}", self.master_index.entries.len()),
-            Color::Green,
-            Color::Black,
-        );
+        Popup::new(self.theme.warning)
+            .fill(self.theme.text)
+            .draw(&mut self.buffer, area);
+        TextView::new(lines).draw(&mut self.buffer, area.inner());
     }
 }
@@ -0,0 +1,216 @@
+/*
+ * src/util/backend.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// where finished frames go: the real terminal, or a grid in memory so drawing can be tested
+/// without one. `DoubleBuffer::flush` only hands over the cells that changed
+use crossterm::{
+    cursor,
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute, queue,
+    style::{
+        Attribute, Print, ResetColor, SetAttribute, SetAttributes, SetBackgroundColor,
+        SetForegroundColor,
+    },
+    terminal,
+};
+use std::io::{self, Write};
+
+use crate::util::term::Cell;
+
+pub trait Backend {
+    /// (width, height) in cells
+    fn size(&self) -> io::Result<(usize, usize)>;
+    /// put `cells` on row `y`, the first one at column `x`
+    fn draw(&mut self, x: usize, y: usize, cells: &[Cell]) -> io::Result<()>;
+    /// everything drawn so far shows up
+    fn flush(&mut self) -> io::Result<()>;
+    /// take the terminal over: raw mode, the alternate screen, no cursor, and mouse reports if
+    /// `mouse` is set
+    fn enter(&mut self, mouse: bool) -> io::Result<()>;
+    /// hand the terminal back the way `enter` found it
+    fn leave(&mut self, mouse: bool) -> io::Result<()>;
+    /// blank the screen and put the cursor in the top left corner
+    fn clear(&mut self) -> io::Result<()>;
+}
+
+/// the terminal, through crossterm. a row of cells goes out as one cursor move and one write,
+/// and colors are only set when they change
+pub struct CrosstermBackend<W: Write> {
+    out: W,
+    /// colors and attributes last sent, none at the start of a frame
+    pen: Option<Cell>,
+}
+
+impl<W: Write> CrosstermBackend<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, pen: None }
+    }
+}
+
+impl<W: Write> Backend for CrosstermBackend<W> {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        let (width, height) = terminal::size()?;
+        Ok((width as usize, height as usize))
+    }
+
+    fn draw(&mut self, x: usize, y: usize, cells: &[Cell]) -> io::Result<()> {
+        queue!(self.out, cursor::MoveTo(x as u16, y as u16))?;
+        let mut run = String::new();
+        for cell in cells {
+            if self.pen.is_none_or(|pen| !same_style(&pen, cell)) {
+                if !run.is_empty() {
+                    queue!(self.out, Print(&run))?;
+                    run.clear();
+                }
+                set_style(&mut self.out, self.pen.as_ref(), cell)?;
+                self.pen = Some(*cell);
+            }
+            run.push_str(cell.symbol.as_str());
+        }
+        queue!(self.out, Print(&run))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        queue!(self.out, SetAttribute(Attribute::Reset), ResetColor)?;
+        self.pen = None;
+        self.out.flush()
+    }
+
+    fn enter(&mut self, mouse: bool) -> io::Result<()> {
+        execute!(self.out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        if mouse {
+            execute!(self.out, EnableMouseCapture)?;
+        }
+        terminal::enable_raw_mode()
+    }
+
+    fn leave(&mut self, mouse: bool) -> io::Result<()> {
+        if mouse {
+            execute!(self.out, DisableMouseCapture)?;
+        }
+        execute!(self.out, terminal::LeaveAlternateScreen, cursor::Show)?;
+        terminal::disable_raw_mode()?;
+        self.clear()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        execute!(
+            self.out,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, 0)
+        )
+    }
+}
+
+fn same_style(a: &Cell, b: &Cell) -> bool {
+    a.fg == b.fg && a.bg == b.bg && a.attrs == b.attrs
+}
+
+/// send what's needed to go from drawing like `pen` to drawing like `cell`
+fn set_style(out: &mut impl Write, pen: Option<&Cell>, cell: &Cell) -> io::Result<()> {
+    if pen.is_none_or(|pen| pen.attrs != cell.attrs) {
+        // there's no way to switch a single attribute off everywhere, so start over. the
+        // reset takes the colors with it
+        return queue!(
+            out,
+            SetAttribute(Attribute::Reset),
+            SetAttributes(cell.attrs),
+            SetForegroundColor(cell.fg),
+            SetBackgroundColor(cell.bg)
+        );
+    }
+    if pen.is_none_or(|pen| pen.fg != cell.fg) {
+        queue!(out, SetForegroundColor(cell.fg))?;
+    }
+    if pen.is_none_or(|pen| pen.bg != cell.bg) {
+        queue!(out, SetBackgroundColor(cell.bg))?;
+    }
+    Ok(())
+}
+
+/// a terminal of a fixed size that only keeps the cells drawn on it, for tests
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct TestBackend {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+#[cfg(test)]
+impl TestBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    /// the text of row `y` the way it looks on screen
+    pub fn row(&self, y: usize) -> String {
+        self.cells[y * self.width..(y + 1) * self.width]
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect()
+    }
+
+    /// every row, top to bottom
+    pub fn rows(&self) -> Vec<String> {
+        (0..self.height).map(|y| self.row(y)).collect()
+    }
+}
+
+#[cfg(test)]
+impl Backend for TestBackend {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        Ok((self.width, self.height))
+    }
+
+    fn draw(&mut self, x: usize, y: usize, cells: &[Cell]) -> io::Result<()> {
+        if y >= self.height || x >= self.width {
+            return Ok(());
+        }
+        let start = y * self.width + x;
+        let n = cells.len().min(self.width - x);
+        self.cells[start..start + n].copy_from_slice(&cells[..n]);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn enter(&mut self, _mouse: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn leave(&mut self, _mouse: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.cells.fill(Cell::default());
+        Ok(())
+    }
+}
@@ -17,94 +17,642 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
-/// NOTE! This file is largely untouched and incomplete, eventually this will control how we call methods on State
-/// and by parsing commands we can do things like state.write_loaded_entry_to_disk()
+/// the command registry. every command the command bar knows about is a `CommandSpec` in
+/// `COMMANDS`: its names, what it takes, its help text and the function that runs it. parsing,
+/// :help and tab completion all work off this table, so adding a command is adding a row here
 // src/util/command.rs
-use std::str::FromStr;
-
-use crate::{state::state::State, util::util::log_message};
+use crate::{
+    state::state::State,
+    util::{
+        keymap::KeyMode,
+        parser::{parse, Address, ParseError, ParsedCommand, Range},
+    },
+};
 
 /// Unique identifier for an entry (using the id from Entry).
 pub type EntryId = String;
 
-/// Command abstraction – each variant corresponds to a user action.
-#[derive(Debug)]
-pub enum Command {
-    AddEntry(String),     // add a new entry with the given title/label
-    DeleteEntry(EntryId), // delete the entry with the specified id
-    Save,                 // save current entry (equivalent to :w)
-    Quit,                 // quit
-    QuitForce,            // quit without saving
-    Invalid(String),      // unrecognized command.
-}
-
-impl FromStr for Command {
-    type Err = ();
-
-    /// simple parser splits the input on whitespace and matches the first token.
-    ///   "add My new entry" -> Command::AddEntry("My new entry".into())
-    ///   "delete <id>"     -> Command::DeleteEntry(id)
-    ///   "w" or "save"     -> Command::Save
-    ///   "q" or "quit"     -> Command::Quit
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let tokens: Vec<&str> = s.trim().splitn(2, ' ').collect();
-        if tokens.is_empty() {
-            return Err(());
+/// what an argument or option value is, which decides what tab completes it with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Text,     // free text, nothing to complete
+    Entry,    // an entry label
+    Tag,      // a tag, or a comma-separated list of them
+    Template, // the name of a template in the templates directory
+    Path,     // a file path
+    Notebook, // another notebook, see `Config::notebook_path`
+    Command,  // the name of a command
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+    pub rest: bool, // takes every remaining word, e.g. a label with spaces in it
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OptionSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+}
+
+/// runs a command that passed the checks in `CommandSpec::check`. errors are for the user
+pub type Handler = fn(&mut State, &ParsedCommand) -> Result<(), String>;
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [ArgSpec],
+    pub options: &'static [OptionSpec],
+    pub range: bool, // accepts a range in front of the name
+    pub bang: bool,  // accepts a ! after the name
+    pub help: &'static str,
+    pub handler: Handler,
+}
+
+const fn arg(name: &'static str, kind: ArgKind) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        optional: false,
+        rest: false,
+    }
+}
+
+const fn optional(name: &'static str, kind: ArgKind) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        optional: true,
+        rest: false,
+    }
+}
+
+const fn rest(name: &'static str, kind: ArgKind) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        optional: true,
+        rest: true,
+    }
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "add",
+        aliases: &[],
+        args: &[rest("label", ArgKind::Text)],
+        options: &[OptionSpec {
+            name: "tags",
+            kind: ArgKind::Tag,
+        }],
+        range: false,
+        bang: false,
+        help: "add a new, empty entry. tags=a,b tags it",
+        handler: cmd_add,
+    },
+    CommandSpec {
+        name: "new",
+        aliases: &[],
+        args: &[
+            arg("template", ArgKind::Template),
+            rest("label", ArgKind::Text),
+        ],
+        options: &[OptionSpec {
+            name: "label",
+            kind: ArgKind::Text,
+        }],
+        range: false,
+        bang: false,
+        help: "add a new entry filled in from a template and open it",
+        handler: cmd_new,
+    },
+    CommandSpec {
+        name: "today",
+        aliases: &[],
+        args: &[],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "open today's entry, creating it from the daily template if needed",
+        handler: cmd_today,
+    },
+    CommandSpec {
+        name: "open",
+        aliases: &["o"],
+        args: &[optional("entry", ArgKind::Entry)],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "open an entry for reading, the selected one by default. quote a label with spaces",
+        handler: cmd_open,
+    },
+    CommandSpec {
+        name: "delete",
+        aliases: &[],
+        args: &[rest("entry", ArgKind::Entry)],
+        options: &[],
+        range: true,
+        bang: false,
+        help: "delete the entries named, one per argument (quote labels with spaces), every entry in a range (:1,5delete), or else the marked or selected ones, after asking",
+        handler: cmd_delete,
+    },
+    CommandSpec {
+        name: "rename",
+        aliases: &[],
+        args: &[rest("label", ArgKind::Text)],
+        options: &[OptionSpec {
+            name: "entry",
+            kind: ArgKind::Entry,
+        }],
+        range: false,
+        bang: false,
+        help: "give the open or selected entry a new label, or the one named with entry= (quote a label with spaces)",
+        handler: cmd_rename,
+    },
+    CommandSpec {
+        name: "dup",
+        aliases: &[],
+        args: &[optional("entry", ArgKind::Entry)],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "copy an entry, the selected one by default, under a new id. quote a label with spaces",
+        handler: cmd_dup,
+    },
+    CommandSpec {
+        name: "merge",
+        aliases: &[],
+        args: &[rest("entry", ArgKind::Entry)],
+        options: &[],
+        range: true,
+        bang: false,
+        help: "combine the entries named, one per argument (quote labels with spaces), in a range or marked into one in date order, moving the originals to the trash",
+        handler: cmd_merge,
+    },
+    CommandSpec {
+        name: "filter",
+        aliases: &[],
+        args: &[rest("text", ArgKind::Text)],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "highlight entries whose label or tags match, n and N jump between them and * marks them. no text clears it",
+        handler: cmd_filter,
+    },
+    CommandSpec {
+        name: "tag",
+        aliases: &[],
+        args: &[rest("tags", ArgKind::Tag)],
+        options: &[],
+        range: true,
+        bang: false,
+        help: "tag the marked or selected entries, or a range. -tag removes one",
+        handler: cmd_tag,
+    },
+    CommandSpec {
+        name: "export",
+        aliases: &[],
+        args: &[arg("dir", ArgKind::Path)],
+        options: &[],
+        range: true,
+        bang: false,
+        help: "write the marked or selected entries, or a range, into a directory as markdown",
+        handler: cmd_export,
+    },
+    CommandSpec {
+        name: "move",
+        aliases: &[],
+        args: &[arg("notebook", ArgKind::Notebook)],
+        options: &[],
+        range: true,
+        bang: false,
+        help: "move the marked or selected entries, or a range, to another notebook",
+        handler: cmd_move,
+    },
+    CommandSpec {
+        name: "goto",
+        aliases: &[],
+        args: &[],
+        options: &[],
+        range: true,
+        bang: false,
+        help: "select the entry at a list position. a bare :N does the same",
+        handler: cmd_goto,
+    },
+    CommandSpec {
+        name: "calendar",
+        aliases: &["cal"],
+        args: &[],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "show the calendar",
+        handler: cmd_calendar,
+    },
+    CommandSpec {
+        name: "save",
+        aliases: &["w"],
+        args: &[],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "save the open entry",
+        handler: cmd_save,
+    },
+    CommandSpec {
+        name: "quit",
+        aliases: &["q"],
+        args: &[],
+        options: &[],
+        range: false,
+        bang: true,
+        help: "quit rnbook. with ! unsaved changes are thrown away",
+        handler: cmd_quit,
+    },
+    CommandSpec {
+        name: "keys",
+        aliases: &[],
+        args: &[optional("mode", ArgKind::Text)],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "list the key bindings of a mode (browse, read, edit or command), the current one by default",
+        handler: cmd_keys,
+    },
+    CommandSpec {
+        name: "registers",
+        aliases: &["reg"],
+        args: &[],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "list the macros recorded into registers with q",
+        handler: cmd_registers,
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["h"],
+        args: &[optional("command", ArgKind::Command)],
+        options: &[],
+        range: false,
+        bang: false,
+        help: "list every command, or show how to use one",
+        handler: cmd_help,
+    },
+];
+
+/// the command called `name`, by its name or one of its aliases
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name == name || spec.aliases.contains(&name))
+}
+
+impl CommandSpec {
+    /// e.g. `[range]delete [id]` or `quit[!]`
+    pub fn usage(&self) -> String {
+        let mut usage = String::new();
+        if self.range {
+            usage.push_str("[range]");
         }
-        match tokens[0].to_lowercase().as_str() {
-            "add" => {
-                if tokens.len() > 1 {
-                    Ok(Command::AddEntry(tokens[1].to_string()))
-                } else {
-                    Ok(Command::AddEntry(String::new()))
-                }
-            }
-            "delete" => {
-                if tokens.len() > 1 {
-                    // todo obviously user isnt gonna input the whole id itself, selected
-                    Ok(Command::DeleteEntry(tokens[1].to_string()))
-                } else {
-                    Err(())
-                }
+        usage.push_str(self.name);
+        if self.bang {
+            usage.push_str("[!]");
+        }
+        for arg in self.args {
+            let name = if arg.rest {
+                format!("{}...", arg.name)
+            } else {
+                arg.name.to_string()
+            };
+            if arg.optional {
+                usage.push_str(&format!(" [{}]", name));
+            } else {
+                usage.push_str(&format!(" <{}>", name));
             }
-            "w" | "save" => Ok(Command::Save),
-            "q" | "quit" => Ok(Command::Quit),
-            "q!" => Ok(Command::QuitForce),
-            _ => Ok(Command::Invalid(s.to_string())),
         }
+        for option in self.options {
+            usage.push_str(&format!(" [{}=...]", option.name));
+        }
+        usage
+    }
+
+    /// the lines :help shows for this command
+    pub fn help_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(":{}", self.usage()), String::new()];
+        lines.push(self.help.to_string());
+        if !self.aliases.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("aliases: {}", self.aliases.join(", ")));
+        }
+        lines
+    }
+
+    /// the spec of the argument at `index`, following a trailing `rest` argument
+    pub fn arg_at(&self, index: usize) -> Option<&ArgSpec> {
+        self.args
+            .get(index)
+            .or_else(|| self.args.last().filter(|arg| arg.rest))
+    }
+
+    /// make sure the parsed command only uses what this command takes
+    pub fn check(&self, parsed: &ParsedCommand) -> Result<(), ParseError> {
+        if !self.range {
+            parsed.forbid_range()?;
+        }
+        if !self.bang {
+            parsed.forbid_bang()?;
+        }
+        let allowed: Vec<&str> = self.options.iter().map(|option| option.name).collect();
+        parsed.allow_options(&allowed)?;
+        if !self.args.last().is_some_and(|arg| arg.rest) {
+            parsed.max_args(self.args.len())?;
+        }
+        let required = self.args.iter().filter(|arg| !arg.optional).count();
+        if parsed.args.len() < required {
+            let missing = &self.args[parsed.args.len()];
+            return Err(ParseError::new(
+                parsed.name_pos + parsed.name.chars().count(),
+                &format!("{} needs a {}", self.name, missing.name),
+            ));
+        }
+        Ok(())
     }
 }
 
-/// simple command dispatcher that acts on the State
+/// looks commands up in the registry and runs them on the State
 pub struct Commander;
 
 impl Commander {
-    pub fn dispatch(cmd: Command, state: &mut State) {
-        match cmd {
-            Command::AddEntry(label) => {
-                state.add_entry(&label);
-            }
-            Command::DeleteEntry(id) => {
-                if let Err(e) = state.delete_entry(&id) {
-                    log_message(&format!("failed to delete entry: {}", e));
-                }
-            }
-            Command::Save => {
-                if let Err(e) = state.save_current_entry() {
-                    log_message(&format!("failed to save entry: {}", e));
-                }
-            }
-            Command::Quit => {
-                // TODO warn of unsaved changes
-                log_message("quit command run");
-                state.quit();
-            }
-            Command::QuitForce => {
-                // TODO fix state.quit()
-                log_message("quitforce command run");
-                state.quit();
-            }
-            Command::Invalid(s) => log_message(&format!("unrecognized command: {}", s)),
+    /// parse a line of the command bar and find the command it names
+    pub fn parse(input: &str) -> Result<(&'static CommandSpec, ParsedCommand), ParseError> {
+        let parsed = parse(input)?;
+        let name = match (parsed.name.as_str(), parsed.range) {
+            ("", Some(_)) => "goto",
+            (name, _) => name,
+        };
+        let spec = find_command(name).ok_or_else(|| {
+            ParseError::new(
+                parsed.name_pos,
+                &format!("unrecognized command: {}", parsed.name),
+            )
+        })?;
+        spec.check(&parsed)?;
+        Ok((spec, parsed))
+    }
+
+    pub fn dispatch(spec: &CommandSpec, parsed: &ParsedCommand, state: &mut State) {
+        if let Err(e) = (spec.handler)(state, parsed) {
+            state.error(&format!("{}: {}", spec.name, e));
+        }
+    }
+}
+
+fn cmd_add(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let tags = parsed.option("tags").map_or(Vec::new(), |tags| {
+        tags.value
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    });
+    let label = parsed.joined_args();
+    state.create_entry(&label, String::new(), tags);
+    state.info(&format!("added '{}'", label));
+    Ok(())
+}
+
+fn cmd_new(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let template = &parsed.args[0].value;
+    let label = match parsed.option("label") {
+        Some(label) => Some(label.value.clone()),
+        None if parsed.args.len() > 1 => Some(
+            parsed.args[1..]
+                .iter()
+                .map(|arg| arg.value.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        None => None,
+    };
+    let id = state
+        .add_entry_from_template(template, label.as_deref())
+        .map_err(|e| format!("failed to add entry from template: {}", e))?;
+    state.open_entry(&id);
+    Ok(())
+}
+
+fn cmd_today(state: &mut State, _: &ParsedCommand) -> Result<(), String> {
+    state
+        .open_today()
+        .map_err(|e| format!("failed to open today's entry: {}", e))
+}
+
+/// the entries a command with a range and entry arguments works on: the ones named, each
+/// argument a reference of its own (quote labels with spaces), or else the range, the marked
+/// entries or the selected one, see `State::target_ids`
+fn target_entries(state: &State, parsed: &ParsedCommand) -> Result<Vec<EntryId>, String> {
+    match parsed.range {
+        Some(_) if !parsed.args.is_empty() => Err(String::from("give either a range or entries")),
+        None if !parsed.args.is_empty() => parsed
+            .args
+            .iter()
+            .map(|arg| state.resolve_entry_ref(&arg.value))
+            .collect(),
+        range => state.target_ids(range),
+    }
+}
+
+fn cmd_open(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let id = state.resolve_entry_ref(parsed.args.first().map_or("", |arg| &arg.value))?;
+    state.open_entry(&id);
+    Ok(())
+}
+
+fn cmd_delete(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let mut ids = target_entries(state, parsed)?;
+    ids.sort();
+    ids.dedup();
+    state.confirm_delete(ids);
+    Ok(())
+}
+
+fn cmd_rename(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let label = parsed.joined_args();
+    if label.is_empty() {
+        return Err(String::from("rename needs a new label"));
+    }
+    let id = state.resolve_entry_ref(parsed.option("entry").map_or("", |arg| &arg.value))?;
+    state
+        .rename_with_relink(&id, &label)
+        .map_err(|e| format!("failed to rename entry: {}", e))?;
+    state.info(&format!("renamed to '{}'", label));
+    Ok(())
+}
+
+fn cmd_dup(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let id = state.resolve_entry_ref(parsed.args.first().map_or("", |arg| &arg.value))?;
+    let copy = state
+        .duplicate_entry(&id)
+        .map_err(|e| format!("failed to copy entry: {}", e))?;
+    if let Some(pos) = state
+        .master_index
+        .entries
+        .iter()
+        .position(|meta| meta.id == copy)
+    {
+        state.idx = pos;
+    }
+    state.info("entry copied");
+    Ok(())
+}
+
+fn cmd_merge(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let ids = target_entries(state, parsed)?;
+    let mut unique = ids.clone();
+    unique.sort();
+    unique.dedup();
+    if unique.len() < 2 {
+        return Err(String::from("merge needs at least two entries"));
+    }
+    state
+        .merge_entries(&ids)
+        .map_err(|e| format!("failed to merge entries: {}", e))?;
+    state.info(&format!("merged {} entries", unique.len()));
+    Ok(())
+}
+
+fn cmd_filter(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let text = parsed.joined_args();
+    if text.is_empty() {
+        state.filter = None;
+        return Ok(());
+    }
+    state.filter = Some(text);
+    if !state.is_filter_match(state.idx) {
+        state.select_filter_match(true);
+    }
+    let n = (0..state.master_index.entries.len())
+        .filter(|&i| state.is_filter_match(i))
+        .count();
+    state.info(&format!("{} entries match", n));
+    Ok(())
+}
+
+fn cmd_tag(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let mut add = Vec::new();
+    let mut remove = Vec::new();
+    for tag in parsed.args.iter().flat_map(|arg| arg.value.split(',')) {
+        match tag.trim().strip_prefix('-') {
+            Some(tag) if !tag.is_empty() => remove.push(tag.to_string()),
+            Some(_) => {}
+            None if !tag.trim().is_empty() => add.push(tag.trim().to_string()),
+            None => {}
         }
     }
+    if add.is_empty() && remove.is_empty() {
+        return Err(String::from("tag needs tags to add or -tags to remove"));
+    }
+    let ids = state.target_ids(parsed.range)?;
+    state
+        .tag_entries(&ids, &add, &remove)
+        .map_err(|e| format!("failed to tag entries: {}", e))?;
+    state.info(&format!("tagged {} entries", ids.len()));
+    Ok(())
+}
+
+fn cmd_export(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let dir = crate::util::completion::expand_home(&parsed.args[0].value);
+    let ids = state.target_ids(parsed.range)?;
+    let n = state
+        .export_entries(&ids, &dir)
+        .map_err(|e| format!("failed to export entries: {}", e))?;
+    state.info(&format!("exported {} entries to {}", n, dir.display()));
+    Ok(())
+}
+
+fn cmd_move(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let name = &parsed.args[0].value;
+    let dir = state.config.notebook_path(name);
+    let ids = state.target_ids(parsed.range)?;
+    state
+        .move_entries(&ids, &dir)
+        .map_err(|e| format!("failed to move entries: {}", e))?;
+    state.info(&format!("moved {} entries to {}", ids.len(), name));
+    Ok(())
+}
+
+fn cmd_goto(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let address = parsed.range.map_or(Address::Current, |range| range.end);
+    let range = Range {
+        start: address,
+        end: address,
+    };
+    let (pos, _) = range.resolve(state.idx, state.master_index.entries.len())?;
+    state.idx = pos;
+    Ok(())
+}
+
+fn cmd_calendar(state: &mut State, _: &ParsedCommand) -> Result<(), String> {
+    state.open_calendar();
+    Ok(())
+}
+
+fn cmd_save(state: &mut State, _: &ParsedCommand) -> Result<(), String> {
+    state
+        .save_open_entry()
+        .map_err(|e| format!("failed to save entry: {}", e))?;
+    state.info("saved");
+    Ok(())
+}
+
+fn cmd_quit(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    if state.editor.dirty && !parsed.bang {
+        return Err(String::from(
+            "the open entry has unsaved changes, :w saves them and :q! throws them away",
+        ));
+    }
+    state.quit();
+    Ok(())
+}
+
+fn cmd_keys(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let mode = match parsed.args.first() {
+        Some(name) => KeyMode::from_name(&name.value)
+            .ok_or_else(|| format!("no mode called {}", name.value))?,
+        None => state.key_mode(),
+    };
+    let lines = state.keymap.help_lines(mode);
+    state.show_help(lines);
+    Ok(())
+}
+
+fn cmd_registers(state: &mut State, _: &ParsedCommand) -> Result<(), String> {
+    let lines = state.register_lines();
+    state.show_help(lines);
+    Ok(())
+}
+
+fn cmd_help(state: &mut State, parsed: &ParsedCommand) -> Result<(), String> {
+    let lines = match parsed.args.first() {
+        Some(name) => find_command(&name.value)
+            .ok_or_else(|| format!("no command called {}", name.value))?
+            .help_lines(),
+        None => {
+            let width = COMMANDS
+                .iter()
+                .map(|spec| spec.usage().chars().count())
+                .max()
+                .unwrap_or(0);
+            COMMANDS
+                .iter()
+                .map(|spec| format!(":{:<width$}  {}", spec.usage(), spec.help, width = width))
+                .collect()
+        }
+    };
+    state.show_help(lines);
+    Ok(())
 }
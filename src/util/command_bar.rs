@@ -17,45 +17,125 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
-#[derive(Debug, Clone)]
+use crate::util::text::{char_width, pad};
+
+#[derive(Debug, Clone, Default)]
 pub struct CommandBar {
     pub user_buffer: String,
     pub buffer: String,
+    pub cursor: usize, // char position of the cursor in buffer
 }
 
 impl CommandBar {
+    /// the visible part of the buffer, padded to `x - 2` cells. long input scrolls so the cursor
+    /// stays in view
     pub fn stringify(&mut self, x: u32) -> String {
-        let mut str = String::new();
-        let max_chars = x - 2;
-
-        for (index, character) in self.buffer.char_indices() {
-            if index < max_chars as usize {
-                str.push(character)
-            }
-        }
-
-        while str.len() < max_chars as usize {
-            str.push(' ')
+        let max_cells = x.saturating_sub(2) as usize;
+        let visible: String = self.buffer.chars().skip(self.view_offset(x)).collect();
+        pad(&visible, max_cells)
+    }
+    /// how many chars stringify scrolls off the front to keep the cursor visible
+    pub fn view_offset(&self, x: u32) -> usize {
+        let max_cells = x.saturating_sub(2) as usize;
+        let before: Vec<char> = self.buffer.chars().take(self.cursor).collect();
+        // the cursor's own cell and the chars before it have to fit
+        let mut used = 1;
+        let mut offset = before.len();
+        while offset > 0 && used + char_width(before[offset - 1]) <= max_cells {
+            offset -= 1;
+            used += char_width(before[offset]);
         }
-
-        str
+        offset
     }
-    pub fn push_char(&mut self, c: char) {
-        self.buffer.push(c)
+    /// the cell the char at `pos` is drawn in by stringify, if it's in view
+    pub fn column(&self, pos: usize, x: u32) -> Option<usize> {
+        let offset = self.view_offset(x);
+        let column = self
+            .buffer
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(pos)
+            .skip(offset)
+            .map(char_width)
+            .sum();
+        (pos >= offset && column < x.saturating_sub(2) as usize).then_some(column)
+    }
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.buffer
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.buffer.len(), |(i, _)| i)
     }
-    pub fn push_str(&mut self, s: &str) {
-        self.buffer.push_str(s)
+    fn len(&self) -> usize {
+        self.buffer.chars().count()
     }
+    /// insert `c` at the cursor
+    pub fn push_char(&mut self, c: char) {
+        let i = self.byte_index(self.cursor);
+        self.buffer.insert(i, c);
+        self.cursor += 1;
+    }
+    /// delete the char before the cursor
     pub fn pop_char(&mut self) {
-        self.buffer.pop();
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let i = self.byte_index(self.cursor);
+            self.buffer.remove(i);
+        }
+    }
+    /// delete the char under the cursor
+    pub fn delete_char(&mut self) {
+        if self.cursor < self.len() {
+            let i = self.byte_index(self.cursor);
+            self.buffer.remove(i);
+        }
+    }
+    /// delete the word before the cursor, and the whitespace after it (Ctrl+W)
+    pub fn delete_word(&mut self) {
+        let chars: Vec<char> = self.buffer.chars().collect();
+        let mut start = self.cursor;
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        let (from, to) = (self.byte_index(start), self.byte_index(self.cursor));
+        self.buffer.replace_range(from..to, "");
+        self.cursor = start;
+    }
+    /// delete everything before the cursor (Ctrl+U)
+    pub fn delete_to_start(&mut self) {
+        let to = self.byte_index(self.cursor);
+        self.buffer.replace_range(..to, "");
+        self.cursor = 0;
+    }
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+    pub fn move_right(&mut self) {
+        self.cursor = std::cmp::min(self.cursor + 1, self.len());
+    }
+    pub fn move_start(&mut self) {
+        self.cursor = 0;
+    }
+    pub fn move_end(&mut self) {
+        self.cursor = self.len();
+    }
+    /// replace the buffer, with the cursor at the end
+    pub fn set(&mut self, text: &str) {
+        self.buffer = text.to_string();
+        self.move_end();
     }
     pub fn clear(&mut self) {
-        self.buffer.clear()
+        self.buffer.clear();
+        self.cursor = 0;
     }
     pub fn get_buffer_contents(&self) -> String {
         self.buffer.clone()
     }
     pub fn swap(&mut self) {
         std::mem::swap(&mut self.buffer, &mut self.user_buffer);
+        self.move_end();
     }
 }
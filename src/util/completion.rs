@@ -0,0 +1,327 @@
+/*
+ * src/util/completion.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// tab completion for the command bar. what gets completed depends on where the cursor is: the
+/// command name, or an argument or option value of the kind the registry says it is
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    state::state::State,
+    util::{
+        command::{find_command, ArgKind, COMMANDS},
+        parser::parse,
+    },
+};
+
+/// the candidates for the word being completed, and which one is in the command bar right now
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub head: String, // the input before the word being completed
+    pub candidates: Vec<String>,
+    pub idx: usize,
+    pub unique: bool, // a lone candidate also gets a trailing space
+}
+
+impl Completion {
+    /// the command bar's contents with the current candidate filled in
+    pub fn applied(&self) -> String {
+        let mut applied = format!("{}{}", self.head, quote_arg(&self.candidates[self.idx]));
+        if self.unique {
+            applied.push(' ');
+        }
+        applied
+    }
+}
+
+/// what the word at the end of the input is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionContext {
+    pub head: String,
+    pub partial: String,
+    pub kind: ArgKind,
+}
+
+/// work out what the last word of `input` is and what kind of thing it should complete to
+pub fn completion_context(input: &str) -> Option<CompletionContext> {
+    let (head, raw) = split_last_word(input);
+
+    // still on the first word: complete the command name, after any range in front of it
+    if head.trim().trim_start_matches(':').trim().is_empty() {
+        let prefix_len = raw
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || ",.$%:".contains(*c))
+            .count();
+        let (range, name) = raw.split_at(prefix_len);
+        return Some(CompletionContext {
+            head: format!("{}{}", head, range),
+            partial: name.to_string(),
+            kind: ArgKind::Command,
+        });
+    }
+
+    let parsed = parse(head.trim_end()).ok()?;
+    let name = if parsed.name.is_empty() {
+        "goto"
+    } else {
+        parsed.name.as_str()
+    };
+    let spec = find_command(name)?;
+
+    // a key=value option, complete its value
+    if let Some((key, value)) = raw.split_once('=') {
+        if let Some(option) = spec.options.iter().find(|option| option.name == key) {
+            return Some(with_tag_segments(CompletionContext {
+                head: format!("{}{}=", head, key),
+                partial: unquote(value),
+                kind: option.kind,
+            }));
+        }
+    }
+
+    let arg = spec.arg_at(parsed.args.len())?;
+    Some(with_tag_segments(CompletionContext {
+        head: head.to_string(),
+        partial: unquote(raw),
+        kind: arg.kind,
+    }))
+}
+
+/// tags are written comma-separated, only the one after the last comma is completed
+fn with_tag_segments(mut context: CompletionContext) -> CompletionContext {
+    if context.kind == ArgKind::Tag {
+        if let Some((done, last)) = context.partial.rsplit_once(',') {
+            context.head.push_str(done);
+            context.head.push(',');
+            context.partial = last.to_string();
+        }
+    }
+    context
+}
+
+/// split the input into everything before the last word and the last word itself (quotes and
+/// all). input ending in whitespace has an empty last word
+fn split_last_word(input: &str) -> (&str, &str) {
+    let mut start = 0;
+    let mut in_double = false;
+    let mut in_single = false;
+    let mut escaped = false;
+    let mut in_word = false;
+    for (i, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if !in_single => escaped = true,
+            '"' if !in_single => in_double = !in_double,
+            '\'' if !in_double => in_single = !in_single,
+            c if c.is_whitespace() && !in_double && !in_single => {
+                in_word = false;
+                continue;
+            }
+            _ => {}
+        }
+        if !in_word {
+            in_word = true;
+            start = i;
+        }
+    }
+    if !in_word {
+        start = input.len();
+    }
+    input.split_at(start)
+}
+
+/// the value of a half-typed word, without its quotes and escapes
+fn unquote(raw: &str) -> String {
+    let mut value = String::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {}
+            '\\' => value.extend(chars.next()),
+            c => value.push(c),
+        }
+    }
+    value
+}
+
+/// quote a completed value if the parser would otherwise split or mangle it
+pub fn quote_arg(value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || "\"'\\=".contains(c)) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// `~/...` relative to the home directory, anything else as is
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// files and directories starting with `partial`, written the way the user started writing them
+fn path_candidates(partial: &str) -> Vec<String> {
+    let (dir_text, file_prefix) = match partial.rfind('/') {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+    let dir = if dir_text.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(dir_text)
+    };
+    let Ok(read_dir) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = read_dir
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(file_prefix) || (name.starts_with('.') && file_prefix.is_empty()) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir_text, name, slash))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+fn template_candidates(templates_path: &Path, partial: &str) -> Vec<String> {
+    let Ok(read_dir) = fs::read_dir(templates_path) else {
+        return Vec::new();
+    };
+    let names: BTreeSet<String> = read_dir
+        .flatten()
+        .filter_map(|entry| {
+            entry
+                .path()
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .filter(|name| name.starts_with(partial))
+        .collect();
+    names.into_iter().collect()
+}
+
+/// the notebooks next to the one in `entries_path`, or paths if `partial` has a slash in it
+fn notebook_candidates(entries_path: &Path, partial: &str) -> Vec<String> {
+    if partial.contains('/') {
+        return path_candidates(partial);
+    }
+    let Some(Ok(read_dir)) = entries_path.parent().map(fs::read_dir) else {
+        return Vec::new();
+    };
+    let names: BTreeSet<String> = read_dir
+        .flatten()
+        .filter(|entry| entry.path().is_dir() && entry.path() != entries_path)
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(partial) && !name.starts_with('.'))
+        .collect();
+    names.into_iter().collect()
+}
+
+impl State {
+    /// everything of `kind` that starts with `partial`
+    pub fn completion_candidates(&self, kind: ArgKind, partial: &str) -> Vec<String> {
+        let lower = partial.to_lowercase();
+        match kind {
+            ArgKind::Text => Vec::new(),
+            ArgKind::Command => {
+                let names: BTreeSet<&str> = COMMANDS
+                    .iter()
+                    .flat_map(|spec| std::iter::once(spec.name).chain(spec.aliases.iter().copied()))
+                    .filter(|name| name.starts_with(&lower))
+                    .collect();
+                names.into_iter().map(String::from).collect()
+            }
+            ArgKind::Entry => {
+                let mut labels: Vec<String> = Vec::new();
+                for meta in &self.master_index.entries {
+                    if meta.label.to_lowercase().starts_with(&lower)
+                        && !labels.contains(&meta.label)
+                    {
+                        labels.push(meta.label.clone());
+                    }
+                }
+                labels
+            }
+            ArgKind::Tag => {
+                let tags: BTreeSet<&String> = self
+                    .master_index
+                    .entries
+                    .iter()
+                    .flat_map(|meta| meta.tags.iter())
+                    .filter(|tag| tag.to_lowercase().starts_with(&lower))
+                    .collect();
+                tags.into_iter().cloned().collect()
+            }
+            ArgKind::Template => match self.config.templates_path() {
+                Ok(path) => template_candidates(&path, partial),
+                Err(_) => Vec::new(),
+            },
+            ArgKind::Path => path_candidates(partial),
+            ArgKind::Notebook => notebook_candidates(&self.config.entries_path, partial),
+        }
+    }
+
+    /// complete the word at the end of the command bar. pressing tab again without typing
+    /// anything in between moves on to the next candidate
+    pub fn complete_command(&mut self) {
+        let input = self.command_bar.get_buffer_contents();
+        if let Some(completion) = &mut self.completion {
+            if completion.applied() == input && completion.candidates.len() > 1 {
+                completion.idx = (completion.idx + 1) % completion.candidates.len();
+                self.command_bar.set(&completion.applied());
+                return;
+            }
+        }
+
+        self.completion = None;
+        let Some(context) = completion_context(&input) else {
+            return;
+        };
+        let candidates = self.completion_candidates(context.kind, &context.partial);
+        if candidates.is_empty() {
+            return;
+        }
+        // directories and tag lists usually go on, so don't finish the word with a space
+        let unique = candidates.len() == 1
+            && context.kind != ArgKind::Tag
+            && !(matches!(context.kind, ArgKind::Path | ArgKind::Notebook)
+                && candidates[0].ends_with('/'));
+        let completion = Completion {
+            head: context.head,
+            candidates,
+            idx: 0,
+            unique,
+        };
+        self.command_bar.set(&completion.applied());
+        self.completion = Some(completion);
+    }
+}
@@ -19,11 +19,17 @@
 
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fs,
     io::{Error, ErrorKind},
     path::PathBuf,
 };
 
+use crate::util::{hooks::Hooks, theme::DEFAULT_THEME};
+
+/// name of the master index file inside an entries directory
+pub const MASTER_INDEX_FILE: &str = "rnbook_master_list.json";
+
 /// on Unix-like systems the config file is stored at ~/.config/rnbook/config.json.
 /// on Windows it is stored at %APPDATA%/rnbook/config.json.
 /// the configuration also contains the path to the entries directory,
@@ -33,6 +39,45 @@ pub struct Config {
     /// the path where entry files are stored.
     /// (the master index file “rnbook_master_list.json” will be inside this directory)
     pub entries_path: PathBuf,
+    /// name of the template in the templates directory that :today fills in when it creates
+    /// the day's entry. no template means an empty entry
+    #[serde(default)]
+    pub daily_template: Option<String>,
+    /// key bindings per mode on top of the defaults, see util/keymap.rs
+    #[serde(default)]
+    pub keymap: HashMap<String, HashMap<String, String>>,
+    /// commands run when entries are created, saved, renamed or deleted, see util/hooks.rs
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// name of the color theme, built in ("dark" or "light") or in the themes directory
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    /// whether BROWSE starts with the selected entry shown next to the list
+    #[serde(default = "default_preview")]
+    pub preview: bool,
+    /// how much of the width the list takes next to the preview, in percent
+    #[serde(default = "default_split_ratio")]
+    pub split_ratio: usize,
+    /// whether the mouse clicks, scrolls and selects. off leaves it to the terminal, which
+    /// then does its own selecting
+    #[serde(default = "default_mouse")]
+    pub mouse: bool,
+}
+
+fn default_theme() -> String {
+    DEFAULT_THEME.to_string()
+}
+
+fn default_preview() -> bool {
+    true
+}
+
+fn default_split_ratio() -> usize {
+    50
+}
+
+fn default_mouse() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -44,17 +89,32 @@ impl Default for Config {
         // default entries directory: <config_dir>/entries
         let mut entries_path = config_dir.clone();
         entries_path.push("entries");
-        Self { entries_path }
+        Self {
+            entries_path,
+            daily_template: None,
+            keymap: HashMap::new(),
+            hooks: Hooks::default(),
+            theme: default_theme(),
+            preview: default_preview(),
+            split_ratio: default_split_ratio(),
+            mouse: default_mouse(),
+        }
     }
 }
 
+/// the rnbook directory inside the OS-specific config directory, e.g. ~/.config/rnbook
+pub fn config_dir() -> Result<PathBuf, std::io::Error> {
+    let mut config_dir = dirs::config_dir()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "couldn't find config directory"))?;
+    config_dir.push("rnbook");
+    Ok(config_dir)
+}
+
 impl Config {
     /// load the config from the OS-specific config directory
     /// if the file does not exist or is invalid, the default configuration is used and written to disk
     pub fn load() -> Result<Self, std::io::Error> {
-        let mut config_path = dirs::config_dir()
-            .ok_or_else(|| Error::new(ErrorKind::NotFound, "couldn't find config directory"))?;
-        config_path.push("rnbook");
+        let mut config_path = config_dir()?;
         config_path.push("config.json");
 
         if config_path.exists() {
@@ -70,9 +130,7 @@ impl Config {
 
     /// save the configuration to the appropriate config directory
     pub fn save(&self) -> Result<(), std::io::Error> {
-        let mut config_dir = dirs::config_dir()
-            .ok_or_else(|| Error::new(ErrorKind::NotFound, "couldn't find config directory"))?;
-        config_dir.push("rnbook");
+        let config_dir = config_dir()?;
         fs::create_dir_all(&config_dir)?;
         let mut config_file = config_dir.clone();
         config_file.push("config.json");
@@ -84,7 +142,40 @@ impl Config {
     /// returns the path to the master index file, which is always inside the entries directory
     pub fn master_index_path(&self) -> PathBuf {
         let mut p = self.entries_path.clone();
-        p.push("rnbook_master_list.json");
+        p.push(MASTER_INDEX_FILE);
         p
     }
+
+    /// returns the path to where merged entries are moved to, inside the entries directory
+    pub fn trash_path(&self) -> PathBuf {
+        self.entries_path.join(".trash")
+    }
+
+    /// the name of the notebook, which is the name of the entries directory
+    pub fn notebook_name(&self) -> String {
+        self.entries_path
+            .file_name()
+            .map_or(String::from("notebook"), |name| {
+                name.to_string_lossy().to_string()
+            })
+    }
+
+    /// the entries directory of another notebook. a bare name is a directory next to this
+    /// notebook's, anything with a slash in it is a path
+    pub fn notebook_path(&self, name: &str) -> PathBuf {
+        if name.contains('/') {
+            return crate::util::completion::expand_home(name);
+        }
+        match self.entries_path.parent() {
+            Some(parent) => parent.join(name),
+            None => PathBuf::from(name),
+        }
+    }
+
+    /// returns the path to the templates directory, e.g. ~/.config/rnbook/templates
+    pub fn templates_path(&self) -> Result<PathBuf, std::io::Error> {
+        let mut p = config_dir()?;
+        p.push("templates");
+        Ok(p)
+    }
 }
@@ -27,10 +27,23 @@ pub const LEFT_UPPER_SHOULDER: char = '┌';
 pub const RIGHT_UPPER_SHOULDER: char = '┐';
 pub const LEFT_LOWER_SHOULDER: char = '└';
 pub const RIGHT_LOWER_SHOULDER: char = '┘';
-pub const WHITESPACE: char = ' ';
+pub const PIN_MARKER: char = '*';
+/// drawn left of entries marked for a bulk command
+pub const MARK_MARKER: char = '+';
+/// drawn left of entries in a range mark that hasn't been finished yet
+pub const PENDING_MARK_MARKER: char = '~';
+pub const SCROLLBAR_THUMB: char = '█';
+
+/// bounds for the list's share of the width next to the preview, in percent
+pub const MIN_SPLIT: usize = 20;
+pub const MAX_SPLIT: usize = 80;
 
 pub const TOO_SMALL_WARNING: &str = "> 60x4 TERM SIZE REQUIRED";
 pub const NO_ENTRIES_WARNING: &str = "< not an entry to be found :) >";
+pub const EMPTY_ENTRY_WARNING: &str = "< nothing written here yet >";
+
+/// the format of `Entry.date`, also used to pick out entries by day
+pub const DATE_FORMAT: &str = "%Y/%m/%d";
 
 // pub const CHRONICLE_RESOURCE_PATH: &str =
 //    "/home/fizbin/lair/proj/rust/chronicle/asset/chronicle.txt";
@@ -0,0 +1,131 @@
+/*
+ * src/util/history.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// the command bar's history, kept one command per line in ~/.config/rnbook/history
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::util::config::config_dir;
+
+/// how many commands are kept between sessions
+const HISTORY_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub entries: Vec<String>, // oldest first
+    pub pos: Option<usize>,   // the entry shown in the command bar while walking with Up/Down
+    pub draft: String,        // what was typed before walking into the history
+}
+
+/// a reverse-incremental search (Ctrl+R) through the history
+#[derive(Debug, Clone, Default)]
+pub struct HistorySearch {
+    pub query: String,
+    pub found: Option<usize>, // index into History.entries of the current match
+}
+
+impl History {
+    pub fn path() -> io::Result<PathBuf> {
+        let mut p = config_dir()?;
+        p.push("history");
+        Ok(p)
+    }
+
+    /// read the saved history. a missing file is just an empty history
+    pub fn load() -> Self {
+        let entries = Self::path()
+            .and_then(fs::read_to_string)
+            .map(|text| {
+                text.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            entries,
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(path)?;
+        for entry in &self.entries {
+            writeln!(file, "{}", entry)?;
+        }
+        Ok(())
+    }
+
+    /// remember a submitted command, skipping repeats of the last one
+    pub fn push(&mut self, command: &str) {
+        self.pos = None;
+        self.draft.clear();
+        let command = command.trim();
+        if command.is_empty() || self.entries.last().is_some_and(|last| last == command) {
+            return;
+        }
+        self.entries.push(command.to_string());
+        if self.entries.len() > HISTORY_LIMIT {
+            let extra = self.entries.len() - HISTORY_LIMIT;
+            self.entries.drain(..extra);
+        }
+    }
+
+    /// step back to an older command. `current` is what's in the command bar, kept as the draft
+    /// when first stepping into the history
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let pos = match self.pos {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(pos) => pos.saturating_sub(1),
+        };
+        self.pos = Some(pos);
+        Some(&self.entries[pos])
+    }
+
+    /// step forward to a newer command, and back out to the draft past the newest
+    pub fn newer(&mut self) -> Option<&str> {
+        let pos = self.pos?;
+        if pos + 1 < self.entries.len() {
+            self.pos = Some(pos + 1);
+            Some(&self.entries[pos + 1])
+        } else {
+            self.pos = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// the newest entry before `before` that contains `query`
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        let before = before.min(self.entries.len());
+        self.entries[..before]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+}
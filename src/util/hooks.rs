@@ -0,0 +1,256 @@
+/*
+ * src/util/hooks.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// hooks: shell commands from config.json run when entries are created, saved, renamed or
+/// deleted, e.g. to commit the entries directory to git
+///
+/// ```json
+/// "hooks": { "on_save": "cd \"$RNBOOK_ENTRIES_PATH\" && git add -A && git commit -qm \"$RNBOOK_ENTRY_LABEL\"" }
+/// ```
+///
+/// a hook gets the entry in RNBOOK_EVENT, RNBOOK_ENTRY_ID, RNBOOK_ENTRY_PATH, RNBOOK_ENTRY_LABEL
+/// and RNBOOK_ENTRIES_PATH, and the same as a JSON object on stdin. hooks run one at a time, in
+/// the order they were fired, on a worker thread so a slow one doesn't hold up the UI. failures
+/// come back to the status line
+use std::{
+    fmt,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, SendError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Hooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_create: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_save: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_rename: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_delete: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Create,
+    Save,
+    Rename,
+    Delete,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookEvent::Create => write!(f, "create"),
+            HookEvent::Save => write!(f, "save"),
+            HookEvent::Rename => write!(f, "rename"),
+            HookEvent::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+impl Hooks {
+    pub fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Create => self.on_create.as_deref(),
+            HookEvent::Save => self.on_save.as_deref(),
+            HookEvent::Rename => self.on_rename.as_deref(),
+            HookEvent::Delete => self.on_delete.as_deref(),
+        }
+        .filter(|command| !command.trim().is_empty())
+    }
+}
+
+/// what a hook is told about the entry
+#[derive(Serialize, Debug, Clone)]
+pub struct HookPayload {
+    pub event: String,
+    pub id: String,
+    pub label: String,
+    pub path: PathBuf,
+    pub entries_path: PathBuf,
+}
+
+/// how a hook went, collected by the event loop
+#[derive(Debug, Clone)]
+pub struct HookResult {
+    pub event: HookEvent,
+    pub outcome: Result<(), String>,
+}
+
+/// where finished hooks leave their results for the event loop to pick up
+pub type HookResults = Arc<Mutex<Vec<HookResult>>>;
+
+/// a hook waiting for the worker: its event, command and payload
+type HookJob = (HookEvent, String, HookPayload);
+
+/// how long quitting waits for the hooks still queued or running
+pub const HOOK_WAIT: Duration = Duration::from_secs(10);
+
+/// feeds hooks to the worker thread, which is started with the first one
+#[derive(Debug, Default)]
+pub struct HookQueue {
+    sender: Option<Sender<HookJob>>,
+    worker: Option<JoinHandle<()>>,
+    pub results: HookResults,
+}
+
+impl HookQueue {
+    /// run `command` after the hooks already queued
+    pub fn push(&mut self, event: HookEvent, command: &str, payload: HookPayload) {
+        let job = (event, command.to_string(), payload);
+        let job = match &self.sender {
+            Some(sender) => match sender.send(job) {
+                Ok(()) => return,
+                Err(SendError(job)) => job, // the worker is gone, start another
+            },
+            None => job,
+        };
+        let (sender, receiver) = mpsc::channel::<HookJob>();
+        let results = self.results.clone();
+        let worker = thread::spawn(move || {
+            for (event, command, payload) in receiver {
+                let outcome = run_hook(&command, &payload);
+                if let Ok(mut results) = results.lock() {
+                    results.push(HookResult { event, outcome });
+                }
+            }
+        });
+        // the worker holds the receiver until every sender is dropped, so this can't fail
+        let _ = sender.send(job);
+        self.sender = Some(sender);
+        self.worker = Some(worker);
+    }
+
+    /// stop taking hooks and wait for the queued ones to run, for at most `timeout`. returns
+    /// false if it gave up on some
+    pub fn finish(&mut self, timeout: Duration) -> bool {
+        // the worker stops once the queue is empty and the sender is gone
+        self.sender = None;
+        let Some(worker) = self.worker.take() else {
+            return true;
+        };
+        let deadline = Instant::now() + timeout;
+        while !worker.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = worker.join();
+        true
+    }
+}
+
+fn run_hook(command: &str, payload: &HookPayload) -> Result<(), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .env("RNBOOK_EVENT", &payload.event)
+        .env("RNBOOK_ENTRY_ID", &payload.id)
+        .env("RNBOOK_ENTRY_LABEL", &payload.label)
+        .env("RNBOOK_ENTRY_PATH", &payload.path)
+        .env("RNBOOK_ENTRIES_PATH", &payload.entries_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let json = serde_json::to_string(payload).map_err(|e| e.to_string())?;
+        // a hook that doesn't read its stdin closes it early, that's fine
+        let _ = stdin.write_all(json.as_bytes());
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .map_or(output.status.to_string(), |line| line.trim().to_string());
+    Err(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, time::Duration};
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_hooks_in_order() {
+        let dir = std::env::temp_dir().join(format!("rnbook-hooks-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("log");
+        let mut queue = HookQueue::default();
+        // the first hook is the slowest, it still finishes first
+        let events = [
+            (HookEvent::Create, "sleep 0.2; "),
+            (HookEvent::Save, "sleep 0.1; "),
+            (HookEvent::Delete, ""),
+        ];
+        for (event, delay) in events {
+            let payload = HookPayload {
+                event: event.to_string(),
+                id: String::from("id"),
+                label: String::from("label"),
+                path: dir.join("entry.md"),
+                entries_path: dir.clone(),
+            };
+            let command = format!("{}echo \"$RNBOOK_EVENT\" >> {:?}", delay, log);
+            queue.push(event, &command, payload);
+        }
+        for _ in 0..100 {
+            if queue.results.lock().unwrap().len() == events.len() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let results = queue.results.lock().unwrap();
+        let order: Vec<_> = results.iter().map(|result| result.event).collect();
+        assert_eq!(
+            order,
+            [HookEvent::Create, HookEvent::Save, HookEvent::Delete]
+        );
+        assert!(results.iter().all(|result| result.outcome.is_ok()));
+        assert_eq!(fs::read_to_string(&log).unwrap(), "create\nsave\ndelete\n");
+        drop(results);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
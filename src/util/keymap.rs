@@ -0,0 +1,963 @@
+/*
+ * src/util/keymap.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// key bindings. every action the keyboard can trigger is in `ACTIONS` along with its default
+/// keys in each mode, and the keymap section of config.json can rebind them per mode, e.g.
+///
+/// ```json
+/// "keymap": { "browse": { "x": "delete", "dd": "none" }, "read": { "<C-d>": "page_down" } }
+/// ```
+///
+/// keys are written like vim's: plain chars stand for themselves, `<...>` names a special key
+/// or a chord (`<CR>`, `<Esc>`, `<F5>`, `<C-r>`, `<A-x>`) and several keys in a row make a
+/// sequence (`gg`). binding a sequence to "none" removes it
+use std::{collections::HashMap, fmt};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// the action name that unbinds a key sequence
+pub const UNBOUND: &str = "none";
+
+/// the modes that have their own bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyMode {
+    Browse,
+    Read,
+    Edit,
+    Command,
+}
+
+impl KeyMode {
+    pub const ALL: [KeyMode; 4] = [
+        KeyMode::Browse,
+        KeyMode::Read,
+        KeyMode::Edit,
+        KeyMode::Command,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyMode::Browse => "browse",
+            KeyMode::Read => "read",
+            KeyMode::Edit => "edit",
+            KeyMode::Command => "command",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// one key press, with its modifiers. shift is left out for chars since it's in the char
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                // terminals report ctrl+r the same whichever case it was typed in
+                if modifiers.contains(KeyModifiers::CONTROL) {
+                    KeyCode::Char(c.to_ascii_lowercase())
+                } else {
+                    KeyCode::Char(c)
+                }
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    pub fn from_event(key_event: &KeyEvent) -> Self {
+        Self::new(key_event.code, key_event.modifiers)
+    }
+
+    /// a char typed without ctrl or alt, the kind that goes into text
+    pub fn typed_char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c)
+                if !self
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+}
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("cr", KeyCode::Enter),
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("s-tab", KeyCode::BackTab),
+    ("bs", KeyCode::Backspace),
+    ("backspace", KeyCode::Backspace),
+    ("del", KeyCode::Delete),
+    ("delete", KeyCode::Delete),
+    ("space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+    ("gt", KeyCode::Char('>')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("insert", KeyCode::Insert),
+    ("nul", KeyCode::Null),
+    ("begin", KeyCode::KeypadBegin),
+    ("capslock", KeyCode::CapsLock),
+    ("scrolllock", KeyCode::ScrollLock),
+    ("numlock", KeyCode::NumLock),
+    ("printscreen", KeyCode::PrintScreen),
+    ("pause", KeyCode::Pause),
+    ("menu", KeyCode::Menu),
+];
+
+/// parse what's between `<` and `>`, e.g. `C-r`, `Space` or `A-Down`
+fn parse_named(name: &str) -> Result<KeyChord, String> {
+    let lower = name.to_lowercase();
+    if let Some((_, code)) = NAMED_KEYS.iter().find(|(key, _)| *key == lower) {
+        return Ok(KeyChord::new(*code, KeyModifiers::NONE));
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        if n > 0 {
+            return Ok(KeyChord::new(KeyCode::F(n), KeyModifiers::NONE));
+        }
+    }
+    let Some((modifier, rest)) = name.split_once('-') else {
+        return Err(format!("unknown key <{}>", name));
+    };
+    let modifier = match modifier.to_lowercase().as_str() {
+        "c" => KeyModifiers::CONTROL,
+        "a" | "m" => KeyModifiers::ALT,
+        "s" => KeyModifiers::SHIFT,
+        _ => return Err(format!("unknown modifier in <{}>", name)),
+    };
+    let inner = if rest.chars().count() == 1 {
+        KeyChord::new(
+            KeyCode::Char(rest.chars().next().unwrap_or(' ')),
+            KeyModifiers::NONE,
+        )
+    } else {
+        parse_named(rest)?
+    };
+    Ok(KeyChord::new(inner.code, inner.modifiers | modifier))
+}
+
+/// parse a key sequence like `gg`, `<C-r>` or `<Space>x`
+pub fn parse_keys(text: &str) -> Result<Vec<KeyChord>, String> {
+    let mut keys = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '<' {
+            let rest = chars.as_str();
+            let Some(end) = rest.find('>') else {
+                return Err(format!("unclosed <{}", rest));
+            };
+            keys.push(parse_named(&rest[..end])?);
+            chars = rest[end + 1..].chars();
+        } else {
+            keys.push(KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+    if keys.is_empty() {
+        return Err(String::from("empty key sequence"));
+    }
+    Ok(keys)
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut prefix = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            prefix.push_str("C-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            prefix.push_str("A-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            prefix.push_str("S-");
+        }
+        let name = match self.code {
+            KeyCode::Char(' ') => String::from("Space"),
+            KeyCode::Char('<') => String::from("lt"),
+            KeyCode::Char(c) if prefix.is_empty() => return write!(f, "{}", c),
+            // `>` would end the name early
+            KeyCode::Char('>') => String::from("gt"),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            KeyCode::Enter => String::from("CR"),
+            KeyCode::Backspace => String::from("BS"),
+            KeyCode::Delete => String::from("Del"),
+            KeyCode::BackTab => String::from("S-Tab"),
+            KeyCode::PageUp => String::from("PageUp"),
+            KeyCode::PageDown => String::from("PageDown"),
+            KeyCode::Null => String::from("Nul"),
+            KeyCode::KeypadBegin => String::from("Begin"),
+            KeyCode::CapsLock => String::from("CapsLock"),
+            KeyCode::ScrollLock => String::from("ScrollLock"),
+            KeyCode::NumLock => String::from("NumLock"),
+            KeyCode::PrintScreen => String::from("PrintScreen"),
+            // their debug names are how NAMED_KEYS spells them
+            KeyCode::Esc
+            | KeyCode::Tab
+            | KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::Home
+            | KeyCode::End
+            | KeyCode::Insert
+            | KeyCode::Pause
+            | KeyCode::Menu => format!("{:?}", self.code),
+            // media and lone modifier keys only come with the kitty protocol, which isn't on
+            KeyCode::Media(_) | KeyCode::Modifier(_) => format!("{:?}", self.code),
+        };
+        write!(f, "<{}{}>", prefix, name)
+    }
+}
+
+/// write a key sequence back the way it is written in the config
+pub fn keys_to_string(keys: &[KeyChord]) -> String {
+    keys.iter().map(|key| key.to_string()).collect()
+}
+
+/// everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Down,
+    Up,
+    Left,
+    Right,
+    PageDown,
+    PageUp,
+    First,
+    Last,
+    LineStart,
+    LineEnd,
+    Command,
+    Quit,
+    Close,
+    Save,
+    Open,
+    Filter,
+    MoveUp,
+    MoveDown,
+    TogglePin,
+    Calendar,
+    ToggleMark,
+    MarkRange,
+    MarkMatches,
+    NextMatch,
+    PrevMatch,
+    TogglePreview,
+    WidenList,
+    NarrowList,
+    Delete,
+    Edit,
+    Backlinks,
+    FollowLink,
+    GoBack,
+    Newline,
+    Indent,
+    Backspace,
+    DeleteChar,
+    Read,
+    Submit,
+    Complete,
+    DeleteWord,
+    DeleteToStart,
+    HistoryPrev,
+    HistoryNext,
+    HistorySearch,
+    RecordMacro,
+    PlayMacro,
+}
+
+#[derive(Debug)]
+pub struct ActionSpec {
+    pub action: Action,
+    pub name: &'static str,
+    pub help: &'static str,
+    /// the modes the action works in, with the keys it has there unless the config says
+    /// otherwise
+    pub defaults: &'static [(KeyMode, &'static [&'static str])],
+}
+
+use KeyMode::{Browse, Command, Edit, Read};
+
+pub const ACTIONS: &[ActionSpec] = &[
+    ActionSpec {
+        action: Action::Down,
+        name: "down",
+        help: "select the next entry, or move the cursor down a line",
+        defaults: &[
+            (Browse, &["<Down>", "j"]),
+            (Read, &["<Down>", "j"]),
+            (Edit, &["<Down>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::Up,
+        name: "up",
+        help: "select the previous entry, or move the cursor up a line",
+        defaults: &[
+            (Browse, &["<Up>", "k"]),
+            (Read, &["<Up>", "k"]),
+            (Edit, &["<Up>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::Left,
+        name: "left",
+        help: "move the cursor left",
+        defaults: &[
+            (Read, &["<Left>", "h"]),
+            (Edit, &["<Left>"]),
+            (Command, &["<Left>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::Right,
+        name: "right",
+        help: "move the cursor right",
+        defaults: &[
+            (Read, &["<Right>", "l"]),
+            (Edit, &["<Right>"]),
+            (Command, &["<Right>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::PageDown,
+        name: "page_down",
+        help: "move a screen down",
+        defaults: &[
+            (Browse, &["<PageDown>"]),
+            (Read, &["<PageDown>"]),
+            (Edit, &["<PageDown>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::PageUp,
+        name: "page_up",
+        help: "move a screen up",
+        defaults: &[
+            (Browse, &["<PageUp>"]),
+            (Read, &["<PageUp>"]),
+            (Edit, &["<PageUp>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::First,
+        name: "first",
+        help: "go to the first entry, or the top of the open one",
+        defaults: &[(Browse, &["<Home>", "gg"]), (Read, &["gg"])],
+    },
+    ActionSpec {
+        action: Action::Last,
+        name: "last",
+        help: "go to the last entry, or the bottom of the open one",
+        defaults: &[(Browse, &["<End>", "G"]), (Read, &["G"])],
+    },
+    ActionSpec {
+        action: Action::LineStart,
+        name: "line_start",
+        help: "move the cursor to the start of the line",
+        defaults: &[
+            (Read, &["<Home>", "0"]),
+            (Edit, &["<Home>"]),
+            (Command, &["<Home>", "<C-a>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::LineEnd,
+        name: "line_end",
+        help: "move the cursor to the end of the line",
+        defaults: &[
+            (Read, &["<End>", "$"]),
+            (Edit, &["<End>"]),
+            (Command, &["<End>", "<C-e>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::Command,
+        name: "command",
+        help: "open the command bar",
+        defaults: &[(Browse, &[":"]), (Read, &[":"])],
+    },
+    ActionSpec {
+        action: Action::Quit,
+        name: "quit",
+        help: "quit rnbook",
+        defaults: &[
+            (Browse, &["<C-c>"]),
+            (Read, &["<C-c>"]),
+            (Edit, &["<C-c>"]),
+            (Command, &["<C-c>"]),
+        ],
+    },
+    ActionSpec {
+        action: Action::Close,
+        name: "close",
+        help: "close the open entry. when browsing, clear the marks, then the filter, then quit",
+        defaults: &[(Browse, &["<Esc>"]), (Read, &["<Esc>"])],
+    },
+    ActionSpec {
+        action: Action::Save,
+        name: "save",
+        help: "save the open entry",
+        defaults: &[(Read, &["<C-s>"]), (Edit, &["<C-s>"])],
+    },
+    ActionSpec {
+        action: Action::Open,
+        name: "open",
+        help: "open the selected entry",
+        defaults: &[(Browse, &["<CR>"])],
+    },
+    ActionSpec {
+        action: Action::Filter,
+        name: "filter",
+        help: "start typing a filter",
+        defaults: &[(Browse, &["/"])],
+    },
+    ActionSpec {
+        action: Action::MoveUp,
+        name: "move_up",
+        help: "move the selected entry up the list",
+        defaults: &[(Browse, &["K"])],
+    },
+    ActionSpec {
+        action: Action::MoveDown,
+        name: "move_down",
+        help: "move the selected entry down the list",
+        defaults: &[(Browse, &["J"])],
+    },
+    ActionSpec {
+        action: Action::TogglePin,
+        name: "toggle_pin",
+        help: "pin the selected entry to the top, or unpin it",
+        defaults: &[(Browse, &["p"])],
+    },
+    ActionSpec {
+        action: Action::Calendar,
+        name: "calendar",
+        help: "show the calendar",
+        defaults: &[(Browse, &["c"])],
+    },
+    ActionSpec {
+        action: Action::ToggleMark,
+        name: "toggle_mark",
+        help: "mark the selected entry, or unmark it",
+        defaults: &[(Browse, &["<Space>"])],
+    },
+    ActionSpec {
+        action: Action::MarkRange,
+        name: "mark_range",
+        help: "start a range of marks, or mark everything since the start",
+        defaults: &[(Browse, &["V"])],
+    },
+    ActionSpec {
+        action: Action::MarkMatches,
+        name: "mark_matches",
+        help: "mark every entry matching the filter",
+        defaults: &[(Browse, &["*"])],
+    },
+    ActionSpec {
+        action: Action::NextMatch,
+        name: "next_match",
+        help: "select the next entry matching the filter",
+        defaults: &[(Browse, &["n"])],
+    },
+    ActionSpec {
+        action: Action::PrevMatch,
+        name: "prev_match",
+        help: "select the previous entry matching the filter",
+        defaults: &[(Browse, &["N"])],
+    },
+    ActionSpec {
+        action: Action::TogglePreview,
+        name: "toggle_preview",
+        help: "show the selected entry next to the list, or hide it",
+        defaults: &[(Browse, &["P"])],
+    },
+    ActionSpec {
+        action: Action::WidenList,
+        name: "widen_list",
+        help: "give the list more room next to the preview",
+        defaults: &[(Browse, &[">"])],
+    },
+    ActionSpec {
+        action: Action::NarrowList,
+        name: "narrow_list",
+        help: "give the preview more room next to the list",
+        defaults: &[(Browse, &["<lt>"])],
+    },
+    ActionSpec {
+        action: Action::Delete,
+        name: "delete",
+        help: "delete the marked entries or the selected one, after asking",
+        defaults: &[(Browse, &["dd"])],
+    },
+    ActionSpec {
+        action: Action::Edit,
+        name: "edit",
+        help: "start editing the open entry",
+        defaults: &[(Read, &["i"])],
+    },
+    ActionSpec {
+        action: Action::Backlinks,
+        name: "backlinks",
+        help: "show the entries linking to the open one",
+        defaults: &[(Read, &["b"])],
+    },
+    ActionSpec {
+        action: Action::FollowLink,
+        name: "follow_link",
+        help: "open the entry the link under the cursor points at",
+        defaults: &[(Read, &["<CR>"])],
+    },
+    ActionSpec {
+        action: Action::GoBack,
+        name: "go_back",
+        help: "go back to the entry a link was followed from",
+        defaults: &[(Read, &["<BS>"])],
+    },
+    ActionSpec {
+        action: Action::Newline,
+        name: "newline",
+        help: "break the line at the cursor",
+        defaults: &[(Edit, &["<CR>"])],
+    },
+    ActionSpec {
+        action: Action::Indent,
+        name: "indent",
+        help: "insert four spaces",
+        defaults: &[(Edit, &["<Tab>"])],
+    },
+    ActionSpec {
+        action: Action::Backspace,
+        name: "backspace",
+        help: "delete the char before the cursor",
+        defaults: &[(Edit, &["<BS>"]), (Command, &["<BS>"])],
+    },
+    ActionSpec {
+        action: Action::DeleteChar,
+        name: "delete_char",
+        help: "delete the char under the cursor",
+        defaults: &[(Edit, &["<Del>"]), (Command, &["<Del>"])],
+    },
+    ActionSpec {
+        action: Action::Read,
+        name: "read",
+        help: "stop editing",
+        defaults: &[(Edit, &["<Esc>"])],
+    },
+    ActionSpec {
+        action: Action::Submit,
+        name: "submit",
+        help: "run the command",
+        defaults: &[(Command, &["<CR>"])],
+    },
+    ActionSpec {
+        action: Action::Close,
+        name: "cancel",
+        help: "close the command bar",
+        defaults: &[(Command, &["<Esc>"])],
+    },
+    ActionSpec {
+        action: Action::Complete,
+        name: "complete",
+        help: "complete the word at the end of the command",
+        defaults: &[(Command, &["<Tab>"])],
+    },
+    ActionSpec {
+        action: Action::DeleteWord,
+        name: "delete_word",
+        help: "delete the word before the cursor",
+        defaults: &[(Command, &["<C-w>"])],
+    },
+    ActionSpec {
+        action: Action::DeleteToStart,
+        name: "delete_to_start",
+        help: "delete everything before the cursor",
+        defaults: &[(Command, &["<C-u>"])],
+    },
+    ActionSpec {
+        action: Action::HistoryPrev,
+        name: "history_prev",
+        help: "bring back the previous command",
+        defaults: &[(Command, &["<Up>"])],
+    },
+    ActionSpec {
+        action: Action::HistoryNext,
+        name: "history_next",
+        help: "bring back the next command",
+        defaults: &[(Command, &["<Down>"])],
+    },
+    ActionSpec {
+        action: Action::HistorySearch,
+        name: "history_search",
+        help: "search the command history as you type",
+        defaults: &[(Command, &["<C-r>"])],
+    },
+    ActionSpec {
+        action: Action::RecordMacro,
+        name: "record_macro",
+        help: "record keys into the register named next, or stop recording",
+        defaults: &[(Browse, &["q"]), (Read, &["q"]), (Edit, &["<A-q>"])],
+    },
+    ActionSpec {
+        action: Action::PlayMacro,
+        name: "play_macro",
+        help: "replay the keys in the register named next, @ for the last one played",
+        defaults: &[(Browse, &["@"]), (Read, &["@"]), (Edit, &["<A-@>"])],
+    },
+];
+
+/// the action called `name` if it works in `mode`
+pub fn find_action(mode: KeyMode, name: &str) -> Option<&'static ActionSpec> {
+    ACTIONS.iter().find(|spec| {
+        spec.name == name
+            && spec
+                .defaults
+                .iter()
+                .any(|(spec_mode, _)| *spec_mode == mode)
+    })
+}
+
+/// what the keys pressed so far amount to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Action(Action),
+    Prefix, // the start of a longer sequence, wait for more keys
+    None,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    pub bindings: HashMap<KeyMode, Vec<(Vec<KeyChord>, &'static ActionSpec)>>,
+}
+
+impl Keymap {
+    /// the default bindings with the user's on top. the second value lists what in the user's
+    /// keymap couldn't be used
+    pub fn new(user: &HashMap<String, HashMap<String, String>>) -> (Self, Vec<String>) {
+        let mut keymap = Keymap::default();
+        for spec in ACTIONS {
+            for (mode, keys) in spec.defaults {
+                for keys in keys.iter() {
+                    // the defaults are ours, a bad one is a bug
+                    let keys = parse_keys(keys).expect("bad default key binding");
+                    keymap.bindings.entry(*mode).or_default().push((keys, spec));
+                }
+            }
+        }
+
+        let mut problems = Vec::new();
+        let mut modes: Vec<_> = user.iter().collect();
+        modes.sort_by_key(|(mode, _)| mode.as_str());
+        for (mode_name, bindings) in modes {
+            let Some(mode) = KeyMode::from_name(mode_name) else {
+                problems.push(format!("unknown mode '{}'", mode_name));
+                continue;
+            };
+            let mut bindings: Vec<_> = bindings.iter().collect();
+            bindings.sort();
+            for (keys_text, action_name) in bindings {
+                let keys = match parse_keys(keys_text) {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        problems.push(format!("{}: {}", mode_name, e));
+                        continue;
+                    }
+                };
+                let spec = if action_name == UNBOUND {
+                    None
+                } else {
+                    match find_action(mode, action_name) {
+                        Some(spec) => Some(spec),
+                        None => {
+                            problems.push(format!(
+                                "{}: unknown action '{}' for {}",
+                                mode_name, action_name, keys_text
+                            ));
+                            continue;
+                        }
+                    }
+                };
+                let mode_bindings = keymap.bindings.entry(mode).or_default();
+                mode_bindings.retain(|(bound, _)| *bound != keys);
+                if let Some(spec) = spec {
+                    mode_bindings.push((keys, spec));
+                }
+            }
+        }
+        (keymap, problems)
+    }
+
+    /// what `keys` does in `mode`. a sequence that is bound but also starts a longer one waits
+    /// for the next key to tell them apart
+    pub fn lookup(&self, mode: KeyMode, keys: &[KeyChord]) -> Lookup {
+        let Some(bindings) = self.bindings.get(&mode) else {
+            return Lookup::None;
+        };
+        let mut exact = None;
+        let mut longer = false;
+        for (bound, spec) in bindings {
+            if bound.as_slice() == keys {
+                exact = Some(spec.action);
+            } else if bound.starts_with(keys) {
+                longer = true;
+            }
+        }
+        if longer {
+            Lookup::Prefix
+        } else {
+            exact.map_or(Lookup::None, Lookup::Action)
+        }
+    }
+
+    /// the bindings of `mode` as lines for the help popup, one per action
+    pub fn help_lines(&self, mode: KeyMode) -> Vec<String> {
+        let bindings = self.bindings.get(&mode).cloned().unwrap_or_default();
+        let mut rows: Vec<(String, &ActionSpec)> = Vec::new();
+        for spec in ACTIONS {
+            let keys: Vec<String> = bindings
+                .iter()
+                .filter(|(_, bound)| std::ptr::eq(*bound, spec))
+                .map(|(keys, _)| keys_to_string(keys))
+                .collect();
+            if !keys.is_empty() {
+                rows.push((keys.join(" "), spec));
+            }
+        }
+        let width = rows
+            .iter()
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut lines = vec![format!("{} mode", mode.name()), String::new()];
+        lines.extend(rows.iter().map(|(keys, spec)| {
+            format!(
+                "{:<width$}  {:<16} {}",
+                keys,
+                spec.name,
+                spec.help,
+                width = width
+            )
+        }));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord::new(code, modifiers)
+    }
+
+    fn chars(text: &str) -> Vec<KeyChord> {
+        text.chars()
+            .map(|c| key(KeyCode::Char(c), KeyModifiers::NONE))
+            .collect()
+    }
+
+    #[test]
+    fn parses_keys() {
+        let none = KeyModifiers::NONE;
+        let cases = [
+            ("gg", chars("gg")),
+            (
+                "<C-r>",
+                vec![key(KeyCode::Char('r'), KeyModifiers::CONTROL)],
+            ),
+            (
+                "<c-R>",
+                vec![key(KeyCode::Char('r'), KeyModifiers::CONTROL)],
+            ),
+            ("<A-Q>", vec![key(KeyCode::Char('Q'), KeyModifiers::ALT)]),
+            ("<lt>", vec![key(KeyCode::Char('<'), none)]),
+            ("<lt>x", chars("<x")),
+            ("<S-Tab>", vec![key(KeyCode::BackTab, none)]),
+            ("<S-Up>", vec![key(KeyCode::Up, KeyModifiers::SHIFT)]),
+            (
+                "<C-A-Down>",
+                vec![key(
+                    KeyCode::Down,
+                    KeyModifiers::CONTROL | KeyModifiers::ALT,
+                )],
+            ),
+            (
+                "<Space>x",
+                vec![key(KeyCode::Char(' '), none), key(KeyCode::Char('x'), none)],
+            ),
+            ("<CR>", vec![key(KeyCode::Enter, none)]),
+            ("<esc>", vec![key(KeyCode::Esc, none)]),
+            ("<A-é>", vec![key(KeyCode::Char('é'), KeyModifiers::ALT)]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_keys(text), Ok(expected), "keys: {:?}", text);
+        }
+
+        let errors = [
+            ("", "empty key sequence"),
+            ("<Foo>", "unknown key <Foo>"),
+            ("<X-a>", "unknown modifier in <X-a>"),
+            ("<C-Foo>", "unknown key <Foo>"),
+            ("<>", "unknown key <>"),
+            ("<C-r", "unclosed <C-r"),
+            ("a<", "unclosed <"),
+            // the modifier's lowercase is longer than it is
+            ("<Ⱥ-é>", "unknown modifier in <Ⱥ-é>"),
+        ];
+        for (text, expected) in errors {
+            assert_eq!(
+                parse_keys(text),
+                Err(String::from(expected)),
+                "keys: {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn looks_up_sequences() {
+        let (keymap, problems) = Keymap::new(&HashMap::new());
+        assert!(problems.is_empty());
+        let browse = |text: &str| keymap.lookup(KeyMode::Browse, &parse_keys(text).unwrap());
+        assert_eq!(browse("g"), Lookup::Prefix);
+        assert_eq!(browse("gg"), Lookup::Action(Action::First));
+        assert_eq!(browse("gx"), Lookup::None);
+        assert_eq!(browse("d"), Lookup::Prefix);
+        assert_eq!(browse("dd"), Lookup::Action(Action::Delete));
+        assert_eq!(browse("j"), Lookup::Action(Action::Down));
+        // dd only deletes while browsing
+        let read = keymap.lookup(KeyMode::Read, &chars("dd"));
+        assert_eq!(read, Lookup::None);
+
+        // a key bound on its own still waits when it also starts a longer sequence
+        let user = HashMap::from([(
+            String::from("browse"),
+            HashMap::from([(String::from("g"), String::from("last"))]),
+        )]);
+        let (keymap, _) = Keymap::new(&user);
+        assert_eq!(keymap.lookup(KeyMode::Browse, &chars("g")), Lookup::Prefix);
+        assert_eq!(
+            keymap.lookup(KeyMode::Browse, &chars("gg")),
+            Lookup::Action(Action::First)
+        );
+    }
+
+    #[test]
+    fn applies_user_bindings() {
+        let user = HashMap::from([
+            (
+                String::from("browse"),
+                HashMap::from([
+                    (String::from("x"), String::from("delete")),
+                    (String::from("dd"), String::from(UNBOUND)),
+                    (String::from("gg"), String::from("no_such_action")),
+                    (String::from("<Foo>"), String::from("quit")),
+                    (String::from("z"), String::from("newline")),
+                ]),
+            ),
+            (
+                String::from("nowhere"),
+                HashMap::from([(String::from("x"), String::from("quit"))]),
+            ),
+        ]);
+        let (keymap, problems) = Keymap::new(&user);
+        assert_eq!(
+            problems,
+            vec![
+                "browse: unknown key <Foo>",
+                "browse: unknown action 'no_such_action' for gg",
+                "browse: unknown action 'newline' for z",
+                "unknown mode 'nowhere'",
+            ]
+        );
+
+        let browse = |text: &str| keymap.lookup(KeyMode::Browse, &parse_keys(text).unwrap());
+        assert_eq!(browse("x"), Lookup::Action(Action::Delete));
+        // unbinding dd leaves d with nothing to start
+        assert_eq!(browse("d"), Lookup::None);
+        assert_eq!(browse("dd"), Lookup::None);
+        // a binding that failed leaves the default alone
+        assert_eq!(browse("gg"), Lookup::Action(Action::First));
+        assert_eq!(browse("z"), Lookup::None);
+    }
+
+    #[test]
+    fn writes_keys_back() {
+        let mut codes: Vec<KeyCode> = NAMED_KEYS.iter().map(|(_, code)| *code).collect();
+        codes.extend((1..=24).map(KeyCode::F));
+        codes.extend(['a', 'Q', '>', '-', '@', '"', '\\'].map(KeyCode::Char));
+        let modifiers = [
+            KeyModifiers::NONE,
+            KeyModifiers::CONTROL,
+            KeyModifiers::ALT,
+            KeyModifiers::SHIFT,
+            KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT,
+        ];
+        for code in codes {
+            for modifiers in modifiers {
+                let key = KeyChord::new(code, modifiers);
+                let written = key.to_string();
+                assert_eq!(parse_keys(&written), Ok(vec![key]), "written: {}", written);
+            }
+        }
+
+        let cases = [
+            ("<F5>", "<F5>"),
+            ("<insert><C-F12>", "<Insert><C-F12>"),
+            ("<A-gt>", "<A-gt>"),
+            ("<lt>>", "<lt>>"),
+            ("g<Space><S-Tab>", "g<Space><S-Tab>"),
+        ];
+        for (text, expected) in cases {
+            let keys = parse_keys(text).unwrap_or_default();
+            assert_eq!(keys_to_string(&keys), expected, "keys: {:?}", text);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+/*
+ * src/util/layout.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// a region of the screen, `width` cells across and `height` rows down from (`x`, `y`).
+/// everything here is cut down to fit, so a terminal of any size gives rects that are at worst
+/// empty, never out of bounds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// how much to take off each side of a rect
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Padding {
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+    pub left: usize,
+}
+
+impl Padding {
+    pub fn uniform(n: usize) -> Self {
+        Self {
+            top: n,
+            right: n,
+            bottom: n,
+            left: n,
+        }
+    }
+
+    /// `n` cells off the left and right
+    pub fn horizontal(n: usize) -> Self {
+        Self {
+            right: n,
+            left: n,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// side by side, left to right
+    Horizontal,
+    /// stacked, top to bottom
+    Vertical,
+}
+
+/// how big one part of a split wants to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constraint {
+    /// exactly this many cells
+    Length(usize),
+    /// this share of the whole, out of 100
+    Percent(usize),
+    /// whatever is left over, shared with the other `Fill`s
+    Fill,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// one past the rightmost column
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    /// one past the bottom row
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    /// whether the cell at column `x`, row `y` is inside
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.right()).contains(&x) && (self.y..self.bottom()).contains(&y)
+    }
+
+    pub fn pad(self, padding: Padding) -> Self {
+        let left = padding.left.min(self.width);
+        let top = padding.top.min(self.height);
+        Self {
+            x: self.x + left,
+            y: self.y + top,
+            width: (self.width - left).saturating_sub(padding.right),
+            height: (self.height - top).saturating_sub(padding.bottom),
+        }
+    }
+
+    /// the inside of a box drawn around this rect
+    pub fn inner(self) -> Self {
+        self.pad(Padding::uniform(1))
+    }
+
+    /// row `i` of this rect, empty if it's past the bottom
+    pub fn row(self, i: usize) -> Self {
+        Self {
+            y: self.y + i.min(self.height),
+            height: usize::from(i < self.height),
+            ..self
+        }
+    }
+
+    /// a rect of at most `width` by `height` in the middle of this one
+    pub fn centered(self, width: usize, height: usize) -> Self {
+        let width = width.min(self.width);
+        let height = height.min(self.height);
+        Self {
+            x: self.x + (self.width - width) / 2,
+            y: self.y + (self.height - height) / 2,
+            width,
+            height,
+        }
+    }
+
+    /// the part this rect has in common with `other`
+    pub fn intersection(self, other: Self) -> Self {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Self {
+            x,
+            y,
+            width: self.right().min(other.right()).saturating_sub(x),
+            height: self.bottom().min(other.bottom()).saturating_sub(y),
+        }
+    }
+
+    /// cut this rect into parts along `direction`. fixed sizes are handed out first, the last
+    /// parts giving way when they don't all fit, then what's left goes to the `Fill`s evenly
+    pub fn split<const N: usize>(
+        self,
+        direction: Direction,
+        constraints: [Constraint; N],
+    ) -> [Rect; N] {
+        let total = match direction {
+            Direction::Horizontal => self.width,
+            Direction::Vertical => self.height,
+        };
+        let mut sizes = constraints.map(|constraint| match constraint {
+            Constraint::Length(n) => n,
+            Constraint::Percent(p) => total * p.min(100) / 100,
+            Constraint::Fill => 0,
+        });
+
+        let mut over = sizes.iter().sum::<usize>().saturating_sub(total);
+        for size in sizes.iter_mut().rev() {
+            let cut = over.min(*size);
+            *size -= cut;
+            over -= cut;
+        }
+
+        let left = total - sizes.iter().sum::<usize>();
+        let stretchy: Vec<usize> = (0..N)
+            .filter(|&i| constraints[i] == Constraint::Fill)
+            .collect();
+        for (n, &i) in stretchy.iter().enumerate() {
+            sizes[i] += left / stretchy.len() + usize::from(n < left % stretchy.len());
+        }
+
+        let mut offset = 0;
+        sizes.map(|size| {
+            let rect = match direction {
+                Direction::Horizontal => Rect::new(self.x + offset, self.y, size, self.height),
+                Direction::Vertical => Rect::new(self.x, self.y + offset, self.width, size),
+            };
+            offset += size;
+            rect
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Constraint::*;
+
+    #[test]
+    fn splits() {
+        let area = Rect::new(1, 2, 20, 10);
+        let [status, body] = area.split(Direction::Vertical, [Length(1), Fill]);
+        assert_eq!(status, Rect::new(1, 2, 20, 1));
+        assert_eq!(body, Rect::new(1, 3, 20, 9));
+
+        let [a, b, c] = area.split(Direction::Horizontal, [Percent(50), Fill, Fill]);
+        assert_eq!((a.x, a.width), (1, 10));
+        assert_eq!((b.x, b.width), (11, 5));
+        assert_eq!((c.x, c.width), (16, 5));
+
+        // without a Fill the slack is left over
+        let [a, b] = area.split(Direction::Horizontal, [Length(3), Length(4)]);
+        assert_eq!((a.width, b.width, b.right()), (3, 4, 8));
+    }
+
+    #[test]
+    fn too_little_room() {
+        let area = Rect::new(0, 0, 5, 3);
+        let [a, b, c] = area.split(Direction::Vertical, [Length(2), Length(4), Fill]);
+        assert_eq!((a.height, b.height, c.height), (2, 1, 0));
+        assert_eq!(c.y, 3);
+
+        assert!(Rect::new(0, 0, 1, 1).inner().is_empty());
+        assert_eq!(Rect::new(4, 4, 1, 0).inner(), Rect::new(5, 4, 0, 0));
+        assert_eq!(area.centered(9, 1), Rect::new(0, 1, 5, 1));
+        assert!(area.row(3).is_empty());
+        assert!(area.intersection(Rect::new(6, 0, 2, 2)).is_empty());
+        assert!(area.contains(4, 2) && !area.contains(5, 2) && !area.contains(0, 3));
+        assert!(!c.contains(0, 3));
+    }
+}
@@ -0,0 +1,579 @@
+/*
+ * src/util/markdown.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// entries rendered as markdown for READ and the preview: headings, emphasis, lists and
+/// checkboxes, block quotes, code blocks and rules, laid out to a width. every cell drawn from
+/// the source remembers where in it it came from, so the cursor (which stays in the source)
+/// can be shown where its text ended up
+use crossterm::style::Attribute;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::state::link::link::find_links;
+use crate::util::{
+    constant::HORIZONTAL_LINE,
+    term::Style,
+    text::{self, grapheme_width},
+    theme::Theme,
+    widget::{Line, Span},
+};
+
+/// how a list item is marked when it isn't numbered
+const BULLET: &str = "• ";
+/// what a block quote is marked with down its left side
+const QUOTE_BAR: &str = "│ ";
+
+/// one row of rendered text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Row {
+    pub line: Line,
+    /// (cell, byte offset in the source) of everything on the row that came from the source
+    pub sources: Vec<(usize, usize)>,
+}
+
+impl Row {
+    /// the grapheme drawn at cell `x`, a space if there's nothing there
+    pub fn grapheme_at(&self, x: usize) -> &str {
+        let mut cell = 0;
+        for span in &self.line {
+            for grapheme in span.text.graphemes(true) {
+                if cell == x {
+                    return grapheme;
+                }
+                cell += grapheme_width(grapheme);
+            }
+        }
+        " "
+    }
+
+    /// restyle everything drawn from the source bytes in `range`
+    pub fn select(&mut self, range: &Range<usize>, style: Style) {
+        let mut line: Line = Vec::new();
+        let mut cell = 0;
+        for span in &self.line {
+            for grapheme in span.text.graphemes(true) {
+                let selected = self
+                    .sources
+                    .iter()
+                    .any(|&(x, source)| x == cell && range.contains(&source));
+                let style = if selected { style } else { span.style };
+                match line.last_mut() {
+                    Some(last) if last.style == style => last.text.push_str(grapheme),
+                    _ => line.push(Span::new(grapheme, style)),
+                }
+                cell += grapheme_width(grapheme);
+            }
+        }
+        self.line = line;
+    }
+}
+
+/// `source` rendered to rows at most `width` cells wide
+pub fn render(source: &str, width: usize, theme: &Theme) -> Vec<Row> {
+    let mut renderer = Renderer {
+        theme,
+        width: width.max(1),
+        rows: Vec::new(),
+        atoms: Vec::new(),
+        styles: Vec::new(),
+        containers: Vec::new(),
+        lists: Vec::new(),
+        code_block: false,
+        gap: false,
+        links: link_ranges(source),
+    };
+    let options = Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+    for (event, range) in Parser::new_ext(source, options).into_offset_iter() {
+        renderer.event(event, source, range);
+    }
+    renderer.flush();
+    renderer.rows
+}
+
+/// the row and cell the source byte `offset` ended up at: the first thing drawn from there or
+/// after it, or the last thing drawn at all
+pub fn locate(rows: &[Row], offset: usize) -> (usize, usize) {
+    let mut last = (0, 0);
+    for (i, row) in rows.iter().enumerate() {
+        for &(x, source) in &row.sources {
+            if source >= offset {
+                return (i, x);
+            }
+            last = (i, x);
+        }
+    }
+    last
+}
+
+/// the source byte offset behind cell `x` of row `row`, the other way round from `locate`. past
+/// the end of a row is after its last grapheme, and a row with nothing from the source (a
+/// blank or a rule) gives the nearest one that has something
+pub fn source_at(rows: &[Row], row: usize, x: usize) -> Option<usize> {
+    if let Some(found) = rows.get(row).filter(|found| !found.sources.is_empty()) {
+        let &(cell, source) = found
+            .sources
+            .iter()
+            .rev()
+            .find(|&&(cell, _)| cell <= x)
+            .unwrap_or(&found.sources[0]);
+        let grapheme = found.grapheme_at(cell);
+        return if x >= cell + grapheme_width(grapheme) {
+            Some(source + grapheme.len())
+        } else {
+            Some(source)
+        };
+    }
+    let row = row.min(rows.len());
+    rows[row..]
+        .iter()
+        .find_map(|found| found.sources.first())
+        .or_else(|| {
+            rows[..row]
+                .iter()
+                .rev()
+                .find_map(|found| found.sources.last())
+        })
+        .map(|&(_, source)| source)
+}
+
+/// byte ranges of the `[[links]]` in `source`, which markdown itself doesn't know about
+fn link_ranges(source: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for line in source.split_inclusive('\n') {
+        let byte = |col: usize| start + line.char_indices().nth(col).map_or(line.len(), |(i, _)| i);
+        for link in find_links(line) {
+            ranges.push(byte(link.start)..byte(link.end));
+        }
+        start += line.len();
+    }
+    ranges
+}
+
+/// a grapheme on its way into a row
+#[derive(Debug, Clone)]
+struct Atom {
+    text: String,
+    style: Style,
+    source: Option<usize>,
+}
+
+impl Atom {
+    fn new(text: &str, style: Style, source: Option<usize>) -> Self {
+        Self {
+            text: text.to_string(),
+            style,
+            source,
+        }
+    }
+}
+
+/// blocks that put something in front of every row inside them
+enum Container {
+    Quote,
+    /// a list item, `first` until its marker has been drawn
+    Item {
+        marker: String,
+        first: bool,
+    },
+}
+
+struct Renderer<'a> {
+    theme: &'a Theme,
+    width: usize,
+    rows: Vec<Row>,
+    /// the text of the block being laid out
+    atoms: Vec<Atom>,
+    /// inline styles, innermost last
+    styles: Vec<Style>,
+    containers: Vec<Container>,
+    /// the next number of each list, `None` for bullets
+    lists: Vec<Option<u64>>,
+    code_block: bool,
+    /// a blank row goes before the next block
+    gap: bool,
+    links: Vec<Range<usize>>,
+}
+
+impl Renderer<'_> {
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or(self.theme.text)
+    }
+
+    fn push_style(&mut self, style: Style) {
+        self.styles.push(style);
+    }
+
+    /// the current style with `attr` added
+    fn with(&self, attr: Attribute) -> Style {
+        self.style().with(attr)
+    }
+
+    fn event(&mut self, event: Event, source: &str, range: Range<usize>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.code_block => self.code_text(&text, range.start),
+            Event::Text(text) => {
+                // escapes and entities don't line up with the source, so they all point at it
+                let exact = text.len() == range.len();
+                self.text(&text, self.style(), range.start, exact);
+            }
+            Event::Code(code) => {
+                let start = range.start + source[range.clone()].find(&*code).unwrap_or(0);
+                self.text(&code, self.theme.code, start, true);
+            }
+            Event::Html(html) | Event::InlineHtml(html) => self.text(
+                &html,
+                self.theme.muted,
+                range.start,
+                html.len() == range.len(),
+            ),
+            Event::SoftBreak => self.atoms.push(Atom::new(" ", self.style(), None)),
+            Event::HardBreak => self.atoms.push(Atom::new("\n", self.style(), None)),
+            Event::Rule => {
+                self.block_start();
+                let rule = HORIZONTAL_LINE.to_string().repeat(self.room());
+                let atoms = vec![Atom::new(&rule, self.theme.muted, Some(range.start))];
+                self.push_row(atoms, false);
+                self.gap = true;
+            }
+            Event::TaskListMarker(checked) => {
+                let box_ = if checked { "[x] " } else { "[ ] " };
+                // one atom per char like text, so flush measures it right and can break after it
+                for (i, grapheme) in box_.grapheme_indices(true) {
+                    self.atoms.push(Atom::new(
+                        grapheme,
+                        self.theme.bullet,
+                        Some(range.start + i),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.block_start(),
+            Tag::Heading { level, .. } => {
+                self.block_start();
+                let mut style = self.theme.heading.with(Attribute::Bold);
+                if level == HeadingLevel::H1 {
+                    style = style.with(Attribute::Underlined);
+                }
+                self.push_style(style);
+            }
+            Tag::BlockQuote(_) => {
+                self.block_start();
+                self.containers.push(Container::Quote);
+                self.push_style(self.theme.quote);
+            }
+            Tag::CodeBlock(_) => {
+                self.block_start();
+                self.code_block = true;
+            }
+            Tag::List(start) => {
+                self.block_start();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => BULLET.to_string(),
+                };
+                self.containers.push(Container::Item {
+                    marker,
+                    first: true,
+                });
+            }
+            Tag::Emphasis => self.push_style(self.with(Attribute::Italic)),
+            Tag::Strong => self.push_style(self.with(Attribute::Bold)),
+            Tag::Strikethrough => self.push_style(self.with(Attribute::CrossedOut)),
+            Tag::Link { .. } | Tag::Image { .. } => {
+                let style = self.theme.link.with_all(self.style().attrs);
+                self.push_style(style.with(Attribute::Underlined));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush();
+                self.gap = true;
+            }
+            TagEnd::Heading(_) | TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.styles.pop();
+                if matches!(tag, TagEnd::BlockQuote(_)) {
+                    self.containers.pop();
+                }
+                self.gap = true;
+            }
+            TagEnd::CodeBlock => {
+                self.code_block = false;
+                self.gap = true;
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                self.gap = true;
+            }
+            TagEnd::Item => {
+                self.flush();
+                // an empty item still gets its marker
+                if let Some(Container::Item { first: true, .. }) = self.containers.last() {
+                    self.push_row(Vec::new(), false);
+                }
+                self.containers.pop();
+            }
+            TagEnd::Emphasis
+            | TagEnd::Strong
+            | TagEnd::Strikethrough
+            | TagEnd::Link
+            | TagEnd::Image => {
+                self.styles.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// inline text starting at byte `start` of the source. unless `exact`, the text isn't
+    /// what's in the source there and all of it points at `start`
+    fn text(&mut self, text: &str, style: Style, start: usize, exact: bool) {
+        for (i, grapheme) in text.grapheme_indices(true) {
+            let source = if exact { start + i } else { start };
+            let style = if self.links.iter().any(|link| link.contains(&source)) {
+                self.theme.link.with_all(style.attrs)
+            } else {
+                style
+            };
+            let grapheme = if grapheme == "\t" { " " } else { grapheme };
+            self.atoms.push(Atom::new(grapheme, style, Some(source)));
+        }
+    }
+
+    /// lines of a code block, cut to fit rather than wrapped, on the code background all the
+    /// way across
+    fn code_text(&mut self, text: &str, start: usize) {
+        let room = self.room();
+        let style = self.theme.code;
+        let mut offset = start;
+        for line in text.split_inclusive('\n') {
+            let mut atoms = Vec::new();
+            let mut used = 0;
+            for (i, grapheme) in line.trim_end_matches('\n').grapheme_indices(true) {
+                let (grapheme, width) = match grapheme {
+                    "\t" => ("    ", 4),
+                    _ => (grapheme, grapheme_width(grapheme)),
+                };
+                if used + width > room {
+                    self.push_row(std::mem::take(&mut atoms), false);
+                    used = 0;
+                }
+                atoms.push(Atom::new(grapheme, style, Some(offset + i)));
+                used += width;
+            }
+            atoms.push(Atom::new(
+                &" ".repeat(room.saturating_sub(used)),
+                style,
+                None,
+            ));
+            self.push_row(atoms, false);
+            offset += line.len();
+        }
+    }
+
+    /// cells left for text once the containers have put their markers in front
+    fn room(&self) -> usize {
+        let taken: usize = self
+            .containers
+            .iter()
+            .map(|container| match container {
+                Container::Quote => text::width(QUOTE_BAR),
+                Container::Item { marker, .. } => text::width(marker),
+            })
+            .sum();
+        self.width.saturating_sub(taken).max(1)
+    }
+
+    /// finish what came before and leave a blank row if a block ended just before
+    fn block_start(&mut self) {
+        self.flush();
+        if self.gap && !self.rows.is_empty() {
+            self.push_row(Vec::new(), true);
+        }
+        self.gap = false;
+    }
+
+    /// lay out the text of the block so far, breaking rows at spaces where it can
+    fn flush(&mut self) {
+        let room = self.room();
+        let mut row: Vec<Atom> = Vec::new();
+        let mut used = 0;
+        for atom in std::mem::take(&mut self.atoms) {
+            if atom.text == "\n" {
+                self.push_row(std::mem::take(&mut row), false);
+                used = 0;
+                continue;
+            }
+            let width = grapheme_width(&atom.text);
+            if used + width > room && !row.is_empty() {
+                let carry = match row.iter().rposition(|atom| atom.text == " ") {
+                    Some(i) => {
+                        let carry = row.split_off(i + 1);
+                        row.pop();
+                        carry
+                    }
+                    None => Vec::new(),
+                };
+                self.push_row(std::mem::replace(&mut row, carry), false);
+                used = row.iter().map(|atom| grapheme_width(&atom.text)).sum();
+                if row.is_empty() && atom.text == " " {
+                    continue;
+                }
+            }
+            used += width;
+            row.push(atom);
+        }
+        if !row.is_empty() {
+            self.push_row(row, false);
+        }
+    }
+
+    /// add a row of `atoms` behind the containers' markers. a `blank` row between blocks
+    /// leaves list markers for the row that has text
+    fn push_row(&mut self, atoms: Vec<Atom>, blank: bool) {
+        let mut prefix = Vec::new();
+        for container in &mut self.containers {
+            match container {
+                Container::Quote => prefix.push(Atom::new(QUOTE_BAR, self.theme.quote, None)),
+                Container::Item { marker, first } => {
+                    let text = if *first && !blank {
+                        marker.clone()
+                    } else {
+                        " ".repeat(text::width(marker))
+                    };
+                    *first &= blank;
+                    prefix.push(Atom::new(&text, self.theme.bullet, None));
+                }
+            }
+        }
+
+        let mut row = Row::default();
+        let mut x = 0;
+        for atom in prefix.into_iter().chain(atoms) {
+            if let Some(source) = atom.source {
+                row.sources.push((x, source));
+            }
+            x += text::width(&atom.text);
+            match row.line.last_mut() {
+                Some(last) if last.style == atom.style => last.text.push_str(&atom.text),
+                _ => row.line.push(Span::new(atom.text, atom.style)),
+            }
+        }
+        self.rows.push(row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(rows: &[Row]) -> Vec<String> {
+        rows.iter()
+            .map(|row| row.line.iter().map(|span| span.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn blocks() {
+        let theme = Theme::dark();
+        let source = "# Title\n\nsome *text* here\n\n- one\n- [x] two\n  1. nested\n\n> quoted\n\n---\n\n```\nlet x;\n```\n";
+        let rows = render(source, 12, &theme);
+        assert_eq!(
+            text(&rows),
+            vec![
+                "Title",
+                "",
+                "some text",
+                "here",
+                "",
+                "• one",
+                "• [x] two",
+                "  1. nested",
+                "",
+                "│ quoted",
+                "",
+                "────────────",
+                "",
+                "let x;      ",
+            ]
+        );
+        assert_eq!(
+            rows[0].line[0].style,
+            theme
+                .heading
+                .with(Attribute::Bold)
+                .with(Attribute::Underlined)
+        );
+        assert!(rows[2].line[1].style.attrs.has(Attribute::Italic));
+        assert_eq!(rows[13].line[0].style, theme.code);
+
+        // the checkbox counts its full width when a task wraps
+        let rows = render("- [ ] abcdefgh ij\n", 12, &theme);
+        assert_eq!(text(&rows), vec!["• [ ]", "  abcdefgh", "  ij"]);
+        assert!(text(&rows).iter().all(|row| text::width(row) <= 12));
+        let rows = render("- [x] abc def\n", 12, &theme);
+        assert_eq!(text(&rows), vec!["• [x] abc", "  def"]);
+    }
+
+    #[test]
+    fn sources() {
+        let theme = Theme::dark();
+        let source = "# Head\n\nsee [[a link]]";
+        let rows = render(source, 40, &theme);
+        // the cursor on the "#" shows on the heading's text, the one on "see" where it is
+        assert_eq!(locate(&rows, 0), (0, 0));
+        assert_eq!(locate(&rows, 9), (2, 1));
+        assert_eq!(rows[2].grapheme_at(1), "e");
+        assert_eq!(rows[2].line[1].style, theme.link);
+    }
+
+    #[test]
+    fn clicks() {
+        let theme = Theme::dark();
+        let source = "# Head\n\nsee [[a link]]";
+        let mut rows = render(source, 40, &theme);
+        assert_eq!(source_at(&rows, 0, 1), Some(3));
+        // past the end of the text, and on the blank row between the blocks
+        assert_eq!(source_at(&rows, 0, 30), Some(6));
+        assert_eq!(source_at(&rows, 1, 5), Some(8));
+        assert_eq!(source_at(&rows, 9, 0), Some(21));
+
+        rows[2].select(&(9..11), theme.selection);
+        assert_eq!(rows[2].line[1], Span::new("ee", theme.selection));
+    }
+}
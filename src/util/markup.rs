@@ -0,0 +1,266 @@
+/*
+ * src/util/markup.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// light highlighting for markdown source as it's edited: headings, `*emphasis*`, `**strong**`
+/// or `_emphasis_` spans, `code`, list markers, quotes and rules. it goes a line at a time and
+/// the markers stay in the text, only how they look changes
+use crossterm::style::{Attribute, Attributes};
+
+/// a styled stretch of one line. `start..end` are char offsets covering the markers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleSpan {
+    pub start: usize,
+    pub end: usize,
+    pub attrs: Attributes,
+}
+
+/// what a stretch of source is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    Heading(Attributes),
+    Emphasis(Attributes),
+    Code,
+    /// a list marker or checkbox
+    Bullet,
+    Quote,
+    Rule,
+}
+
+/// a highlighted stretch of one line, in char offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+    pub markup: Markup,
+}
+
+/// whether `line` opens or closes a fenced code block
+pub fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
+
+/// which of `lines` are in fenced code blocks, the fences included
+pub fn fenced(lines: &[String]) -> Vec<bool> {
+    let mut in_code = false;
+    lines
+        .iter()
+        .map(|line| {
+            let fence = is_fence(line);
+            let fenced = in_code || fence;
+            in_code ^= fence;
+            fenced
+        })
+        .collect()
+}
+
+/// whether `line` is a horizontal rule, three or more of the same `-`, `*` or `_`
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && matches!(chars[0], '-' | '*' | '_') && chars.iter().all(|c| *c == chars[0])
+}
+
+/// highlights for `line`, later ones going over earlier ones. `in_code` is for lines of a
+/// fenced code block, the fences included
+pub fn highlight(line: &str, in_code: bool) -> Vec<Highlight> {
+    let chars: Vec<char> = line.chars().collect();
+    let whole = |markup| Highlight {
+        start: 0,
+        end: chars.len(),
+        markup,
+    };
+    if in_code {
+        return vec![whole(Markup::Code)];
+    }
+    if is_rule(line) {
+        return vec![whole(Markup::Rule)];
+    }
+    let mut highlights = Vec::new();
+    if let Some(attrs) = heading_attrs(line) {
+        highlights.push(whole(Markup::Heading(attrs)));
+    }
+
+    let mut i = chars.iter().take_while(|c| c.is_whitespace()).count();
+    if chars.get(i) == Some(&'>') {
+        highlights.push(whole(Markup::Quote));
+        while matches!(chars.get(i), Some('>' | ' ')) {
+            i += 1;
+        }
+    }
+    let digits = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let marker = match chars.get(i + digits) {
+        Some('-' | '*' | '+') if digits == 0 => 1,
+        Some('.' | ')') if digits > 0 => digits + 1,
+        _ => 0,
+    };
+    if marker > 0 && chars.get(i + marker) == Some(&' ') {
+        let mut end = i + marker;
+        let rest: String = chars[end + 1..].iter().take(3).collect();
+        if matches!(rest.as_str(), "[ ]" | "[x]" | "[X]") {
+            end += 4;
+        }
+        highlights.push(Highlight {
+            start: i,
+            end,
+            markup: Markup::Bullet,
+        });
+    }
+
+    let mut j = 0;
+    while j < chars.len() {
+        if chars[j] != '`' {
+            j += 1;
+            continue;
+        }
+        match chars[j + 1..].iter().position(|c| *c == '`') {
+            Some(n) => {
+                highlights.push(Highlight {
+                    start: j,
+                    end: j + n + 2,
+                    markup: Markup::Code,
+                });
+                j += n + 2;
+            }
+            None => break,
+        }
+    }
+    highlights.extend(find_emphasis(line).into_iter().map(|span| Highlight {
+        start: span.start,
+        end: span.end,
+        markup: Markup::Emphasis(span.attrs),
+    }));
+    highlights
+}
+
+/// attributes for the whole of `line` if it's a heading: bold, and underlined for the top level
+pub fn heading_attrs(line: &str) -> Option<Attributes> {
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let mut attrs = Attributes::from(Attribute::Bold);
+    if level == 1 {
+        attrs.set(Attribute::Underlined);
+    }
+    Some(attrs)
+}
+
+/// emphasis spans in `line`, in order. unclosed markers and the insides of `code` are left
+/// alone, and so are underscores inside words like snake_case
+pub fn find_emphasis(line: &str) -> Vec<StyleSpan> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let marker: &[char] = match chars[i] {
+            '`' => {
+                // skip code spans whole
+                match chars[i + 1..].iter().position(|c| *c == '`') {
+                    Some(n) => i += n + 2,
+                    None => i += 1,
+                }
+                continue;
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => &['*', '*'],
+            '*' => &['*'],
+            '_' if i == 0 || !chars[i - 1].is_alphanumeric() => &['_'],
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let inner = i + marker.len();
+        let close = (inner + 1..chars.len()).find(|&j| {
+            chars[j..].starts_with(marker)
+                && !chars[j - 1].is_whitespace()
+                && (marker[0] != '_' || chars.get(j + 1).is_none_or(|c| !c.is_alphanumeric()))
+        });
+        match close {
+            Some(j) if !chars[inner].is_whitespace() => {
+                let attr = match marker.len() {
+                    2 => Attribute::Bold,
+                    _ => Attribute::Italic,
+                };
+                spans.push(StyleSpan {
+                    start: i,
+                    end: j + marker.len(),
+                    attrs: attr.into(),
+                });
+                i = j + marker.len();
+            }
+            _ => i += marker.len(),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(line: &str) -> Vec<(usize, usize, bool)> {
+        find_emphasis(line)
+            .into_iter()
+            .map(|span| (span.start, span.end, span.attrs.has(Attribute::Bold)))
+            .collect()
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(spans("a *b* c"), vec![(2, 5, false)]);
+        assert_eq!(
+            spans("**bold** and _it_"),
+            vec![(0, 8, true), (13, 17, false)]
+        );
+        assert_eq!(spans("snake_case_name"), vec![]);
+        assert_eq!(spans("2 * 3 * 4"), vec![]);
+        assert_eq!(spans("`*not*` *yes*"), vec![(8, 13, false)]);
+        assert_eq!(spans("*unclosed"), vec![]);
+    }
+
+    #[test]
+    fn highlights() {
+        let kinds = |line: &str| -> Vec<(usize, usize, Markup)> {
+            highlight(line, false)
+                .into_iter()
+                .map(|h| (h.start, h.end, h.markup))
+                .collect()
+        };
+        assert_eq!(
+            kinds("- [x] done `x`"),
+            vec![(0, 5, Markup::Bullet), (11, 14, Markup::Code)]
+        );
+        assert_eq!(kinds("  12. twelve"), vec![(2, 5, Markup::Bullet)]);
+        assert_eq!(kinds("> quote"), vec![(0, 7, Markup::Quote)]);
+        assert_eq!(kinds("* * *"), vec![(0, 5, Markup::Rule)]);
+        assert_eq!(kinds("-not a list"), vec![]);
+        assert_eq!(highlight("# not a heading", true)[0].markup, Markup::Code);
+        assert!(is_fence("  ```rust"));
+    }
+
+    #[test]
+    fn headings() {
+        assert!(heading_attrs("# Title").is_some_and(|a| a.has(Attribute::Underlined)));
+        assert!(heading_attrs("### sub").is_some_and(|a| !a.has(Attribute::Underlined)));
+        assert!(heading_attrs("#hashtag").is_none());
+        assert!(heading_attrs("####### seven").is_none());
+    }
+}
@@ -0,0 +1,65 @@
+/*
+ * src/util/message.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// short lived feedback shown in the status line, e.g. "saved" or why a command failed
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    /// how long a message of this level stays up. the worse it is, the longer
+    pub fn timeout(&self) -> Duration {
+        match self {
+            Level::Info => Duration::from_secs(3),
+            Level::Warn => Duration::from_secs(5),
+            Level::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub text: String,
+    pub level: Level,
+    pub shown: Instant,
+}
+
+impl Message {
+    pub fn new(level: Level, text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            level,
+            shown: Instant::now(),
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.shown.elapsed() >= self.level.timeout()
+    }
+
+    /// when the message is due to go
+    pub fn expires_at(&self) -> Instant {
+        self.shown + self.level.timeout()
+    }
+}
@@ -17,10 +17,26 @@
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod backend;
 pub mod command;
 pub mod command_bar;
+pub mod completion;
 pub mod config;
 pub mod constant;
+pub mod history;
+pub mod hooks;
+pub mod keymap;
+pub mod layout;
+pub mod markdown;
+pub mod markup;
+pub mod message;
 pub mod mode;
+pub mod parser;
+pub mod prompt;
+pub mod registers;
+pub mod template;
 pub mod term;
+pub mod text;
+pub mod theme;
 pub mod util;
+pub mod widget;
@@ -28,6 +28,7 @@ pub enum OpenMode {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ModeT {
     BROWSE,
+    CALENDAR,
     OPEN(OpenMode),
 }
 
@@ -35,6 +36,7 @@ impl fmt::Display for ModeT {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ModeT::BROWSE => write!(f, "BROWSE"),
+            ModeT::CALENDAR => write!(f, "CALENDAR"),
             ModeT::OPEN(open_mode) => write!(f, "OPEN({})", open_mode),
         }
     }
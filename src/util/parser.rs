@@ -0,0 +1,559 @@
+/*
+ * src/util/parser.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// the grammar of the command bar, roughly vim's:
+///
+/// ```text
+/// [range] name[!] {argument | key=value}
+/// ```
+///
+/// a range is `N`, `N,M`, `%` (everything), with `.` for the selected entry and `$` for the last
+/// one, e.g. `:1,5delete` or `:.,$delete`. arguments are split on whitespace, unless quoted with
+/// "..." (which understands backslash escapes) or '...' (which doesn't). outside of quotes a
+/// backslash escapes the next char. an unquoted `=` after a plain word makes the token an option.
+/// positions in errors are char offsets into the input, for pointing at the problem
+use std::fmt;
+
+/// a single place in the entry list, as written in a range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    Number(usize), // 1-based list position
+    Current,       // `.`, the selected entry
+    Last,          // `$`, the last entry
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Address,
+    pub end: Address,
+}
+
+/// a positional argument or option value, with where it started in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arg {
+    pub value: String,
+    pub pos: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCommand {
+    pub range: Option<Range>,
+    pub name: String,
+    pub name_pos: usize,
+    pub bang: bool,
+    pub args: Vec<Arg>,
+    pub options: Vec<(String, Arg)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(pos: usize, message: &str) -> Self {
+        Self {
+            pos,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "col {}: {}", self.pos + 1, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Range {
+    /// the 0-based, inclusive list positions the range covers, given the selected position and the
+    /// length of the list
+    pub fn resolve(&self, selected: usize, len: usize) -> Result<(usize, usize), String> {
+        let resolve = |address: Address| match address {
+            Address::Number(n) => n - 1,
+            Address::Current => selected,
+            Address::Last => len.saturating_sub(1),
+        };
+        let (start, end) = (resolve(self.start), resolve(self.end));
+        if len == 0 || end >= len {
+            return Err(format!("there are only {} entries", len));
+        }
+        if start > end {
+            return Err(String::from("backwards range"));
+        }
+        Ok((start, end))
+    }
+}
+
+impl ParsedCommand {
+    /// every positional argument, joined back together with single spaces
+    pub fn joined_args(&self) -> String {
+        self.args
+            .iter()
+            .map(|arg| arg.value.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn option(&self, key: &str) -> Option<&Arg> {
+        self.options.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// fails on the first option that isn't one of `allowed`
+    pub fn allow_options(&self, allowed: &[&str]) -> Result<(), ParseError> {
+        match self
+            .options
+            .iter()
+            .find(|(k, _)| !allowed.contains(&k.as_str()))
+        {
+            Some((key, value)) => Err(ParseError::new(
+                value.pos.saturating_sub(key.chars().count() + 1),
+                &format!("{} doesn't take the option '{}'", self.name, key),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn forbid_bang(&self) -> Result<(), ParseError> {
+        if self.bang {
+            let pos = self.name_pos + self.name.chars().count();
+            return Err(ParseError::new(
+                pos,
+                &format!("{} doesn't take a !", self.name),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn forbid_range(&self) -> Result<(), ParseError> {
+        if self.range.is_some() {
+            return Err(ParseError::new(
+                0,
+                &format!("{} doesn't take a range", self.name),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn max_args(&self, n: usize) -> Result<(), ParseError> {
+        match self.args.get(n) {
+            Some(arg) => Err(ParseError::new(arg.pos, "too many arguments")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// parse one line of the command bar
+pub fn parse(input: &str) -> Result<ParsedCommand, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut pos = skip_whitespace(&chars, 0);
+    if chars.get(pos) == Some(&':') {
+        pos = skip_whitespace(&chars, pos + 1);
+    }
+    if pos >= chars.len() {
+        return Err(ParseError::new(pos, "empty command"));
+    }
+
+    let (range, after_range) = parse_range(&chars, pos)?;
+    pos = skip_whitespace(&chars, after_range);
+
+    let name_pos = pos;
+    while pos < chars.len() && chars[pos].is_ascii_alphabetic() {
+        pos += 1;
+    }
+    let name: String = chars[name_pos..pos]
+        .iter()
+        .collect::<String>()
+        .to_lowercase();
+    if name.is_empty() && pos < chars.len() {
+        return Err(ParseError::new(pos, "expected a command name"));
+    }
+
+    let bang = chars.get(pos) == Some(&'!');
+    if bang {
+        if name.is_empty() {
+            return Err(ParseError::new(pos, "! needs a command"));
+        }
+        pos += 1;
+    }
+    if pos < chars.len() && !chars[pos].is_whitespace() {
+        return Err(ParseError::new(
+            pos,
+            "expected a space after the command name",
+        ));
+    }
+
+    let mut args = Vec::new();
+    let mut options = Vec::new();
+    for token in tokenize(&chars, pos)? {
+        match token.key {
+            Some(key) => options.push((
+                key,
+                Arg {
+                    value: token.value,
+                    pos: token.value_pos,
+                },
+            )),
+            None => args.push(Arg {
+                value: token.value,
+                pos: token.pos,
+            }),
+        }
+    }
+
+    Ok(ParsedCommand {
+        range,
+        name,
+        name_pos,
+        bang,
+        args,
+        options,
+    })
+}
+
+fn skip_whitespace(chars: &[char], mut pos: usize) -> usize {
+    while pos < chars.len() && chars[pos].is_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+/// a range at the start of the command, if there is one. returns where it ended
+fn parse_range(chars: &[char], pos: usize) -> Result<(Option<Range>, usize), ParseError> {
+    if chars.get(pos) == Some(&'%') {
+        let range = Range {
+            start: Address::Number(1),
+            end: Address::Last,
+        };
+        return Ok((Some(range), pos + 1));
+    }
+    let Some((start, mut pos)) = parse_address(chars, pos)? else {
+        return Ok((None, pos));
+    };
+    let mut end = start;
+    if chars.get(pos) == Some(&',') {
+        match parse_address(chars, pos + 1)? {
+            Some((address, after)) => {
+                end = address;
+                pos = after;
+            }
+            None => return Err(ParseError::new(pos + 1, "expected the end of the range")),
+        }
+    }
+    Ok((Some(Range { start, end }), pos))
+}
+
+fn parse_address(chars: &[char], pos: usize) -> Result<Option<(Address, usize)>, ParseError> {
+    match chars.get(pos) {
+        Some('.') => Ok(Some((Address::Current, pos + 1))),
+        Some('$') => Ok(Some((Address::Last, pos + 1))),
+        Some(c) if c.is_ascii_digit() => {
+            let mut end = pos;
+            while end < chars.len() && chars[end].is_ascii_digit() {
+                end += 1;
+            }
+            let digits: String = chars[pos..end].iter().collect();
+            match digits.parse::<usize>() {
+                Ok(0) => Err(ParseError::new(pos, "positions start at 1")),
+                Ok(n) => Ok(Some((Address::Number(n), end))),
+                Err(_) => Err(ParseError::new(pos, "position is too large")),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// one whitespace-separated word of the arguments, after quotes and escapes are resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    value: String,
+    pos: usize,
+    key: Option<String>, // set when the token is `key=value`, `value` is then just the value
+    value_pos: usize,
+}
+
+fn tokenize(chars: &[char], mut pos: usize) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    loop {
+        pos = skip_whitespace(chars, pos);
+        if pos >= chars.len() {
+            return Ok(tokens);
+        }
+
+        let start = pos;
+        let mut value = String::new();
+        let mut key = None;
+        let mut value_pos = start;
+        let mut quoted = false; // anything quoted or escaped so far, which rules out a key
+
+        while pos < chars.len() && !chars[pos].is_whitespace() {
+            match chars[pos] {
+                '"' => {
+                    let open = pos;
+                    pos += 1;
+                    loop {
+                        match chars.get(pos) {
+                            None => return Err(ParseError::new(open, "unterminated \"")),
+                            Some('"') => break,
+                            Some('\\') => {
+                                let escaped = chars
+                                    .get(pos + 1)
+                                    .ok_or_else(|| ParseError::new(open, "unterminated \""))?;
+                                value.push(unescape(*escaped));
+                                pos += 2;
+                            }
+                            Some(c) => {
+                                value.push(*c);
+                                pos += 1;
+                            }
+                        }
+                    }
+                    pos += 1;
+                    quoted = true;
+                }
+                '\'' => {
+                    let open = pos;
+                    pos += 1;
+                    while chars.get(pos) != Some(&'\'') {
+                        match chars.get(pos) {
+                            None => return Err(ParseError::new(open, "unterminated '")),
+                            Some(c) => value.push(*c),
+                        }
+                        pos += 1;
+                    }
+                    pos += 1;
+                    quoted = true;
+                }
+                '\\' => {
+                    let escaped = chars
+                        .get(pos + 1)
+                        .ok_or_else(|| ParseError::new(pos, "nothing to escape"))?;
+                    value.push(unescape(*escaped));
+                    pos += 2;
+                    quoted = true;
+                }
+                '=' if key.is_none() && !quoted && is_option_key(&value) => {
+                    key = Some(std::mem::take(&mut value));
+                    value_pos = pos + 1;
+                    pos += 1;
+                }
+                c => {
+                    value.push(c);
+                    pos += 1;
+                }
+            }
+        }
+
+        tokens.push(Token {
+            value,
+            pos: start,
+            key,
+            value_pos,
+        });
+    }
+}
+
+fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        c => c,
+    }
+}
+
+/// option keys are plain words: a letter or `_`, then letters, digits, `_` or `-`
+fn is_option_key(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a compact rendering of a parse result, so each case fits on a line
+    fn show(result: Result<ParsedCommand, ParseError>) -> String {
+        let parsed = match result {
+            Ok(parsed) => parsed,
+            Err(e) => return format!("err@{}: {}", e.pos, e.message),
+        };
+        let address = |a: Address| match a {
+            Address::Number(n) => n.to_string(),
+            Address::Current => String::from("."),
+            Address::Last => String::from("$"),
+        };
+        let mut out = String::new();
+        if let Some(range) = parsed.range {
+            out.push_str(&format!(
+                "[{},{}]",
+                address(range.start),
+                address(range.end)
+            ));
+        }
+        out.push_str(&parsed.name);
+        if parsed.bang {
+            out.push('!');
+        }
+        for arg in &parsed.args {
+            out.push_str(&format!(" <{}>", arg.value));
+        }
+        for (key, value) in &parsed.options {
+            out.push_str(&format!(" {}=<{}>", key, value.value));
+        }
+        out
+    }
+
+    #[test]
+    fn parses_commands() {
+        let cases = [
+            // plain names and arguments
+            ("w", "w"),
+            (":w", "w"),
+            ("  save  ", "save"),
+            ("Quit", "quit"),
+            ("add My new entry", "add <My> <new> <entry>"),
+            ("add   spaced    out", "add <spaced> <out>"),
+            // bang
+            ("q!", "q!"),
+            ("delete! abc", "delete! <abc>"),
+            // quoting
+            ("add \"My new entry\"", "add <My new entry>"),
+            ("add 'single quoted'", "add <single quoted>"),
+            ("add \"\"", "add <>"),
+            ("add a\"b c\"d", "add <ab cd>"),
+            ("add 'no \\escapes'", "add <no \\escapes>"),
+            ("add \"say \\\"hi\\\"\"", "add <say \"hi\">"),
+            ("add \"tab\\there\"", "add <tab\there>"),
+            // escapes outside quotes
+            ("add two\\ words", "add <two words>"),
+            ("add back\\\\slash", "add <back\\slash>"),
+            ("add line\\nbreak", "add <line\nbreak>"),
+            // options
+            ("add entry tags=a,b", "add <entry> tags=<a,b>"),
+            (
+                "new daily label=\"Monday notes\"",
+                "new <daily> label=<Monday notes>",
+            ),
+            ("add x=", "add x=<>"),
+            ("add a=b=c", "add a=<b=c>"),
+            ("add \"a=b\"", "add <a=b>"),
+            ("add a\\=b", "add <a=b>"),
+            ("add =b", "add <=b>"),
+            ("add 1x=2", "add <1x=2>"),
+            ("add my-key=v", "add my-key=<v>"),
+            // ranges
+            ("1,5delete", "[1,5]delete"),
+            ("3delete", "[3,3]delete"),
+            ("1, 5delete", "err@2: expected the end of the range"),
+            (".,$delete", "[.,$]delete"),
+            ("%delete", "[1,$]delete"),
+            ("2 , 3 delete", "err@2: expected a command name"),
+            ("2,3 delete", "[2,3]delete"),
+            ("7", "[7,7]"),
+            ("$", "[$,$]"),
+            // errors
+            ("", "err@0: empty command"),
+            ("   ", "err@3: empty command"),
+            (":", "err@1: empty command"),
+            ("0delete", "err@0: positions start at 1"),
+            ("1,delete", "err@2: expected the end of the range"),
+            ("1,0delete", "err@2: positions start at 1"),
+            (
+                "99999999999999999999999delete",
+                "err@0: position is too large",
+            ),
+            ("add \"open", "err@4: unterminated \""),
+            ("add 'open", "err@4: unterminated '"),
+            ("add \"esc\\", "err@4: unterminated \""),
+            ("add trailing\\", "err@12: nothing to escape"),
+            ("w2", "err@1: expected a space after the command name"),
+            ("q!!", "err@2: expected a space after the command name"),
+            ("!", "err@0: expected a command name"),
+            ("5!", "err@1: expected a command name"),
+            ("#foo", "err@0: expected a command name"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(show(parse(input)), expected, "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn records_positions() {
+        let parsed = parse("  new daily label=\"x y\" more").unwrap();
+        assert_eq!(parsed.name_pos, 2);
+        assert_eq!(parsed.args[0].pos, 6);
+        assert_eq!(parsed.options[0].1.pos, 18);
+        assert_eq!(parsed.args[1].pos, 24);
+    }
+
+    #[test]
+    fn validates_commands() {
+        let cases = [
+            ("rename! x", "err@6: rename doesn't take a !"),
+            ("1,2rename x", "err@0: rename doesn't take a range"),
+            (
+                "rename x foo=bar",
+                "err@9: rename doesn't take the option 'foo'",
+            ),
+            ("rename x", "ok"),
+        ];
+        for (input, expected) in cases {
+            let parsed = parse(input).unwrap();
+            let result = parsed
+                .forbid_bang()
+                .and_then(|_| parsed.forbid_range())
+                .and_then(|_| parsed.allow_options(&[]));
+            let shown = match result {
+                Ok(()) => String::from("ok"),
+                Err(e) => format!("err@{}: {}", e.pos, e.message),
+            };
+            assert_eq!(shown, expected, "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn resolves_ranges() {
+        let range = |start, end| Range { start, end };
+        let cases = [
+            (
+                range(Address::Number(1), Address::Number(3)),
+                0,
+                5,
+                Ok((0, 2)),
+            ),
+            (range(Address::Current, Address::Last), 2, 5, Ok((2, 4))),
+            (range(Address::Number(2), Address::Number(9)), 0, 5, Err(())),
+            (range(Address::Number(4), Address::Number(2)), 0, 5, Err(())),
+            (range(Address::Current, Address::Current), 0, 0, Err(())),
+        ];
+        for (range, selected, len, expected) in cases {
+            assert_eq!(
+                range.resolve(selected, len).map_err(|_| ()),
+                expected,
+                "{:?}",
+                range
+            );
+        }
+    }
+}
@@ -0,0 +1,46 @@
+/*
+ * src/util/prompt.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// what happens when the user answers yes to a prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromptAction {
+    SaveAndClose,               // write the open entry, then close it
+    SaveAndOpen(String),        // write the open entry, then open the one with this id
+    DeleteEntries(Vec<String>), // delete the entries with these ids
+    RelinkRenamed {
+        old_label: String,
+        new_label: String,
+    }, // point links at a renamed entry
+}
+
+/// a yes/no question shown over the screen. while one is up it takes every key press
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub question: String,
+    pub action: PromptAction,
+}
+
+impl Prompt {
+    pub fn new(question: &str, action: PromptAction) -> Self {
+        Self {
+            question: format!("{} (y/n)", question),
+            action,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+/*
+ * src/util/registers.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// macro registers, kept in ~/.config/rnbook/registers.json between sessions. a register holds a
+/// key sequence written the way the keymap writes keys, e.g. `dd<Down>`
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use crate::util::{
+    config::config_dir,
+    keymap::{keys_to_string, parse_keys, KeyChord},
+    util::write_atomic,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    pub macros: BTreeMap<char, String>,
+}
+
+impl Registers {
+    pub fn path() -> io::Result<PathBuf> {
+        let mut p = config_dir()?;
+        p.push("registers.json");
+        Ok(p)
+    }
+
+    /// read the saved registers. a missing or broken file is just no registers
+    pub fn load() -> Self {
+        let macros = Self::path()
+            .and_then(fs::read_to_string)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self { macros }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&path, &serde_json::to_vec_pretty(&self.macros)?)
+    }
+
+    pub fn set(&mut self, register: char, keys: &[KeyChord]) {
+        self.macros.insert(register, keys_to_string(keys));
+    }
+
+    pub fn get(&self, register: char) -> Option<Vec<KeyChord>> {
+        self.macros
+            .get(&register)
+            .and_then(|keys| parse_keys(keys).ok())
+    }
+}
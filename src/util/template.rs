@@ -0,0 +1,259 @@
+/*
+ * src/util/template.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use chrono::{Datelike, Local};
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::{Component, Path},
+};
+
+use crate::util::{config::config_dir, constant::DATE_FORMAT};
+
+/// an entry template, stored as a plain text file in the templates directory.
+/// a template may start with a header fenced by `---` lines that sets the default label and tags:
+///
+/// ```text
+/// ---
+/// label: {{weekday}} {{date}}
+/// tags: daily, journal
+/// ---
+/// # {{date}}
+/// {{prompt}}
+/// ```
+///
+/// `{{date}}`, `{{time}}`, `{{weekday}}` and `{{prompt}}` are filled in when the entry is created.
+/// `{{prompt}}` doesn't ask anything: it's a journaling prompt to write about, one line of
+/// prompts.txt in the config directory picked by the day of the year, so it changes daily and
+/// stays the same all day. without prompts.txt it's left empty
+#[derive(Debug, Clone, Default)]
+pub struct Template {
+    pub name: String,
+    pub label: Option<String>,
+    pub tags: Vec<String>,
+    pub body: String,
+}
+
+impl Template {
+    /// load `<name>`, `<name>.md` or `<name>.txt` from the templates directory. the name can't
+    /// reach outside it
+    pub fn load(templates_path: &Path, name: &str) -> Result<Self, Error> {
+        let mut components = Path::new(name).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("'{}' isn't a template name", name),
+            ));
+        }
+        let candidates = [
+            templates_path.join(name),
+            templates_path.join(format!("{}.md", name)),
+            templates_path.join(format!("{}.txt", name)),
+        ];
+        let path = candidates.iter().find(|p| p.is_file()).ok_or_else(|| {
+            Error::new(ErrorKind::NotFound, format!("no template named '{}'", name))
+        })?;
+        Ok(Self::parse(name, &fs::read_to_string(path)?))
+    }
+
+    /// split the optional header off the template text
+    pub fn parse(name: &str, text: &str) -> Self {
+        let mut template = Template {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        // a header that's never closed is taken for part of the body
+        let mut lines = text.lines();
+        let closed = text.lines().skip(1).any(|line| line.trim() == "---");
+        if closed && text.lines().next().map(str::trim) == Some("---") {
+            lines.next();
+            for line in lines.by_ref() {
+                if line.trim() == "---" {
+                    break;
+                }
+                match line.split_once(':') {
+                    Some((key, value)) if key.trim() == "label" => {
+                        template.label = Some(value.trim().to_string())
+                    }
+                    Some((key, value)) if key.trim() == "tags" => {
+                        template.tags = value
+                            .split(',')
+                            .map(|tag| tag.trim().to_string())
+                            .filter(|tag| !tag.is_empty())
+                            .collect()
+                    }
+                    _ => {}
+                }
+            }
+            template.body = lines.collect::<Vec<_>>().join("\n");
+        } else {
+            template.body = text.to_string();
+        }
+        template
+    }
+
+    /// the template body with its placeholders filled in
+    pub fn render_body(&self) -> String {
+        fill_placeholders(&self.body)
+    }
+
+    /// the label for a new entry: `label` if given, else the template's own label, else the
+    /// template name and today's date
+    pub fn render_label(&self, label: Option<&str>) -> String {
+        match (label, &self.label) {
+            (Some(label), _) => label.to_string(),
+            (None, Some(template_label)) => fill_placeholders(template_label),
+            (None, None) => format!("{} {}", self.name, Local::now().format(DATE_FORMAT)),
+        }
+    }
+}
+
+/// replace every known `{{placeholder}}` in `text`, anything else is left as is
+pub fn fill_placeholders(text: &str) -> String {
+    let now = Local::now();
+    fill(text, |name| match name {
+        "date" => Some(now.format(DATE_FORMAT).to_string()),
+        "time" => Some(now.format("%H:%M").to_string()),
+        "weekday" => Some(now.format("%A").to_string()),
+        "prompt" => Some(daily_prompt(now.ordinal0() as usize)),
+        _ => None,
+    })
+}
+
+/// `text` with each `{{name}}` replaced by `value(name)`. names `value` doesn't know and a `{{`
+/// that's never closed stay as they are, and filled in values aren't looked at again
+fn fill(text: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let filled = after
+            .find("}}")
+            .and_then(|end| Some((end, value(after[..end].trim())?)));
+        match filled {
+            Some((end, filled)) => {
+                out.push_str(&filled);
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// one line out of prompts.txt in the config directory, the same one all day
+fn daily_prompt(day: usize) -> String {
+    let Ok(mut path) = config_dir() else {
+        return String::new();
+    };
+    path.push("prompts.txt");
+    pick_prompt(&fs::read_to_string(path).unwrap_or_default(), day)
+}
+
+/// the non-blank line of `prompts` for day `day`, going round when there are fewer lines
+fn pick_prompt(prompts: &str, day: usize) -> String {
+    let prompts: Vec<&str> = prompts
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if prompts.is_empty() {
+        String::new()
+    } else {
+        prompts[day % prompts.len()].to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        let value = |name: &str| match name {
+            "date" => Some(String::from("2026/01/02")),
+            "prompt" => Some(String::from("what went well? {{date}}")),
+            _ => None,
+        };
+        let cases = [
+            ("# {{date}}", "# 2026/01/02"),
+            ("{{ date }}, {{date}}", "2026/01/02, 2026/01/02"),
+            ("{{prompt}}\n", "what went well? {{date}}\n"),
+            ("{{nope}} and {{date}}", "{{nope}} and 2026/01/02"),
+            ("unclosed {{date", "unclosed {{date"),
+            ("{{{{date}}", "{{2026/01/02"),
+            ("}} {{}}", "}} {{}}"),
+        ];
+        for (text, filled) in cases {
+            assert_eq!(fill(text, value), filled, "filling {:?}", text);
+        }
+    }
+
+    #[test]
+    fn prompts() {
+        let prompts = "first\n\n  second  \n";
+        assert_eq!(pick_prompt(prompts, 0), "first");
+        assert_eq!(pick_prompt(prompts, 3), "second");
+        assert_eq!(pick_prompt("", 3), "");
+    }
+
+    #[test]
+    fn header() {
+        let template = Template::parse(
+            "daily",
+            "---\nlabel: {{weekday}} {{date}}\ntags: daily, , journal\n---\n# {{date}}\n{{prompt}}",
+        );
+        assert_eq!(template.label.as_deref(), Some("{{weekday}} {{date}}"));
+        assert_eq!(template.tags, ["daily", "journal"]);
+        assert_eq!(template.body, "# {{date}}\n{{prompt}}");
+
+        // no header, or one that's never closed, is all body
+        assert_eq!(Template::parse("plain", "# {{date}}").body, "# {{date}}");
+        let unclosed = Template::parse("broken", "---\nlabel: x");
+        assert_eq!(unclosed.label, None);
+        assert_eq!(unclosed.body, "---\nlabel: x");
+    }
+
+    #[test]
+    fn loads_by_name() {
+        let dir = std::env::temp_dir().join(format!("rnbook-templates-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("daily.md"), "# {{date}}\n").unwrap();
+        fs::write(dir.join("sub").join("inner.md"), "inner\n").unwrap();
+        assert_eq!(Template::load(&dir, "daily").unwrap().body, "# {{date}}\n");
+        assert_eq!(
+            Template::load(&dir, "missing").unwrap_err().to_string(),
+            "no template named 'missing'"
+        );
+        for name in ["", ".", "..", "../daily", "sub/inner", "/etc/passwd"] {
+            let error = Template::load(&dir, name).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "name: {:?}", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
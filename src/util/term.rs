@@ -16,182 +16,458 @@
  * You should have received a copy of the GNU General Public License
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
-use crossterm::{
-    cursor, execute,
-    style::{Color, ResetColor, SetBackgroundColor, SetForegroundColor},
-    terminal::{self},
-};
+use crossterm::style::{Attribute, Attributes, Color};
+
+use std::{fmt, io};
+use unicode_segmentation::UnicodeSegmentation;
 
-use std::{
-    collections::{HashMap, HashSet},
-    io::Write,
+use crate::util::{
+    backend::Backend,
+    text::{char_width, grapheme_width},
 };
 
-#[derive(Debug, Clone)]
-pub struct DoubleBuffer {
-    front_buffer: HashMap<(usize, usize), char>,
-    back_buffer: HashMap<(usize, usize), char>,
+/// longest grapheme cluster a cell holds, in bytes. longer ones are rare (emoji joined out of
+/// many others) and show up as a replacement char
+const SYMBOL_CAP: usize = 30;
+
+/// the grapheme cluster in a cell, kept inline so cells stay `Copy`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    len: u8,
+    bytes: [u8; SYMBOL_CAP],
+}
+
+impl Symbol {
+    /// the right half of a wide grapheme. it's drawn along with the left half, so it's empty
+    const WIDE_TAIL: Symbol = Symbol {
+        len: 0,
+        bytes: [0; SYMBOL_CAP],
+    };
+
+    pub fn new(grapheme: &str) -> Self {
+        if grapheme.len() > SYMBOL_CAP {
+            return Self::from_char(char::REPLACEMENT_CHARACTER);
+        }
+        let mut bytes = [0; SYMBOL_CAP];
+        bytes[..grapheme.len()].copy_from_slice(grapheme.as_bytes());
+        Self {
+            len: grapheme.len() as u8,
+            bytes,
+        }
+    }
+
+    pub fn from_char(c: char) -> Self {
+        Self::new(c.encode_utf8(&mut [0; 4]))
+    }
 
-    front_fg_buffer: HashMap<(usize, usize), Color>,
-    front_bg_buffer: HashMap<(usize, usize), Color>,
+    pub fn as_str(&self) -> &str {
+        // only ever filled from a &str
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
 
-    back_fg_buffer: HashMap<(usize, usize), Color>,
-    back_bg_buffer: HashMap<(usize, usize), Color>,
+    fn is_wide_tail(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/// how text is drawn: colors plus attributes like bold or reverse video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attributes,
+}
+
+impl Style {
+    pub fn new(fg: Color, bg: Color) -> Self {
+        Self {
+            fg,
+            bg,
+            attrs: Attributes::default(),
+        }
+    }
+
+    /// the same style with `attr` turned on as well
+    pub fn with(mut self, attr: Attribute) -> Self {
+        self.attrs.set(attr);
+        self
+    }
+
+    /// the same style with `attrs` turned on as well
+    pub fn with_all(mut self, attrs: Attributes) -> Self {
+        self.attrs.extend(attrs);
+        self
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self::new(Color::White, Color::Black)
+    }
+}
+
+/// one cell on screen and how it's drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub symbol: Symbol,
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attributes,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: Symbol::from_char(' '),
+            fg: Color::White,
+            bg: Color::Black,
+            attrs: Attributes::default(),
+        }
+    }
+}
+
+/// the screen as two grids of cells, row after row. frames are drawn into the back grid and
+/// `flush` only sends the cells that differ from the front grid, i.e. what's on screen
+#[derive(Debug, Clone)]
+pub struct DoubleBuffer {
+    front: Vec<Cell>,
+    back: Vec<Cell>,
+    /// the front grid doesn't match the screen, e.g. after a resize, so everything is sent
+    full_redraw: bool,
+    /// how blank cells look
+    base: Style,
 
     pub width: usize,
     pub height: usize,
     pub too_small_flag: bool,
+    /// too narrow for anything side by side, so BROWSE shows the list without the preview
+    pub narrow_flag: bool,
 }
 
 impl DoubleBuffer {
-    pub fn new() -> Self {
-        let mut too_small_flag: bool = false;
-        let (width, height) = terminal::size().unwrap();
-        if width < 60 || height < 4 {
-            too_small_flag = true;
-        }
+    /// a buffer of the given size, blank and due for a full redraw
+    pub fn with_size(width: usize, height: usize) -> Self {
         Self {
-            front_buffer: HashMap::new(),
-            back_buffer: HashMap::new(),
-
-            front_fg_buffer: HashMap::new(),
-            front_bg_buffer: HashMap::new(),
-            back_fg_buffer: HashMap::new(),
-            back_bg_buffer: HashMap::new(),
-
-            width: width as usize,
-            height: height as usize,
-            too_small_flag,
+            front: vec![Cell::default(); width * height],
+            back: vec![Cell::default(); width * height],
+            full_redraw: true,
+            base: Style::default(),
+            width,
+            height,
+            too_small_flag: width < 60 || height < 4,
+            narrow_flag: width < 100,
         }
     }
 
-    /// recalculate terminal size in case of resize
-    pub fn resize(&mut self) {
-        if let Ok((tw, th)) = terminal::size() {
-            self.width = tw as usize;
-            self.height = th as usize;
-            self.too_small_flag = tw < 60 || th < 4;
+    /// start over at a new size, e.g. after the terminal was resized. the next flush sends
+    /// everything
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let base = self.base;
+        *self = Self::with_size(width, height);
+        self.set_base(base);
+    }
+
+    /// use `base` for blank cells from now on, redrawing everything
+    pub fn set_base(&mut self, base: Style) {
+        self.base = base;
+        let blank = self.blank();
+        self.back.fill(blank);
+        self.full_redraw = true;
+    }
 
-            self.front_buffer.clear();
-            self.front_fg_buffer.clear();
-            self.front_bg_buffer.clear();
+    fn blank(&self) -> Cell {
+        Cell {
+            symbol: Symbol::from_char(' '),
+            fg: self.base.fg,
+            bg: self.base.bg,
+            attrs: self.base.attrs,
+        }
+    }
 
-            self.back_buffer.clear();
-            self.back_fg_buffer.clear();
-            self.back_bg_buffer.clear();
+    /// writes a colored char to the back buffer
+    pub fn write_colored(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color) {
+        let cell = Cell {
+            symbol: Symbol::from_char(ch),
+            fg,
+            bg,
+            attrs: Attributes::default(),
+        };
+        self.put(x, y, cell, char_width(ch));
+    }
 
-            for y in 0..self.height {
-                for x in 0..self.width {
-                    self.back_buffer.insert((x, y), ' ');
-                    self.back_fg_buffer.insert((x, y), Color::White);
-                    self.back_bg_buffer.insert((x, y), Color::Black);
-                }
+    /// writes styled text to the back buffer a grapheme at a time, cut off at the right edge.
+    /// returns how many cells it took
+    pub fn write_styled(&mut self, x: usize, y: usize, text: &str, style: Style) -> usize {
+        let mut used = 0;
+        for grapheme in text.graphemes(true) {
+            let width = grapheme_width(grapheme);
+            if x + used + width > self.width {
+                break;
             }
-            self.flush(&mut std::io::stdout());
+            let cell = Cell {
+                symbol: Symbol::new(grapheme),
+                fg: style.fg,
+                bg: style.bg,
+                attrs: style.attrs,
+            };
+            self.put(x + used, y, cell, width);
+            used += width;
         }
+        used
     }
 
-    /// write to the **back buffer**
-    pub fn write(&mut self, x: usize, y: usize, ch: char) {
-        self.write_colored(x, y, ch, Color::White, Color::Black);
+    /// puts a cell `width` cells wide into the back buffer. a wide one also takes the cell to
+    /// its right, and a wide grapheme that gets half overwritten is blanked out entirely
+    fn put(&mut self, x: usize, y: usize, mut cell: Cell, width: usize) {
+        if width == 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let wide = width == 2;
+        if wide && x + 1 >= self.width {
+            cell.symbol = Symbol::from_char(' ');
+        }
+        let wide = wide && x + 1 < self.width;
+        let i = y * self.width + x;
+        if self.back[i].symbol.is_wide_tail() && x > 0 {
+            self.back[i - 1].symbol = Symbol::from_char(' ');
+        }
+        let last = if wide { x + 1 } else { x };
+        if last + 1 < self.width && self.back[i + last - x + 1].symbol.is_wide_tail() {
+            self.back[i + last - x + 1].symbol = Symbol::from_char(' ');
+        }
+        self.back[i] = cell;
+        if wide {
+            self.back[i + 1] = Cell {
+                symbol: Symbol::WIDE_TAIL,
+                ..cell
+            };
+        }
     }
 
-    /// writes a colored char to the back buffer
-    pub fn write_colored(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color) {
-        if x < self.width && y < self.height {
-            self.back_buffer.insert((x, y), ch);
-            self.back_fg_buffer.insert((x, y), fg);
-            self.back_bg_buffer.insert((x, y), bg);
-        }
+    /// the text of row `y` in the back buffer, the way it would look on screen
+    #[cfg(test)]
+    pub fn row(&self, y: usize) -> String {
+        self.back[y * self.width..(y + 1) * self.width]
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect()
     }
 
     /// clear buffer with whitespace
     pub fn clear(&mut self) {
+        let blank = self.blank();
+        self.back.fill(blank);
+    }
+
+    /// diff & flush to `backend`. changed cells next to each other on a row are handed over
+    /// together, and the back grid is blank again for the next frame
+    pub fn flush(&mut self, backend: &mut impl Backend) -> io::Result<()> {
         for y in 0..self.height {
-            for x in 0..self.width {
-                self.back_buffer.insert((x, y), ' ');
+            let row = y * self.width..(y + 1) * self.width;
+            let (front, back) = (&self.front[row.clone()], &self.back[row]);
+            let mut x = 0;
+            while x < self.width {
+                if !self.full_redraw && front[x] == back[x] {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < self.width && (self.full_redraw || front[x] != back[x]) {
+                    x += 1;
+                }
+                backend.draw(start, y, &back[start..x])?;
             }
         }
+        backend.flush()?;
+
+        std::mem::swap(&mut self.front, &mut self.back);
+        let blank = self.blank();
+        self.back.fill(blank);
+        self.full_redraw = false;
+        Ok(())
     }
+}
 
-    /// diff & flush to stdout
-    pub fn flush(&mut self, stdout: &mut impl Write) {
-        if self.front_buffer.is_empty() {
-            for y in 0..self.height {
-                for x in 0..self.width {
-                    let pos = (x, y);
-                    let new_char = *self.back_buffer.get(&pos).unwrap_or(&' ');
-                    let new_fg = *self
-                        .back_fg_buffer
-                        .get(&pos)
-                        .unwrap_or(&crossterm::style::Color::White);
-                    let new_bg = *self
-                        .back_bg_buffer
-                        .get(&pos)
-                        .unwrap_or(&crossterm::style::Color::Black);
-                    execute!(
-                        stdout,
-                        cursor::MoveTo(x as u16, y as u16),
-                        SetForegroundColor(new_fg),
-                        SetBackgroundColor(new_bg)
-                    )
-                    .unwrap();
-                    print!("{}", new_char);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_graphemes_take_two_cells() {
+        let mut buffer = DoubleBuffer::with_size(10, 1);
+        let used = buffer.write_styled(0, 0, "日記🎉!", Style::default());
+        assert_eq!(used, 7);
+        assert_eq!(buffer.row(0), "日記🎉!   ");
+        assert!(buffer.back[1].symbol.is_wide_tail());
+    }
+
+    #[test]
+    fn combining_marks_stay_in_their_cell() {
+        let mut buffer = DoubleBuffer::with_size(6, 1);
+        assert_eq!(
+            buffer.write_styled(0, 0, "he\u{301}llo", Style::default()),
+            5
+        );
+        assert_eq!(buffer.back[1].symbol.as_str(), "e\u{301}");
+    }
+
+    #[test]
+    fn half_overwritten_wide_graphemes_are_blanked() {
+        let mut buffer = DoubleBuffer::with_size(6, 1);
+        buffer.write_styled(0, 0, "日本語", Style::default());
+        buffer.write_colored(1, 0, 'x', Color::White, Color::Black);
+        buffer.write_colored(4, 0, 'y', Color::White, Color::Black);
+        assert_eq!(buffer.row(0), " x本y ");
+    }
+
+    #[test]
+    fn wide_graphemes_are_cut_at_the_edge() {
+        let mut buffer = DoubleBuffer::with_size(5, 1);
+        assert_eq!(buffer.write_styled(0, 0, "ab日本", Style::default()), 4);
+        assert_eq!(buffer.row(0), "ab日 ");
+        buffer.write_colored(4, 0, '本', Color::White, Color::Black);
+        assert_eq!(buffer.row(0), "ab日 ");
+    }
+}
+
+/// run with `cargo test --release -- --ignored --nocapture flush_benchmark`. compares this
+/// buffer against the HashMap buffer it replaced, frames go to a sink so only the buffer's own
+/// work is timed
+#[cfg(test)]
+mod bench {
+    use super::*;
+    use crate::util::backend::CrosstermBackend;
+    use crossterm::{
+        cursor, queue,
+        style::{Print, SetBackgroundColor, SetForegroundColor},
+    };
+    use std::{
+        collections::{HashMap, HashSet},
+        io::{sink, Write},
+        time::{Duration, Instant},
+    };
+
+    const W: usize = 200;
+    const H: usize = 60;
+    const FRAMES: u32 = 200;
+
+    /// the previous implementation, cut down to what a frame costs
+    #[derive(Default)]
+    struct HashMapBuffer {
+        front: HashMap<(usize, usize), (char, Color, Color)>,
+        back: HashMap<(usize, usize), (char, Color, Color)>,
+    }
+
+    impl HashMapBuffer {
+        fn write_colored(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color) {
+            self.back.insert((x, y), (ch, fg, bg));
+        }
+
+        fn clear(&mut self) {
+            for y in 0..H {
+                for x in 0..W {
+                    self.back.insert((x, y), (' ', Color::White, Color::Black));
                 }
             }
-        } else {
-            let all_positions: HashSet<(usize, usize)> = self
-                .front_buffer
-                .keys()
-                .chain(self.back_buffer.keys())
-                .cloned()
-                .collect();
-
-            for pos in all_positions {
-                if pos.0 >= self.width || pos.1 >= self.height {
-                    continue;
-                }
-                let old_char = *self.front_buffer.get(&pos).unwrap_or(&' ');
-                let new_char = *self.back_buffer.get(&pos).unwrap_or(&' ');
-                let old_fg = *self
-                    .front_fg_buffer
-                    .get(&pos)
-                    .unwrap_or(&crossterm::style::Color::White);
-                let new_fg = *self
-                    .back_fg_buffer
-                    .get(&pos)
-                    .unwrap_or(&crossterm::style::Color::White);
-                let old_bg = *self
-                    .front_bg_buffer
-                    .get(&pos)
-                    .unwrap_or(&crossterm::style::Color::Black);
-                let new_bg = *self
-                    .back_bg_buffer
-                    .get(&pos)
-                    .unwrap_or(&crossterm::style::Color::Black);
-
-                if old_char != new_char || old_fg != new_fg || old_bg != new_bg {
-                    execute!(
-                        stdout,
+        }
+
+        fn flush(&mut self, out: &mut impl Write) {
+            let blank = (' ', Color::White, Color::Black);
+            let full_redraw = self.front.is_empty();
+            let positions: HashSet<(usize, usize)> = if full_redraw {
+                (0..H).flat_map(|y| (0..W).map(move |x| (x, y))).collect()
+            } else {
+                self.front.keys().chain(self.back.keys()).cloned().collect()
+            };
+            for pos in positions {
+                let old = *self.front.get(&pos).unwrap_or(&blank);
+                let new = *self.back.get(&pos).unwrap_or(&blank);
+                if full_redraw || old != new {
+                    queue!(
+                        out,
                         cursor::MoveTo(pos.0 as u16, pos.1 as u16),
-                        SetForegroundColor(new_fg),
-                        SetBackgroundColor(new_bg)
+                        SetForegroundColor(new.1),
+                        SetBackgroundColor(new.2),
+                        Print(new.0)
                     )
                     .unwrap();
-                    print!("{}", new_char);
                 }
             }
+            out.flush().unwrap();
+            std::mem::swap(&mut self.front, &mut self.back);
+            self.back.clear();
+        }
+    }
+
+    /// a frame that looks like the entry list: text on every row, one highlighted
+    fn frame(n: u32, mut write: impl FnMut(usize, usize, char, Color, Color)) {
+        for y in 0..H {
+            let highlighted = y == n as usize % H;
+            for (x, ch) in "an entry label with some words in it".chars().enumerate() {
+                let (fg, bg) = if highlighted {
+                    (Color::Black, Color::Cyan)
+                } else {
+                    (Color::White, Color::Black)
+                };
+                write(x + 2, y, ch, fg, bg);
+            }
+        }
+    }
+
+    fn time(frames: u32, mut f: impl FnMut(u32)) -> Duration {
+        let start = Instant::now();
+        for n in 0..frames {
+            f(n);
         }
+        start.elapsed() / frames
+    }
+
+    #[test]
+    #[ignore]
+    fn flush_benchmark() {
+        let mut out = sink();
+        let mut backend = CrosstermBackend::new(sink());
 
-        execute!(stdout, ResetColor).unwrap();
-        stdout.flush().unwrap();
+        let mut cells = DoubleBuffer::with_size(W, H);
+        let mut map = HashMapBuffer::default();
+        let idle_cells = time(FRAMES, |_| {
+            cells.clear();
+            frame(0, |x, y, ch, fg, bg| cells.write_colored(x, y, ch, fg, bg));
+            cells.flush(&mut backend).unwrap();
+        });
+        let idle_map = time(FRAMES, |_| {
+            map.clear();
+            frame(0, |x, y, ch, fg, bg| map.write_colored(x, y, ch, fg, bg));
+            map.flush(&mut out);
+        });
 
-        std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
-        std::mem::swap(&mut self.front_fg_buffer, &mut self.back_fg_buffer);
-        std::mem::swap(&mut self.front_bg_buffer, &mut self.back_bg_buffer);
+        let full_cells = time(FRAMES, |n| {
+            cells.full_redraw = true;
+            frame(n, |x, y, ch, fg, bg| cells.write_colored(x, y, ch, fg, bg));
+            cells.flush(&mut backend).unwrap();
+        });
+        let full_map = time(FRAMES, |n| {
+            map.front.clear();
+            frame(n, |x, y, ch, fg, bg| map.write_colored(x, y, ch, fg, bg));
+            map.flush(&mut out);
+        });
 
-        self.back_buffer.clear();
-        self.back_fg_buffer.clear();
-        self.back_bg_buffer.clear();
+        println!("{}x{}, per frame", W, H);
+        println!(
+            "idle:        cells {:>10?}  hashmap {:>10?}",
+            idle_cells, idle_map
+        );
+        println!(
+            "full redraw: cells {:>10?}  hashmap {:>10?}",
+            full_cells, full_map
+        );
+        assert!(idle_cells < idle_map);
+        assert!(full_cells < full_map);
     }
 }
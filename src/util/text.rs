@@ -0,0 +1,103 @@
+/*
+ * src/util/text.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// how much room text takes on screen. the screen is a grid of cells, and a grapheme cluster
+/// (what reads as one character, e.g. an "é" made of an "e" and a combining accent, or a flag
+/// made of two code points) fills one cell, or two if it's wide like CJK and most emoji
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// cells taken by one grapheme cluster: 0, 1 or 2
+pub fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.chars().all(char::is_control) {
+        return 0;
+    }
+    grapheme.width().min(2)
+}
+
+/// cells taken by a single char, for text that is walked char by char like the editor's
+pub fn char_width(c: char) -> usize {
+    if c.is_control() {
+        return 0;
+    }
+    c.width().unwrap_or(0).min(2)
+}
+
+/// cells taken by `text`
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// the longest start of `text` that fits in `max` cells. a wide grapheme that would only
+/// half fit is left out
+pub fn truncate(text: &str, max: usize) -> String {
+    let mut used = 0;
+    let mut end = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > max {
+            break;
+        }
+        end = i + grapheme.len();
+    }
+    text[..end].to_string()
+}
+
+/// `text` cut or padded with spaces to exactly `cells` wide
+pub fn pad(text: &str, cells: usize) -> String {
+    let mut text = truncate(text, cells);
+    let n = width(&text);
+    text.extend(std::iter::repeat_n(' ', cells - n));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(width("hello"), 5);
+        assert_eq!(width("héllo"), 5);
+        assert_eq!(width("he\u{301}llo"), 5); // combining accent
+        assert_eq!(width("日記"), 4);
+        assert_eq!(width("🎉 party"), 8);
+        assert_eq!(width("👨‍👩‍👧"), 2); // one family, joined with zero width joiners
+        assert_eq!(width("🇯🇵"), 2);
+        assert_eq!(width(""), 0);
+    }
+
+    #[test]
+    fn truncating() {
+        assert_eq!(truncate("日本語のメモ", 5), "日本");
+        assert_eq!(truncate("日本語のメモ", 6), "日本語");
+        assert_eq!(truncate("a🎉b", 2), "a");
+        assert_eq!(truncate("a🎉b", 3), "a🎉");
+        assert_eq!(truncate("he\u{301}llo", 2), "he\u{301}");
+        assert_eq!(truncate("abc", 10), "abc");
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(pad("日記", 5), "日記 ");
+        assert_eq!(pad("日記です", 5), "日記 ");
+        assert_eq!(width(&pad("🎉🎉🎉", 5)), 5);
+        assert_eq!(pad("ab", 0), "");
+    }
+}
@@ -0,0 +1,437 @@
+/*
+ * src/util/theme.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// colors for each part of the screen. "dark" (the default) and "light" are built in, and more
+/// can be put in ~/.config/rnbook/themes/<name>.json and picked with "theme" in config.json:
+///
+/// ```json
+/// { "base": "dark", "selection": { "fg": "#1c1c1c", "bg": "#87afd7" }, "link": { "fg": "208", "underline": true } }
+/// ```
+///
+/// a theme file starts from its base (dark if it doesn't say) and changes only the roles it
+/// lists. colors are names like "dark_grey", ansi numbers or "#rrggbb". terminals without
+/// truecolor get the nearest of the 256 colors, or of the 16 if they don't have those either
+use crossterm::style::{Attribute, Color};
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs};
+
+use crate::util::{config::config_dir, term::Style};
+
+/// the theme used when the config doesn't name one
+pub const DEFAULT_THEME: &str = "dark";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// what the terminal says it can do, going by COLORTERM and TERM
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256") || cfg!(windows) {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub text: Style,            // anything without a role of its own
+    pub border: Style,          // the frame around the screen and the scrollbar
+    pub popup: Style,           // frames of the help popup, command window and side panes
+    pub selection: Style,       // the selected entry, day or completion
+    pub cursor: Style,          // the cell under the editor's cursor
+    pub status: Style,          // the status line, and info messages on it
+    pub status_mode: Style,     // the mode and position on the status line
+    pub status_notebook: Style, // the notebook name on the status line
+    pub command: Style,         // text typed into the command window
+    pub warning: Style,         // prompts and warnings
+    pub error: Style,           // errors, and the recording indicator
+    pub heading: Style,         // headings in entries and in the calendar
+    pub link: Style,            // [[links]] between entries
+    pub tag: Style,             // an entry's tags
+    pub mark: Style,            // marked entries
+    pub matched: Style,         // entries matching the filter
+    pub muted: Style,           // things that are there but don't matter much
+    pub code: Style,            // code in entries, on a background of its own
+    pub quote: Style,           // block quotes in entries
+    pub bullet: Style,          // list markers and checkboxes in entries
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        let on_black = |fg| Style::new(fg, Color::Black);
+        Self {
+            text: on_black(Color::White),
+            border: on_black(Color::White),
+            popup: on_black(Color::Cyan),
+            selection: on_black(Color::White).with(Attribute::Reverse),
+            cursor: Style::new(Color::Black, Color::White),
+            status: Style::new(Color::Black, Color::White),
+            status_mode: Style::new(Color::Black, Color::Cyan),
+            status_notebook: Style::new(Color::White, Color::DarkGrey),
+            command: on_black(Color::Green),
+            warning: on_black(Color::Yellow),
+            error: on_black(Color::Red),
+            heading: on_black(Color::Yellow),
+            link: on_black(Color::Cyan),
+            tag: on_black(Color::Green),
+            mark: on_black(Color::Magenta),
+            matched: on_black(Color::Yellow),
+            muted: on_black(Color::DarkGrey),
+            code: Style::new(Color::White, Color::DarkGrey),
+            quote: on_black(Color::Grey).with(Attribute::Italic),
+            bullet: on_black(Color::Cyan),
+        }
+    }
+
+    pub fn light() -> Self {
+        let on_white = |fg| Style::new(fg, Color::White);
+        Self {
+            text: on_white(Color::Black),
+            border: on_white(Color::DarkGrey),
+            popup: on_white(Color::DarkBlue),
+            selection: on_white(Color::Black).with(Attribute::Reverse),
+            cursor: Style::new(Color::White, Color::Black),
+            status: Style::new(Color::Black, Color::Grey),
+            status_mode: Style::new(Color::White, Color::DarkBlue),
+            status_notebook: Style::new(Color::White, Color::DarkGrey),
+            command: on_white(Color::DarkGreen),
+            warning: on_white(Color::DarkYellow),
+            error: on_white(Color::DarkRed),
+            heading: on_white(Color::DarkBlue),
+            link: on_white(Color::DarkCyan),
+            tag: on_white(Color::DarkGreen),
+            mark: on_white(Color::DarkMagenta),
+            matched: on_white(Color::DarkYellow),
+            muted: on_white(Color::DarkGrey),
+            code: Style::new(Color::Black, Color::Grey),
+            quote: on_white(Color::DarkGrey).with(Attribute::Italic),
+            bullet: on_white(Color::DarkCyan),
+        }
+    }
+
+    fn built_in(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            _ => None,
+        }
+    }
+
+    /// the theme called `name`, built in or from the themes directory, with its colors brought
+    /// down to `depth`. whatever is wrong with it is reported back, and the dark theme or the
+    /// base is used in its place
+    pub fn load(name: &str, depth: ColorDepth) -> (Self, Vec<String>) {
+        let mut problems = Vec::new();
+        let theme = match Self::built_in(name) {
+            Some(theme) => theme,
+            None => Self::from_file(name, &mut problems).unwrap_or_default(),
+        };
+        (theme.with_depth(depth), problems)
+    }
+
+    fn from_file(name: &str, problems: &mut Vec<String>) -> Option<Self> {
+        let path = config_dir()
+            .ok()?
+            .join("themes")
+            .join(format!("{}.json", name));
+        let file: ThemeFile = match fs::read_to_string(&path) {
+            Ok(data) => match serde_json::from_str(&data) {
+                Ok(file) => file,
+                Err(e) => {
+                    problems.push(format!("{}: {}", path.display(), e));
+                    return None;
+                }
+            },
+            Err(_) => {
+                problems.push(format!("no theme called '{}'", name));
+                return None;
+            }
+        };
+        let base = file.base.as_deref().unwrap_or(DEFAULT_THEME);
+        let mut theme = Self::built_in(base).unwrap_or_else(|| {
+            problems.push(format!("{}: no built-in theme called '{}'", name, base));
+            Self::dark()
+        });
+        for (role, spec) in &file.roles {
+            match theme.role_mut(role) {
+                Some(style) => spec.apply(style, role, problems),
+                None => problems.push(format!("{}: unknown role '{}'", name, role)),
+            }
+        }
+        Some(theme)
+    }
+
+    fn role_mut(&mut self, role: &str) -> Option<&mut Style> {
+        Some(match role {
+            "text" => &mut self.text,
+            "border" => &mut self.border,
+            "popup" => &mut self.popup,
+            "selection" => &mut self.selection,
+            "cursor" => &mut self.cursor,
+            "status" => &mut self.status,
+            "status_mode" => &mut self.status_mode,
+            "status_notebook" => &mut self.status_notebook,
+            "command" => &mut self.command,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            "heading" => &mut self.heading,
+            "link" => &mut self.link,
+            "tag" => &mut self.tag,
+            "mark" => &mut self.mark,
+            "matched" => &mut self.matched,
+            "muted" => &mut self.muted,
+            "code" => &mut self.code,
+            "quote" => &mut self.quote,
+            "bullet" => &mut self.bullet,
+            _ => return None,
+        })
+    }
+
+    fn with_depth(mut self, depth: ColorDepth) -> Self {
+        for role in [
+            &mut self.text,
+            &mut self.border,
+            &mut self.popup,
+            &mut self.selection,
+            &mut self.cursor,
+            &mut self.status,
+            &mut self.status_mode,
+            &mut self.status_notebook,
+            &mut self.command,
+            &mut self.warning,
+            &mut self.error,
+            &mut self.heading,
+            &mut self.link,
+            &mut self.tag,
+            &mut self.mark,
+            &mut self.matched,
+            &mut self.muted,
+            &mut self.code,
+            &mut self.quote,
+            &mut self.bullet,
+        ] {
+            role.fg = reduce(role.fg, depth);
+            role.bg = reduce(role.bg, depth);
+        }
+        self
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// a theme file: the theme it starts from and the roles it changes
+#[derive(Deserialize, Debug, Default)]
+struct ThemeFile {
+    base: Option<String>,
+    #[serde(flatten)]
+    roles: BTreeMap<String, RoleSpec>,
+}
+
+/// one role in a theme file. anything left out stays as the base has it
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RoleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underline: Option<bool>,
+    reverse: Option<bool>,
+}
+
+impl RoleSpec {
+    fn apply(&self, style: &mut Style, role: &str, problems: &mut Vec<String>) {
+        for (color, target) in [(&self.fg, &mut style.fg), (&self.bg, &mut style.bg)] {
+            if let Some(color) = color {
+                match parse_color(color) {
+                    Some(color) => *target = color,
+                    None => problems.push(format!("{}: bad color '{}'", role, color)),
+                }
+            }
+        }
+        for (on, attr) in [
+            (self.bold, Attribute::Bold),
+            (self.italic, Attribute::Italic),
+            (self.underline, Attribute::Underlined),
+            (self.reverse, Attribute::Reverse),
+        ] {
+            match on {
+                Some(true) => style.attrs.set(attr),
+                Some(false) => style.attrs.unset(attr),
+                None => {}
+            }
+        }
+    }
+}
+
+/// a color name, an ansi number or #rrggbb
+pub fn parse_color(text: &str) -> Option<Color> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+    if let Ok(n) = text.parse::<u8>() {
+        return Some(Color::AnsiValue(n));
+    }
+    Color::try_from(text).ok()
+}
+
+/// the 16 basic colors in ansi order, and roughly how xterm draws them
+const BASIC: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// levels of each channel in the 6x6x6 color cube of the 256 colors
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// `color` as the terminal can show it
+pub fn reduce(color: Color, depth: ColorDepth) -> Color {
+    match (color, depth) {
+        (_, ColorDepth::TrueColor) => color,
+        (Color::Rgb { r, g, b }, ColorDepth::Ansi256) => Color::AnsiValue(nearest_256(r, g, b)),
+        (Color::Rgb { r, g, b }, ColorDepth::Ansi16) => nearest_16((r, g, b)),
+        (Color::AnsiValue(n), ColorDepth::Ansi16) => nearest_16(ansi_to_rgb(n)),
+        _ => color,
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    BASIC
+        .iter()
+        .min_by_key(|(_, basic)| distance(rgb, *basic))
+        .map_or(Color::White, |(color, _)| *color)
+}
+
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| (CUBE[i] as i32 - v as i32).abs())
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+    let cube_rgb = (CUBE[ri], CUBE[gi], CUBE[bi]);
+    // the grey ramp runs from 8 to 238 in steps of 10
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey_level = 8 + 10 * grey;
+    let grey_rgb = (grey_level, grey_level, grey_level);
+    if distance((r, g, b), grey_rgb) < distance((r, g, b), cube_rgb) {
+        232 + grey
+    } else {
+        cube as u8
+    }
+}
+
+fn ansi_to_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => BASIC[n as usize].1,
+        16..=231 => {
+            let i = (n - 16) as usize;
+            (CUBE[i / 36], CUBE[i / 6 % 6], CUBE[i % 6])
+        }
+        _ => {
+            let level = 8 + 10 * (n - 232);
+            (level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("dark_grey"), Some(Color::DarkGrey));
+        assert_eq!(parse_color("208"), Some(Color::AnsiValue(208)));
+        assert_eq!(
+            parse_color("#87afd7"),
+            Some(Color::Rgb {
+                r: 0x87,
+                g: 0xaf,
+                b: 0xd7
+            })
+        );
+        assert_eq!(parse_color("#87afd"), None);
+        assert_eq!(parse_color("purple"), None);
+    }
+
+    #[test]
+    fn fallback() {
+        let rgb = parse_color("#87afd7").unwrap();
+        assert_eq!(reduce(rgb, ColorDepth::TrueColor), rgb);
+        assert_eq!(reduce(rgb, ColorDepth::Ansi256), Color::AnsiValue(110));
+        assert_eq!(reduce(rgb, ColorDepth::Ansi16), Color::DarkGrey);
+        let red = parse_color("#d70000").unwrap();
+        assert_eq!(reduce(red, ColorDepth::Ansi256), Color::AnsiValue(160));
+        assert_eq!(reduce(red, ColorDepth::Ansi16), Color::DarkRed);
+        assert_eq!(
+            reduce(parse_color("#303030").unwrap(), ColorDepth::Ansi256),
+            Color::AnsiValue(236)
+        );
+        assert_eq!(
+            reduce(Color::AnsiValue(196), ColorDepth::Ansi16),
+            Color::Red
+        );
+        assert_eq!(reduce(Color::Cyan, ColorDepth::Ansi16), Color::Cyan);
+    }
+}
@@ -16,9 +16,14 @@
  * You should have received a copy of the GNU General Public License
  * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
  */
-use chrono::Local;
 use serde::{Deserialize, Serialize};
-use std::{fmt, fs::OpenOptions, io::Write};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::util::text::{char_width, pad};
 
 #[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Entry {
@@ -26,21 +31,21 @@ pub struct Entry {
     pub label: String,
     pub date: String,
     pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
     #[serde(skip)]
     pub is_dirty: bool, // runtime flag, not serialized
 }
 
 impl Entry {
-    pub fn stringify(&self, total_width: usize) -> String {
+    pub fn stringify(&self, total_width: usize, pinned: bool) -> String {
         let effective_width = total_width.saturating_sub(2);
 
         let label_width = (effective_width * 80) / 100;
         let date_width = effective_width - label_width - 2;
 
-        let truncated_label: String = self.label.chars().take(label_width).collect();
-        let padded_label = format!("{:<width$}", truncated_label, width = label_width);
-
-        let padded_date = format!("{:<width$}", self.date, width = date_width);
+        let padded_label = pad(&self.marked_label(pinned), label_width);
+        let padded_date = pad(&self.date, date_width);
 
         format!(
             "{}{}{}{}",
@@ -50,24 +55,14 @@ impl Entry {
             crate::util::constant::VERTICAL_LINE,
         )
     }
-    pub fn selected_stringify(&self, total_width: usize) -> String {
-        let effective_width = total_width.saturating_sub(2);
-
-        let label_width = ((effective_width * 80) / 100) - 2;
-        let date_width = effective_width - label_width - 2;
-
-        let truncated_label: String = self.label.chars().take(label_width).collect();
-        let padded_label = format!("{:<width$}", truncated_label, width = label_width);
 
-        let padded_date = format!("{:<width$}", self.date, width = date_width);
-
-        format!(
-            "{}{}{}{}",
-            padded_label,
-            crate::util::constant::VERTICAL_LINE,
-            padded_date,
-            crate::util::constant::VERTICAL_LINE,
-        )
+    /// the label as it is shown in the entry list, prefixed with a marker if the entry is pinned
+    fn marked_label(&self, pinned: bool) -> String {
+        if pinned {
+            format!("{} {}", crate::util::constant::PIN_MARKER, self.label)
+        } else {
+            self.label.clone()
+        }
     }
 }
 
@@ -77,6 +72,10 @@ pub struct EntryMeta {
     pub label: String,
     pub date: String,
     pub file: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub pinned: bool, // pinned entries are kept at the top of the master index
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -84,6 +83,78 @@ pub struct MasterIndex {
     pub entries: Vec<EntryMeta>,
 }
 
+/// split `text` into lines no wider than `width` cells. long lines are broken after the last
+/// space that fits, or mid-word if there isn't one
+pub fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        for (start, end) in wrap_offsets(line, width) {
+            wrapped.push(
+                chars[start..end]
+                    .iter()
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+    }
+    wrapped
+}
+
+/// char ranges that a single line is wrapped into, each no wider than `width` cells. the
+/// ranges cover the whole line, so a position in the line always falls into exactly one of
+/// them (an empty line gives `(0, 0)`)
+pub fn wrap_offsets(line: &str, width: usize) -> Vec<(usize, usize)> {
+    let width = width.max(1);
+    let chars: Vec<char> = line.chars().collect();
+    let mut offsets = Vec::new();
+    let mut start = 0;
+    loop {
+        // how many chars from `start` fit
+        let mut used = 0;
+        let mut fit = 0;
+        for c in &chars[start..] {
+            used += char_width(*c);
+            if used > width {
+                break;
+            }
+            fit += 1;
+        }
+        // a row takes at least one char, even one too wide for it
+        let fit = fit.max(1);
+        if start + fit >= chars.len() {
+            break;
+        }
+        let end = chars[start..start + fit]
+            .iter()
+            .rposition(|c| *c == ' ')
+            .map_or(start + fit, |i| start + i + 1);
+        offsets.push((start, end));
+        start = end;
+    }
+    offsets.push((start, chars.len()));
+    offsets
+}
+
+/// write a file so that it either has the old contents or the new ones, never half of each.
+/// the new contents go to a temporary file next to it which is synced to disk before it's
+/// renamed over it, otherwise a crash could leave the rename done and the file empty
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+    // and the rename itself, where the platform lets a directory be synced
+    if let Some(dir) = path.parent().and_then(|dir| fs::File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
 /// i do not think this function will be used again for some time
 pub fn log_message(message: &str) {
     let mut file = OpenOptions::new()
@@ -94,3 +165,48 @@ pub fn log_message(message: &str) {
 
     writeln!(file, "[b] {}", message).expect("Failed to write to log file");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps() {
+        type Case<'a> = (&'a str, usize, &'a [(usize, usize)]);
+        let cases: [Case; 7] = [
+            ("", 10, &[(0, 0)]),
+            ("fits", 10, &[(0, 4)]),
+            ("exactly10!", 10, &[(0, 10)]),
+            // broken after the last space that fits, the space staying on the first row
+            ("hello world", 8, &[(0, 6), (6, 11)]),
+            ("a b c d", 3, &[(0, 2), (2, 4), (4, 7)]),
+            // a word longer than a row is cut wherever
+            ("abcdefgh", 3, &[(0, 3), (3, 6), (6, 8)]),
+            // wide chars count twice, one that doesn't fit goes to the next row
+            ("日本語", 5, &[(0, 2), (2, 3)]),
+        ];
+        for (line, width, offsets) in cases {
+            assert_eq!(
+                wrap_offsets(line, width),
+                offsets,
+                "{:?} at {}",
+                line,
+                width
+            );
+        }
+        // too narrow for even one wide char still makes progress
+        assert_eq!(wrap_offsets("日本", 1), [(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn writes_atomically() {
+        let dir = std::env::temp_dir().join(format!("rnbook-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.json");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!dir.join("index.json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+/*
+ * src/util/widget.rs
+ *
+ * This file is part of rnbook.
+ *
+ * rnbook is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * rnbook is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with rnbook. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// the pieces screens are drawn with. a widget draws into the rect it's given and nothing
+/// outside of it, whatever the size, so a screen only has to get its layout right
+use crate::util::{
+    constant::{
+        HORIZONTAL_LINE, LEFT_LOWER_SHOULDER, LEFT_UPPER_SHOULDER, RIGHT_LOWER_SHOULDER,
+        RIGHT_UPPER_SHOULDER, SCROLLBAR_THUMB, VERTICAL_LINE,
+    },
+    layout::Rect,
+    term::{DoubleBuffer, Style},
+    text::{self, truncate},
+};
+
+/// a stretch of text in one style
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Span {
+    pub fn new(text: impl Into<String>, style: Style) -> Self {
+        Self {
+            text: text.into(),
+            style,
+        }
+    }
+}
+
+/// one row of spans, drawn left to right
+pub type Line = Vec<Span>;
+
+pub trait Widget {
+    fn draw(&self, buffer: &mut DoubleBuffer, area: Rect);
+}
+
+/// draws `line` into the first row of `area`, cut off at its right edge. returns how many cells
+/// it took
+pub fn write_line(buffer: &mut DoubleBuffer, area: Rect, line: &[Span]) -> usize {
+    if area.is_empty() {
+        return 0;
+    }
+    let mut used = 0;
+    for span in line {
+        let text = truncate(&span.text, area.width - used);
+        used += buffer.write_styled(area.x + used, area.y, &text, span.style);
+        if used >= area.width {
+            break;
+        }
+    }
+    used
+}
+
+/// total width of a line in cells
+pub fn line_width(line: &[Span]) -> usize {
+    line.iter().map(|span| text::width(&span.text)).sum()
+}
+
+/// a box around `area` with an optional title in the top edge and footer in the bottom right.
+/// the screen border is one too, just without clearing what's inside
+pub struct Popup {
+    style: Style,
+    title: Line,
+    footer: Line,
+    fill: Option<Style>,
+}
+
+impl Popup {
+    pub fn new(style: Style) -> Self {
+        Self {
+            style,
+            title: Vec::new(),
+            footer: Vec::new(),
+            fill: None,
+        }
+    }
+
+    pub fn title(mut self, title: Line) -> Self {
+        self.title = title;
+        self
+    }
+
+    pub fn footer(mut self, footer: Line) -> Self {
+        self.footer = footer;
+        self
+    }
+
+    /// blank out the inside with `style` so nothing underneath shows through
+    pub fn fill(mut self, style: Style) -> Self {
+        self.fill = Some(style);
+        self
+    }
+}
+
+impl Widget for Popup {
+    fn draw(&self, buffer: &mut DoubleBuffer, area: Rect) {
+        if area.width < 2 || area.height < 2 {
+            return;
+        }
+        let (fg, bg) = (self.style.fg, self.style.bg);
+        let (right, bottom) = (area.right() - 1, area.bottom() - 1);
+        for x in area.x + 1..right {
+            buffer.write_colored(x, area.y, HORIZONTAL_LINE, fg, bg);
+            buffer.write_colored(x, bottom, HORIZONTAL_LINE, fg, bg);
+        }
+        for y in area.y + 1..bottom {
+            buffer.write_colored(area.x, y, VERTICAL_LINE, fg, bg);
+            buffer.write_colored(right, y, VERTICAL_LINE, fg, bg);
+        }
+        buffer.write_colored(area.x, area.y, LEFT_UPPER_SHOULDER, fg, bg);
+        buffer.write_colored(right, area.y, RIGHT_UPPER_SHOULDER, fg, bg);
+        buffer.write_colored(area.x, bottom, LEFT_LOWER_SHOULDER, fg, bg);
+        buffer.write_colored(right, bottom, RIGHT_LOWER_SHOULDER, fg, bg);
+
+        if let Some(fill) = self.fill {
+            let inner = area.inner();
+            let blank = " ".repeat(inner.width);
+            for y in inner.y..inner.bottom() {
+                buffer.write_styled(inner.x, y, &blank, fill);
+            }
+        }
+
+        // the corners and a cell of edge stay clear on either side
+        let edge = Rect::new(area.x + 2, area.y, area.width.saturating_sub(4), 1);
+        write_line(buffer, edge, &self.title);
+        let n = line_width(&self.footer);
+        if n > 0 && n + 4 < area.width {
+            let footer = Rect::new(right - 1 - n, bottom, n, 1);
+            write_line(buffer, footer, &self.footer);
+        }
+    }
+}
+
+/// rows of text from `scroll` on, one line per row
+pub struct TextView {
+    lines: Vec<Line>,
+    scroll: usize,
+    centered: bool,
+}
+
+impl TextView {
+    pub fn new(lines: Vec<Line>) -> Self {
+        Self {
+            lines,
+            scroll: 0,
+            centered: false,
+        }
+    }
+
+    pub fn scroll(mut self, scroll: usize) -> Self {
+        self.scroll = scroll;
+        self
+    }
+
+    /// put the text in the middle of the area, both ways
+    pub fn centered(mut self) -> Self {
+        self.centered = true;
+        self
+    }
+}
+
+impl Widget for TextView {
+    fn draw(&self, buffer: &mut DoubleBuffer, area: Rect) {
+        let lines = self.lines.iter().skip(self.scroll).take(area.height);
+        let top = if self.centered {
+            (area.height - lines.len()) / 2
+        } else {
+            0
+        };
+        for (i, line) in lines.enumerate() {
+            let mut row = area.row(top + i);
+            if self.centered {
+                row = row.centered(line_width(line), 1);
+            }
+            write_line(buffer, row, line);
+        }
+    }
+}
+
+/// a list of items from the top, the selected one pointed out with a marker in front
+pub struct List<'a> {
+    items: Vec<Line>,
+    selected: Option<usize>,
+    marker: &'a str,
+}
+
+impl<'a> List<'a> {
+    pub fn new(items: Vec<Line>) -> Self {
+        Self {
+            items,
+            selected: None,
+            marker: "",
+        }
+    }
+
+    pub fn select(mut self, selected: Option<usize>) -> Self {
+        self.selected = selected;
+        self
+    }
+
+    /// put `marker` in front of the selected item, and as many spaces in front of the rest
+    pub fn marker(mut self, marker: &'a str) -> Self {
+        self.marker = marker;
+        self
+    }
+}
+
+impl Widget for List<'_> {
+    fn draw(&self, buffer: &mut DoubleBuffer, area: Rect) {
+        let gutter = " ".repeat(text::width(self.marker));
+        for (row, (i, item)) in self.items.iter().enumerate().take(area.height).enumerate() {
+            let area = area.row(row);
+            let marker = if self.selected == Some(i) {
+                self.marker
+            } else {
+                &gutter
+            };
+            let style = item.first().map_or(Style::default(), |span| span.style);
+            let x = write_line(buffer, area, &[Span::new(marker, style)]);
+            write_line(
+                buffer,
+                Rect::new(area.x + x, area.y, area.width - x, 1),
+                item,
+            );
+        }
+    }
+}
+
+/// one line of editable text with the cursor on it. `text` is the part that's in view and
+/// `cursor` the cell the cursor is in, along with what's under it
+pub struct Input<'a> {
+    text: &'a str,
+    style: Style,
+    cursor: Option<(usize, String, Style)>,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(text: &'a str, style: Style) -> Self {
+        Self {
+            text,
+            style,
+            cursor: None,
+        }
+    }
+
+    pub fn cursor(mut self, column: usize, under: impl Into<String>, style: Style) -> Self {
+        self.cursor = Some((column, under.into(), style));
+        self
+    }
+}
+
+impl Widget for Input<'_> {
+    fn draw(&self, buffer: &mut DoubleBuffer, area: Rect) {
+        let area = area.row(0);
+        write_line(
+            buffer,
+            area,
+            &[Span::new(text::pad(self.text, area.width), self.style)],
+        );
+        if let Some((column, under, style)) = &self.cursor {
+            let column = (*column).min(area.width.saturating_sub(1));
+            let cell = Rect::new(area.x + column, area.y, area.width - column, 1);
+            write_line(buffer, cell, &[Span::new(under.as_str(), *style)]);
+        }
+    }
+}
+
+/// a full-width bar: segments from the left, `right` against the right edge if there's room
+/// for it, and `message` in between
+pub struct StatusBar {
+    style: Style,
+    left: Line,
+    message: Option<Span>,
+    right: Option<Span>,
+}
+
+impl StatusBar {
+    pub fn new(style: Style, left: Line) -> Self {
+        Self {
+            style,
+            left,
+            message: None,
+            right: None,
+        }
+    }
+
+    pub fn message(mut self, message: Option<Span>) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn right(mut self, right: Span) -> Self {
+        self.right = Some(right);
+        self
+    }
+}
+
+impl Widget for StatusBar {
+    fn draw(&self, buffer: &mut DoubleBuffer, area: Rect) {
+        let area = area.row(0);
+        write_line(
+            buffer,
+            area,
+            &[Span::new(" ".repeat(area.width), self.style)],
+        );
+        let x = write_line(buffer, area, &self.left);
+
+        let mut end = area.width;
+        if let Some(right) = &self.right {
+            let n = text::width(&right.text);
+            if area.width.saturating_sub(n) > x {
+                end = area.width - n;
+                write_line(
+                    buffer,
+                    Rect::new(area.x + end, area.y, n, 1),
+                    std::slice::from_ref(right),
+                );
+            }
+        }
+        if let Some(message) = &self.message {
+            let room = Rect::new(area.x + x + 1, area.y, end.saturating_sub(x + 2), 1);
+            write_line(buffer, room, std::slice::from_ref(message));
+        }
+    }
+}
+
+/// a one column scrollbar for `total` rows of which `visible` are on screen from `offset`. the
+/// thumb only shows when they don't all fit
+pub struct Scrollbar {
+    total: usize,
+    visible: usize,
+    offset: usize,
+    style: Style,
+}
+
+impl Scrollbar {
+    pub fn new(total: usize, visible: usize, offset: usize, style: Style) -> Self {
+        Self {
+            total,
+            visible,
+            offset,
+            style,
+        }
+    }
+}
+
+impl Widget for Scrollbar {
+    fn draw(&self, buffer: &mut DoubleBuffer, area: Rect) {
+        let (fg, bg) = (self.style.fg, self.style.bg);
+        for y in area.y..area.bottom() {
+            buffer.write_colored(area.x, y, VERTICAL_LINE, fg, bg);
+        }
+        let n = area.height.min(self.visible);
+        if self.total <= n || n == 0 {
+            return;
+        }
+        let thumb_len = std::cmp::max(1, (n * n) / self.total);
+        let max_offset = self.total - n;
+        let thumb_start = (self.offset.min(max_offset) * (n - thumb_len)) / max_offset;
+        for y in thumb_start..thumb_start + thumb_len {
+            buffer.write_colored(area.x, area.y + y, SCROLLBAR_THUMB, fg, bg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_inside() {
+        let mut buffer = DoubleBuffer::with_size(12, 4);
+        let style = Style::default();
+        let area = Rect::new(2, 1, 6, 2);
+        Popup::new(style)
+            .title(vec![Span::new("a long title", style)])
+            .draw(&mut buffer, area);
+        assert_eq!(buffer.row(1), "  ┌─a ─┐    ");
+        assert_eq!(buffer.row(2), "  └────┘    ");
+
+        let mut buffer = DoubleBuffer::with_size(12, 4);
+        let lines = vec![vec![Span::new("日本語のテキスト", style)]; 9];
+        TextView::new(lines).draw(&mut buffer, Rect::new(1, 1, 5, 2));
+        assert_eq!(buffer.row(1), " 日本       ");
+        assert_eq!(buffer.row(3), "            ");
+    }
+
+    #[test]
+    fn status_bar() {
+        let mut buffer = DoubleBuffer::with_size(20, 1);
+        let style = Style::default();
+        StatusBar::new(style, vec![Span::new(" READ ", style)])
+            .message(Some(Span::new("saved and more", style)))
+            .right(Span::new(" 1/2 ", style))
+            .draw(&mut buffer, Rect::new(0, 0, 20, 1));
+        assert_eq!(buffer.row(0), " READ  saved a  1/2 ");
+    }
+}